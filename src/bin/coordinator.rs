@@ -1,6 +1,7 @@
 use clap::Parser;
 use minidist::coordinator::coordinator_cluster;
 use minidist::coordinator::coordinator_server;
+use minidist::storage::storage_catalog;
 
 #[derive(Parser)]
 #[command(name = "coordinator")]
@@ -13,18 +14,19 @@ struct Args {
     #[arg(long, required = true, value_delimiter = ',')]
     workers: Vec<u16>,
 
+    /// Data root: the tables and views listed in its `_catalog.txt`.
     #[arg(long)]
-    table: String,
+    data: String,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let segments = coordinator_cluster::count_segments(std::path::Path::new(&args.table))?;
+    let data = std::path::Path::new(&args.data);
+    storage_catalog::init_catalog(data).map_err(|e| anyhow::anyhow!(e))?;
+    let segments = coordinator_cluster::count_catalog_segments(data)?;
     let worker_ports = coordinator_cluster::resolve_worker_ports(&args.workers, segments)?;
-    let _cluster =
-        coordinator_cluster::WorkerCluster::spawn(&worker_ports, std::path::Path::new(&args.table))
-            .await?;
+    let _cluster = coordinator_cluster::WorkerCluster::spawn(&worker_ports, data).await?;
 
-    coordinator_server::serve(args.port, worker_ports, &args.table).await
+    coordinator_server::serve(args.port, worker_ports, &args.data).await
 }
@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use minidist::storage::storage_catalog::{CATALOG, register};
 use minidist::storage::storage_init::init_table;
 use minidist::storage::storage_inspect::{inspect_metadata, inspect_schema};
 use std::path::PathBuf;
@@ -31,6 +32,10 @@ enum Commands {
 
         #[arg(long, value_name = "N")]
         segments: u32,
+
+        /// Align segment boundaries with an already loaded table.
+        #[arg(long, value_name = "DIR")]
+        colocate_with: Option<String>,
     },
 
     Schema {
@@ -67,6 +72,7 @@ fn main() {
             csv,
             sort_key,
             segments,
+            colocate_with,
         } => {
             let dir = PathBuf::from(dir);
             let csv = PathBuf::from(csv);
@@ -78,13 +84,32 @@ fn main() {
             let schema =
                 minidist::storage::storage_schema::parse_schema_file(&schema_text).unwrap();
 
-            match minidist::storage::storage_load::load_table(
-                &dir,
-                &csv,
-                &sort_key,
-                segments as usize,
-                &schema,
-            ) {
+            let result = match colocate_with {
+                Some(reference) => minidist::storage::storage_load::load_table_colocated(
+                    &dir,
+                    &csv,
+                    &sort_key,
+                    segments as usize,
+                    &schema,
+                    &PathBuf::from(reference),
+                ),
+                None => minidist::storage::storage_load::load_table(
+                    &dir,
+                    &csv,
+                    &sort_key,
+                    segments as usize,
+                    &schema,
+                ),
+            };
+            // A coordinator serving the data root sees the table once it
+            // is loaded and in the catalog.
+            let result = result.and_then(|_| match dir.parent() {
+                Some(root) if root.join(CATALOG).is_file() => {
+                    register(root, &dir.file_name().unwrap_or_default().to_string_lossy())
+                }
+                _ => Ok(()),
+            });
+            match result {
                 Ok(()) => println!("Loaded CSV into {} segments", segments),
                 Err(e) => eprintln!("Error: {}", e),
             }
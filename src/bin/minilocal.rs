@@ -1,13 +1,14 @@
 use clap::Parser;
 use minidist::coordinator::coordinator_merge::merge_partials;
-use minidist::minisql::minisql_eval::{
-    ReadError, ReaderState, format_scalar, init_reader, read_value,
-};
+use minidist::coordinator::coordinator_relation::{groups_to_relation, project_output};
+use minidist::coordinator::coordinator_route::resolve_table;
+use minidist::minisql::minisql_eval::format_scalar;
 use minidist::minisql::minisql_parse;
-use minidist::minisql::minisql_print::format_results;
-use minidist::rpc::ScalarValue;
+use minidist::minisql::minisql_print::format_relation;
 use minidist::storage::storage_schema::ColumnDef;
 use minidist::worker::worker_exec::{WorkerContext, execute_query};
+use minidist::worker::worker_scan::SegmentScan;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
 use std::path::Path;
 use std::time::Instant;
@@ -57,6 +58,18 @@ fn main() -> anyhow::Result<()> {
         match minisql_parse::parse_sql(&query_buf) {
             Ok(mut req) => {
                 req.table = args.table.clone();
+                if let Some(join) = req.join.as_mut() {
+                    let data = table_path.parent().unwrap_or(Path::new("."));
+                    match resolve_table(&data.display().to_string(), &join.right_name) {
+                        Ok(path) => join.right_table = path,
+                        Err(e) => {
+                            eprintln!("join error: {}", e);
+                            query_buf.clear();
+                            prompt = "minilocal> ";
+                            continue;
+                        }
+                    }
+                }
                 if req.aggregates.is_empty() {
                     match scan_projections(&req.table, args.segment, &req.projections) {
                         Ok((rows, scanned)) => {
@@ -80,16 +93,15 @@ fn main() -> anyhow::Result<()> {
                         segment: args.segment,
                     };
                     let partial = execute_query(&ctx, req.clone(), Instant::now());
-                    let (merged, rows_scanned, segments_skipped, exec_ms) =
+                    let (merged, rows_scanned, segments_skipped, exec_us) =
                         merge_partials(&[partial]);
-                    let output = format_results(
-                        merged,
-                        rows_scanned,
-                        segments_skipped,
-                        exec_ms,
-                        &req.group_by,
-                    );
-                    println!("{}", output);
+                    match project_output(groups_to_relation(&merged, &req), &req.output) {
+                        Ok(rel) => println!(
+                            "{}",
+                            format_relation(&rel, rows_scanned, segments_skipped, exec_us / 1000)
+                        ),
+                        Err(e) => eprintln!("query error: {}", e),
+                    }
                 }
             }
             Err(e) => {
@@ -114,7 +126,6 @@ fn scan_projections(
         std::fs::read_to_string(&schema_path).map_err(|e| format!("read schema: {}", e))?;
     let schema = minidist::storage::storage_schema::parse_schema_file(&schema_str)
         .map_err(|e| format!("parse schema: {}", e))?;
-    let segment_dir = Path::new(table).join(format!("seg-{:06}", segment));
 
     let mut needed = Vec::new();
     for name in projections {
@@ -125,39 +136,22 @@ fn scan_projections(
         needed.push(name.clone());
     }
 
-    let mut readers: Vec<(String, ColumnDef, ReaderState)> = Vec::new();
-    for col in &schema {
-        if needed.contains(&col.name) {
-            let path = segment_dir.join(format!("{}.bin", col.name));
-            let reader =
-                init_reader(&path, col).ok_or_else(|| format!("open {:?}: failed", path))?;
-            readers.push((col.name.clone(), col.clone(), reader));
-        }
-    }
+    let defs: HashMap<String, ColumnDef> =
+        schema.iter().map(|c| (c.name.clone(), c.clone())).collect();
+    let columns: Vec<&ColumnDef> = schema.iter().filter(|c| needed.contains(&c.name)).collect();
+    let needed: HashSet<String> = columns.iter().map(|c| c.name.clone()).collect();
+    // Includes the segment's delta segments.
+    let mut scan = SegmentScan::open(table, segment, &defs, &needed)
+        .ok_or_else(|| format!("open segment {} of {}: failed", segment, table))?;
 
     let mut rows = Vec::new();
     let mut rows_scanned = 0u64;
-    loop {
-        let mut row_vals: Vec<Option<ScalarValue>> = Vec::new();
-        let mut eof = false;
-        for (_, def, rdr) in readers.iter_mut() {
-            match read_value(rdr, def) {
-                Ok(v) => row_vals.push(v),
-                Err(ReadError::Eof) => {
-                    eof = true;
-                    break;
-                }
-                Err(ReadError::Io) => {
-                    eof = true;
-                    break;
-                }
-            }
-        }
-        if eof {
-            break;
-        }
+    while let Some(row) = scan.next_row() {
         rows_scanned += 1;
-        let rendered: Vec<String> = row_vals.iter().map(|v| format_scalar(v)).collect();
+        let rendered: Vec<String> = columns
+            .iter()
+            .map(|c| format_scalar(&row.get(&c.name).cloned().flatten()))
+            .collect();
         rows.push(rendered);
     }
 
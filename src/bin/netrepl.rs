@@ -14,6 +14,18 @@ struct Args {
 
     #[arg(long, default_value = "/query")]
     path: String,
+
+    /// Ask for JSON results (rows plus per-worker stats).
+    #[arg(long)]
+    json: bool,
+}
+
+/// `SET`s last for the whole REPL: every request carries this id.
+fn session_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("netrepl-{}-{}", std::process::id(), nanos)
 }
 
 fn main() -> anyhow::Result<()> {
@@ -21,6 +33,7 @@ fn main() -> anyhow::Result<()> {
     let endpoint = format!("{}:{}{}", args.host, args.port, args.path);
     println!("Connecting to coordinator at http://{} ...", endpoint);
 
+    let session = session_id();
     let mut query_buf = String::new();
     let mut prompt = "minidist> ";
 
@@ -40,9 +53,10 @@ fn main() -> anyhow::Result<()> {
             continue;
         }
 
-        match send_request(&args, &query_buf) {
+        // Several statements on one input go out as one script.
+        match send_request(&args, &session, &query_buf) {
             Ok(resp) => println!("{}", resp),
-            Err(e) => eprintln!("Error sending query: {}", e),
+            Err(e) => eprintln!("Error: {}", e),
         }
 
         query_buf.clear();
@@ -52,24 +66,34 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn send_request(args: &Args, body: &str) -> anyhow::Result<String> {
+fn send_request(args: &Args, session: &str, body: &str) -> anyhow::Result<String> {
     let addr = format!("{}:{}", args.host, args.port);
     let mut stream = TcpStream::connect(&addr)?;
 
     let content_len = body.as_bytes().len();
+    let accept = if args.json {
+        "application/json"
+    } else {
+        "text/plain"
+    };
     let request = format!(
-        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-        args.path, args.host, content_len, body
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nAccept: {}\r\nX-Session-Id: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        args.path, args.host, accept, session, content_len, body
     );
 
     stream.write_all(request.as_bytes())?;
     let mut resp = String::new();
     stream.read_to_string(&mut resp)?;
 
-    // Extract body after headers
-    if let Some(idx) = resp.find("\r\n\r\n") {
-        Ok(resp[idx + 4..].to_string())
-    } else {
-        Err(anyhow::anyhow!("Malformed HTTP response"))
+    // Extract body after headers; errors come back as JSON with a non-200
+    // status.
+    let Some(idx) = resp.find("\r\n\r\n") else {
+        return Err(anyhow::anyhow!("Malformed HTTP response"));
+    };
+    let status = resp.lines().next().unwrap_or_default();
+    let body = resp[idx + 4..].to_string();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow::anyhow!("{}: {}", status.trim(), body));
     }
+    Ok(body)
 }
@@ -0,0 +1,112 @@
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_eval::format_scalar;
+use crate::rpc::{Relation, ScalarValue};
+use crate::storage::storage_catalog::{catalog_names, is_registered};
+use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_view::{is_materialized, is_view};
+use crate::worker::worker_exec::load_schema;
+use std::path::{Path, PathBuf};
+
+/// Columns of every table in the catalog, readable like a table.
+pub const COLUMNS_TABLE: &str = "information_schema.columns";
+
+/// Directory of table `name` if the catalog of `data` has it.
+pub fn catalog_table(data: &str, name: &str) -> Option<PathBuf> {
+    let dir = Path::new(data).join(name);
+    (is_registered(Path::new(data), name) && dir.join("_schema.ssf").is_file()).then_some(dir)
+}
+
+fn catalog_error(e: String) -> QueryError {
+    QueryError::Execution { message: e }
+}
+
+fn text(s: &str) -> Option<ScalarValue> {
+    Some(ScalarValue::String(s.to_string()))
+}
+
+/// `SHOW TABLES`: every table and view in the catalog, by name.
+pub fn show_tables(data: &str) -> Result<Relation, QueryError> {
+    let mut rows = Vec::new();
+    for name in catalog_names(Path::new(data)).map_err(catalog_error)? {
+        let dir = Path::new(data).join(&name);
+        let kind = if is_materialized(&dir) {
+            "materialized view"
+        } else if is_view(&dir) {
+            "view"
+        } else if dir.join("_schema.ssf").is_file() {
+            "table"
+        } else {
+            continue; // registered, but dropped halfway
+        };
+        rows.push(vec![text(&name), text(kind)]);
+    }
+    Ok(Relation {
+        columns: vec!["table_name".to_string(), "kind".to_string()],
+        rows,
+    })
+}
+
+/// `DESCRIBE name`: the columns of a table, in schema order.
+pub fn describe(data: &str, name: &str) -> Result<Relation, QueryError> {
+    let Some(dir) = catalog_table(data, name) else {
+        return Err(QueryError::NotFound {
+            message: format!("Table '{}' does not exist", name),
+        });
+    };
+    let mut relation = column_rows(table_columns(name, &dir));
+    // The table and position are implied.
+    relation.columns.drain(0..2);
+    for row in relation.rows.iter_mut() {
+        row.drain(0..2);
+    }
+    Ok(relation)
+}
+
+/// The rows of `information_schema.columns`.
+pub fn information_schema_columns(data: &str) -> Result<Relation, QueryError> {
+    let mut rows = Vec::new();
+    for name in catalog_names(Path::new(data)).map_err(catalog_error)? {
+        if let Some(dir) = catalog_table(data, &name) {
+            rows.extend(table_columns(&name, &dir));
+        }
+    }
+    Ok(column_rows(rows))
+}
+
+fn table_columns(name: &str, dir: &Path) -> Vec<Vec<Option<ScalarValue>>> {
+    load_schema(&dir.display().to_string())
+        .iter()
+        .enumerate()
+        .map(|(i, col)| column_row(name, i, col))
+        .collect()
+}
+
+fn column_rows(rows: Vec<Vec<Option<ScalarValue>>>) -> Relation {
+    let columns = [
+        "table_name",
+        "ordinal_position",
+        "column_name",
+        "data_type",
+        "is_nullable",
+        "is_key",
+        "column_default",
+    ];
+    Relation {
+        columns: columns.iter().map(|c| c.to_string()).collect(),
+        rows,
+    }
+}
+
+fn column_row(table: &str, position: usize, col: &ColumnDef) -> Vec<Option<ScalarValue>> {
+    vec![
+        text(table),
+        Some(ScalarValue::Int(position as i64 + 1)),
+        text(&col.name),
+        text(&col.col_type.to_string()),
+        text(if col.nullable { "YES" } else { "NO" }),
+        Some(ScalarValue::Bool(col.is_key)),
+        col.default
+            .as_ref()
+            .map(|v| ScalarValue::String(format_scalar(&Some(v.clone())))),
+    ]
+}
@@ -1,24 +1,13 @@
+use crate::storage::storage_catalog::catalog_names;
+use crate::storage::storage_segment::base_segment_count;
 use std::path::Path;
 use std::process::{Child, Command};
 use tokio::time::{Duration, sleep};
 
+/// Counts base segments; delta segments are scanned by their base
+/// segment's worker.
 pub fn count_segments(table_dir: &Path) -> anyhow::Result<usize> {
-    let mut count = 0usize;
-    let entries = std::fs::read_dir(table_dir)
-        .map_err(|e| anyhow::anyhow!("Failed to read table dir {:?}: {}", table_dir, e))?;
-
-    for entry in entries {
-        let entry = entry.map_err(|e| anyhow::anyhow!("Failed to read entry: {}", e))?;
-        let file_type = entry
-            .file_type()
-            .map_err(|e| anyhow::anyhow!("Failed to read entry type: {}", e))?;
-        if file_type.is_dir() {
-            let name = entry.file_name();
-            if name.to_string_lossy().starts_with("seg-") {
-                count += 1;
-            }
-        }
-    }
+    let count = base_segment_count(table_dir).map_err(|e| anyhow::anyhow!(e))?;
 
     if count == 0 {
         return Err(anyhow::anyhow!(
@@ -29,6 +18,31 @@ pub fn count_segments(table_dir: &Path) -> anyhow::Result<usize> {
     Ok(count)
 }
 
+/// Base segments of every table in the catalog of `data`: worker i serves
+/// segment i of each of them, so the tables must agree.
+pub fn count_catalog_segments(data: &Path) -> anyhow::Result<usize> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for name in catalog_names(data).map_err(|e| anyhow::anyhow!(e))? {
+        let dir = data.join(&name);
+        if dir.join("_schema.ssf").is_file() {
+            counts.push((name, count_segments(&dir)?));
+        }
+    }
+    let Some((first, segments)) = counts.first().cloned() else {
+        return Err(anyhow::anyhow!("No tables in the catalog of {:?}", data));
+    };
+    if let Some((name, n)) = counts.iter().find(|(_, n)| *n != segments) {
+        return Err(anyhow::anyhow!(
+            "Tables '{}' and '{}' differ in segments ({} vs {})",
+            first,
+            name,
+            segments,
+            n
+        ));
+    }
+    Ok(segments)
+}
+
 pub fn resolve_worker_ports(spec: &[u16], segments: usize) -> anyhow::Result<Vec<u16>> {
     if spec.len() == 1 {
         let start = spec[0];
@@ -56,7 +70,7 @@ pub struct WorkerCluster {
 }
 
 impl WorkerCluster {
-    pub async fn spawn(worker_ports: &[u16], table: &Path) -> anyhow::Result<Self> {
+    pub async fn spawn(worker_ports: &[u16], data: &Path) -> anyhow::Result<Self> {
         let mut children = Vec::new();
         for (i, port) in worker_ports.iter().enumerate() {
             println!("Starting worker on port {port} (segment {i})...");
@@ -68,7 +82,7 @@ impl WorkerCluster {
                 .arg("--port")
                 .arg(port.to_string())
                 .arg("--table")
-                .arg(table.display().to_string())
+                .arg(data.display().to_string())
                 .arg("--segment")
                 .arg(i.to_string())
                 .spawn()?;
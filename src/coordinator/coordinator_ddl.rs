@@ -0,0 +1,278 @@
+use super::coordinator_catalog::catalog_table;
+use super::coordinator_dml::target_table;
+use super::coordinator_route::resolve_tables;
+use super::coordinator_session::{Settings, run_with_settings};
+use crate::minisql::minisql_bind::store_value;
+use crate::minisql::minisql_error::QueryError;
+use crate::rpc::{QueryRequest, Relation, ScalarValue};
+use crate::storage::storage_alter::{SchemaChange, altered_schema, write_schema};
+use crate::storage::storage_append::{StoredRow, load_rows};
+use crate::storage::storage_catalog::{bump_version, register, unregister};
+use crate::storage::storage_init::init_table_with_schema;
+use crate::storage::storage_load::create_empty_segments;
+use crate::storage::storage_schema::{ColumnDef, ColumnType};
+use crate::worker::worker_exec::load_schema;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Schema changes read, change and replace `_schema.ssf`; they run one at a
+/// time.
+static ALTERS: Mutex<()> = Mutex::new(());
+
+/// Directory of the table `name`: tables and views live side by side, in
+/// the data root `data` (see `storage_catalog`).
+pub fn table_dir(data: &str, name: &str) -> Result<PathBuf, QueryError> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', ':'])
+        || name.chars().any(char::is_control)
+    {
+        return Err(QueryError::Bind {
+            message: format!("Invalid table name '{}'", name),
+        });
+    }
+    Ok(Path::new(data).join(name))
+}
+
+/// `CREATE TABLE`: writes the schema, `_table.txt` and one empty segment per
+/// worker (see `publish_table`).
+pub fn create_table(
+    data: &str,
+    name: &str,
+    columns: &[ColumnDef],
+    segments: usize,
+) -> Result<(), QueryError> {
+    let dir = new_table_dir(data, name)?;
+    let mut columns = columns.to_vec();
+    for col in &mut columns {
+        bind_default(col)?;
+    }
+    publish_table(&dir, name, |tmp| {
+        init_table_with_schema(tmp, &columns)
+            .and_then(|_| create_empty_segments(tmp, &columns, segments))
+    })
+}
+
+/// `CREATE TABLE ... AS`: runs the query and writes its result as a new
+/// table (`result_schema`, `load_rows`), one base segment per worker.
+/// Returns the number of rows written.
+pub async fn create_table_as(
+    worker_ports: &[u16],
+    data: &str,
+    name: &str,
+    key: Option<&str>,
+    mut request: QueryRequest,
+    settings: &Settings,
+) -> Result<usize, QueryError> {
+    let dir = new_table_dir(data, name)?;
+    resolve_tables(&mut request, data)?;
+    let source = load_schema(&request.table);
+    // A partial result would silently drop rows.
+    let settings = Settings {
+        allow_partial_results: false,
+        ..settings.clone()
+    };
+    let relation = run_with_settings(worker_ports, request, &settings)
+        .await?
+        .relation;
+    let schema = result_schema(&relation, &source, key)?;
+
+    let mut rows: Vec<StoredRow> = Vec::with_capacity(relation.rows.len());
+    for (i, row) in relation.rows.into_iter().enumerate() {
+        let mut stored = Vec::with_capacity(row.len());
+        for (value, col) in row.into_iter().zip(&schema) {
+            stored.push(match value {
+                Some(v) => Some(
+                    store_value(&v, &col.col_type, &col.name)
+                        .map_err(|message| QueryError::Bind { message })?,
+                ),
+                None if col.is_key => {
+                    return Err(QueryError::Bind {
+                        message: format!("Key column '{}' is NULL in row {}", col.name, i + 1),
+                    });
+                }
+                None => None,
+            });
+        }
+        rows.push(stored);
+    }
+    let count = rows.len();
+    publish_table(&dir, name, |tmp| {
+        init_table_with_schema(tmp, &schema)
+            .and_then(|_| load_rows(tmp, &schema, worker_ports.len(), rows))
+    })?;
+    Ok(count)
+}
+
+/// Schema of a query result: a column named like one of the source table
+/// keeps that column's type, others get the type of their values (`string`
+/// when all are NULL). Only the key column is NOT NULL.
+fn result_schema(
+    relation: &Relation,
+    source: &[ColumnDef],
+    key: Option<&str>,
+) -> Result<Vec<ColumnDef>, QueryError> {
+    let bind = |message: String| QueryError::Bind { message };
+    let key = key.or(relation.columns.first().map(String::as_str));
+    if !relation.columns.iter().any(|c| Some(c.as_str()) == key) {
+        return Err(bind(format!(
+            "Key column '{}' is not in the query result",
+            key.unwrap_or_default()
+        )));
+    }
+    let mut schema = Vec::with_capacity(relation.columns.len());
+    for (i, name) in relation.columns.iter().enumerate() {
+        let mut inferred: Option<ColumnType> = None;
+        for value in relation.rows.iter().filter_map(|r| r[i].as_ref()) {
+            let ty = match value {
+                ScalarValue::Int(_) => ColumnType::Int64,
+                ScalarValue::Float(_) => ColumnType::Float64,
+                ScalarValue::String(_) => ColumnType::String,
+                ScalarValue::Bool(_) => ColumnType::Bool,
+            };
+            inferred = match inferred {
+                None => Some(ty),
+                Some(seen) if seen == ty => Some(seen),
+                // Integers are widened where floats appear too.
+                Some(ColumnType::Int64 | ColumnType::Float64)
+                    if matches!(ty, ColumnType::Int64 | ColumnType::Float64) =>
+                {
+                    Some(ColumnType::Float64)
+                }
+                Some(_) => return Err(bind(format!("Column '{}' mixes value types", name))),
+            };
+        }
+        let declared = source.iter().find(|c| &c.name == name).map(|c| &c.col_type);
+        let col_type = match (declared, inferred) {
+            (Some(d), None) => d.clone(),
+            (Some(d), Some(ColumnType::Int64))
+                if matches!(
+                    d,
+                    ColumnType::Int32
+                        | ColumnType::Int64
+                        | ColumnType::Date
+                        | ColumnType::TimestampMs
+                ) =>
+            {
+                d.clone()
+            }
+            (_, Some(ty)) => ty,
+            (None, None) => ColumnType::String,
+        };
+        let is_key = Some(name.as_str()) == key;
+        schema.push(ColumnDef {
+            name: name.clone(),
+            col_type,
+            nullable: !is_key,
+            is_key,
+            default: None,
+            file: None,
+        });
+    }
+    Ok(schema)
+}
+
+/// Directory for a table that `CREATE TABLE` is about to create.
+pub fn new_table_dir(data: &str, name: &str) -> Result<PathBuf, QueryError> {
+    let dir = table_dir(data, name)?;
+    if name.starts_with("information_schema.") {
+        return Err(QueryError::Bind {
+            message: "Table names starting with 'information_schema.' are reserved".to_string(),
+        });
+    }
+    if dir.exists() {
+        return Err(QueryError::Bind {
+            message: format!("Table '{}' already exists", name),
+        });
+    }
+    Ok(dir)
+}
+
+/// Builds a table under a temporary name, renames it into place and
+/// registers it in the catalog, so a half-written table is never visible.
+/// Build errors (e.g. an invalid schema) are bind errors.
+pub fn publish_table(
+    dir: &Path,
+    name: &str,
+    build: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), QueryError> {
+    let tmp = staging_dir(dir, "create");
+    if let Err(e) = build(&tmp) {
+        let _ = fs::remove_dir_all(&tmp);
+        return Err(QueryError::Bind { message: e });
+    }
+    let failed = |e: String| QueryError::Execution {
+        message: format!("Failed to create '{}': {}", name, e),
+    };
+    fs::rename(&tmp, dir).map_err(|e| {
+        let _ = fs::remove_dir_all(&tmp);
+        failed(e.to_string())
+    })?;
+    register(data_root(dir), name).map_err(failed)
+}
+
+/// Renames a table or view away, drops it from the catalog and removes it,
+/// so queries see either the whole of it or none of it.
+pub fn unpublish_table(dir: &Path, name: &str) -> Result<(), QueryError> {
+    let failed = |e: String| QueryError::Execution {
+        message: format!("Failed to drop '{}': {}", name, e),
+    };
+    let tmp = staging_dir(dir, "drop");
+    fs::rename(dir, &tmp).map_err(|e| failed(e.to_string()))?;
+    unregister(data_root(dir), name).map_err(failed)?;
+    fs::remove_dir_all(&tmp).map_err(|e| failed(e.to_string()))
+}
+
+fn data_root(dir: &Path) -> &Path {
+    dir.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// `DROP TABLE` (see `unpublish_table`).
+pub fn drop_table(data: &str, name: &str, if_exists: bool) -> Result<(), QueryError> {
+    table_dir(data, name)?;
+    let Some(dir) = catalog_table(data, name) else {
+        if if_exists {
+            return Ok(());
+        }
+        return Err(QueryError::NotFound {
+            message: format!("Table '{}' does not exist", name),
+        });
+    };
+    unpublish_table(&dir, name)
+}
+
+/// `ALTER TABLE`: replaces the schema (see `altered_schema`) without
+/// touching any segment, so it takes effect for the next query at once.
+pub fn alter_table(data: &str, name: &str, mut change: SchemaChange) -> Result<(), QueryError> {
+    if let SchemaChange::AddColumn(col) = &mut change {
+        bind_default(col)?;
+    }
+    let _guard = ALTERS.lock().unwrap_or_else(|e| e.into_inner());
+    let (dir, schema) = target_table(data, name)?;
+    let schema =
+        altered_schema(&dir, &schema, &change).map_err(|message| QueryError::Bind { message })?;
+    write_schema(&dir, &schema).map_err(|message| QueryError::Execution { message })?;
+    // Prepared statements bound to the old schema are prepared again.
+    bump_version(data_root(&dir)).map_err(|message| QueryError::Execution { message })
+}
+
+/// Converts a column's `DEFAULT` to its type, like an inserted value.
+fn bind_default(col: &mut ColumnDef) -> Result<(), QueryError> {
+    if let Some(value) = &col.default {
+        col.default = Some(
+            store_value(value, &col.col_type, &col.name)
+                .map_err(|message| QueryError::Bind { message })?,
+        );
+    }
+    Ok(())
+}
+
+/// A hidden sibling of `dir`; names starting with a dot are never tables
+/// (see `table_dir`).
+pub fn staging_dir(dir: &Path, purpose: &str) -> PathBuf {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    dir.with_file_name(format!(".{}.{}-{}", name, purpose, std::process::id()))
+}
@@ -0,0 +1,280 @@
+use super::coordinator_catalog::catalog_table;
+use super::coordinator_ddl::table_dir;
+use super::coordinator_route::resolve_tables;
+use super::coordinator_session::{Settings, run_with_settings};
+use crate::minisql::minisql_bind::store_value;
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_eval::format_scalar;
+use crate::minisql::minisql_parse::InsertSource;
+use crate::rpc::{
+    ArithOp, AssignedValue, Assignment, Mutation, QueryRequest, Relation, ScalarValue,
+};
+use crate::storage::storage_append::{StoredRow, append_rows};
+use crate::storage::storage_schema::{ColumnDef, ColumnType};
+use crate::worker::worker_exec::load_schema;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// `INSERT`: collects the rows on the coordinator, converts them to the
+/// column types and appends them as delta segments (`append_rows`), which
+/// later queries scan together with the base segments. Returns the number
+/// of rows inserted.
+pub async fn insert(
+    worker_ports: &[u16],
+    data: &str,
+    table: &str,
+    columns: &[String],
+    source: InsertSource,
+    settings: &Settings,
+) -> Result<usize, QueryError> {
+    let (dir, schema) = target_table(data, table)?;
+    let targets = target_columns(&schema, columns, table)?;
+
+    let rows = match source {
+        InsertSource::Values(rows) => rows,
+        InsertSource::Query(mut request) => {
+            resolve_tables(&mut request, data)?;
+            // A partial result would silently drop rows.
+            let settings = Settings {
+                allow_partial_results: false,
+                ..settings.clone()
+            };
+            let result = run_with_settings(worker_ports, *request, &settings).await?;
+            result.relation.rows
+        }
+    };
+
+    let mut stored: Vec<StoredRow> = Vec::with_capacity(rows.len());
+    for (i, row) in rows.into_iter().enumerate() {
+        if row.len() != targets.len() {
+            return Err(QueryError::Bind {
+                message: format!(
+                    "INSERT has {} target columns, but row {} has {} values",
+                    targets.len(),
+                    i + 1,
+                    row.len()
+                ),
+            });
+        }
+        let mut full: StoredRow = schema.iter().map(|c| c.default.clone()).collect();
+        for (&idx, value) in targets.iter().zip(row) {
+            let col = &schema[idx];
+            full[idx] = match value {
+                Some(v) => Some(
+                    store_value(&v, &col.col_type, &col.name)
+                        .map_err(|message| QueryError::Bind { message })?,
+                ),
+                None => None,
+            };
+        }
+        if let Some(col) = schema
+            .iter()
+            .zip(&full)
+            .find(|(c, v)| v.is_none() && (!c.nullable || c.is_key))
+            .map(|(c, _)| c)
+        {
+            return Err(QueryError::Bind {
+                message: format!(
+                    "Column '{}' is NOT NULL, but row {} has no value for it",
+                    col.name,
+                    i + 1
+                ),
+            });
+        }
+        stored.push(full);
+    }
+
+    let count = stored.len();
+    if count > 0 {
+        append_rows(&dir, &schema, stored).map_err(|message| QueryError::Execution { message })?;
+    }
+    Ok(count)
+}
+
+/// `DELETE` and `UPDATE`: workers add the rows matching the filters to
+/// their segments' deletion vectors, or rewrite the segments holding them
+/// with the new values. Returns the number of rows changed. Not atomic
+/// across segments; a failure names the segments already changed.
+pub async fn mutate(
+    worker_ports: &[u16],
+    data: &str,
+    mut request: QueryRequest,
+    settings: &Settings,
+) -> Result<u64, QueryError> {
+    let (_, schema) = target_table(data, &request.table)?;
+    if let Some(Mutation::Update(assignments)) = &mut request.mutation {
+        bind_assignments(&schema, &request.table, assignments)?;
+    }
+    resolve_tables(&mut request, data)?;
+    // Every segment must apply the change, and one that started must be
+    // allowed to finish: the session timeout is for reads.
+    let settings = Settings {
+        allow_partial_results: false,
+        timeout_ms: 0,
+        ..settings.clone()
+    };
+    let result = run_with_settings(worker_ports, request, &settings).await?;
+    Ok(changed_rows(&result.relation))
+}
+
+/// The `COUNT(*)` a mutation request returns.
+fn changed_rows(relation: &Relation) -> u64 {
+    match relation.rows.first().and_then(|r| r.first()) {
+        Some(Some(ScalarValue::Int(n))) => *n as u64,
+        _ => 0,
+    }
+}
+
+/// Checks the assignments of an `UPDATE` against the schema and converts
+/// their literals to the column types. The key cannot change, since it
+/// decides where and in which order rows are stored. Arithmetic needs a
+/// numeric column and a number, and a result that fits the target.
+fn bind_assignments(
+    schema: &[ColumnDef],
+    table: &str,
+    assignments: &mut [Assignment],
+) -> Result<(), QueryError> {
+    let bind = |message: String| QueryError::Bind { message };
+    let mut assigned = HashSet::new();
+    for a in assignments {
+        let col = &schema[column_index(schema, &a.column, table)?];
+        if col.is_key {
+            return Err(bind(format!(
+                "Column '{}' is the table key and cannot be updated",
+                col.name
+            )));
+        }
+        if !assigned.insert(col.name.clone()) {
+            return Err(bind(format!("Column '{}' is assigned twice", col.name)));
+        }
+        match &mut a.value {
+            AssignedValue::Value(None) if !col.nullable => {
+                return Err(bind(format!("Column '{}' is NOT NULL", col.name)));
+            }
+            AssignedValue::Value(None) => {}
+            AssignedValue::Value(Some(v)) => {
+                *v = store_value(v, &col.col_type, &col.name).map_err(bind)?;
+            }
+            AssignedValue::Column(name) => {
+                let source = &schema[column_index(schema, name, table)?];
+                if !assignable(&source.col_type, &col.col_type) {
+                    return Err(bind(format!(
+                        "Cannot store column '{}' ({}) in column '{}' ({})",
+                        source.name, source.col_type, col.name, col.col_type
+                    )));
+                }
+                if source.nullable && !col.nullable {
+                    return Err(bind(format!(
+                        "Column '{}' is NOT NULL, but '{}' is nullable",
+                        col.name, source.name
+                    )));
+                }
+            }
+            AssignedValue::Arithmetic { column, op, value } => {
+                let source = &schema[column_index(schema, column, table)?];
+                let expr = format!(
+                    "{} {} {}",
+                    source.name,
+                    op.symbol(),
+                    format_scalar(&Some(value.clone()))
+                );
+                let result = match (&source.col_type, &*value) {
+                    (ColumnType::Int32 | ColumnType::Int64, ScalarValue::Int(_)) => {
+                        ColumnType::Int64
+                    }
+                    (
+                        ColumnType::Int32 | ColumnType::Int64 | ColumnType::Float64,
+                        ScalarValue::Int(_) | ScalarValue::Float(_),
+                    ) => ColumnType::Float64,
+                    _ => {
+                        return Err(bind(format!(
+                            "Cannot compute {}: arithmetic needs a numeric column and a number",
+                            expr
+                        )));
+                    }
+                };
+                let zero = match value {
+                    ScalarValue::Int(i) => *i == 0,
+                    ScalarValue::Float(f) => *f == 0.0,
+                    _ => false,
+                };
+                if *op == ArithOp::Div && zero {
+                    return Err(bind(format!("Cannot compute {}: division by zero", expr)));
+                }
+                // Integer results are range-checked per row for int32.
+                let fits = match result {
+                    ColumnType::Int64 => matches!(
+                        col.col_type,
+                        ColumnType::Int32 | ColumnType::Int64 | ColumnType::Float64
+                    ),
+                    _ => col.col_type == ColumnType::Float64,
+                };
+                if !fits {
+                    return Err(bind(format!(
+                        "Cannot store {} ({}) in column '{}' ({})",
+                        expr, result, col.name, col.col_type
+                    )));
+                }
+                if source.nullable && !col.nullable {
+                    return Err(bind(format!(
+                        "Column '{}' is NOT NULL, but '{}' is nullable",
+                        col.name, source.name
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether every value of a `from` column fits a `to` column.
+fn assignable(from: &ColumnType, to: &ColumnType) -> bool {
+    from == to
+        || matches!(
+            (from, to),
+            (ColumnType::Int32, ColumnType::Int64)
+                | (ColumnType::Int32 | ColumnType::Int64, ColumnType::Float64)
+        )
+}
+
+/// Directory and schema of a table that statements write to.
+pub fn target_table(data: &str, table: &str) -> Result<(PathBuf, Vec<ColumnDef>), QueryError> {
+    table_dir(data, table)?;
+    let dir = catalog_table(data, table).ok_or_else(|| QueryError::NotFound {
+        message: format!("Table '{}' does not exist", table),
+    })?;
+    let schema = load_schema(&dir.display().to_string());
+    Ok((dir, schema))
+}
+
+/// Schema positions of the listed columns (all of them when none are
+/// listed).
+fn target_columns(
+    schema: &[ColumnDef],
+    columns: &[String],
+    table: &str,
+) -> Result<Vec<usize>, QueryError> {
+    if columns.is_empty() {
+        return Ok((0..schema.len()).collect());
+    }
+    let mut targets = Vec::with_capacity(columns.len());
+    for name in columns {
+        let idx = column_index(schema, name, table)?;
+        if targets.contains(&idx) {
+            return Err(QueryError::Bind {
+                message: format!("Column '{}' is listed twice", name),
+            });
+        }
+        targets.push(idx);
+    }
+    Ok(targets)
+}
+
+fn column_index(schema: &[ColumnDef], name: &str, table: &str) -> Result<usize, QueryError> {
+    schema
+        .iter()
+        .position(|c| c.name == name)
+        .ok_or_else(|| QueryError::Bind {
+            message: format!("Unknown column '{}' in table '{}'", name, table),
+        })
+}
@@ -0,0 +1,291 @@
+use super::coordinator_route::windows_local;
+use super::coordinator_stats::{GatherTrace, OperatorTrace, PartialTrace, QueryTrace};
+use crate::minisql::minisql_plan::{LogicalPlan, derive_group_by, plan_query};
+use crate::minisql::minisql_print::{format_filter, format_plan, format_plan_node};
+use crate::rpc::{QueryRequest, Relation, SetOp};
+use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_segment::segment_parts;
+use crate::worker::worker_exec::{compute_min_max, load_schema, pruning_filter};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// `EXPLAIN`: the plan of every SELECT in the statement, where each
+/// fragment runs, how partials are merged and which segments the zone maps
+/// would prune. Nothing is executed; subqueries are shown unresolved.
+pub fn explain_plan(worker_ports: &[u16], request: &QueryRequest) -> String {
+    let ctes: Vec<String> = request.ctes.iter().map(|c| c.name.clone()).collect();
+    let mut out = String::from("EXPLAIN\n");
+    for cte in &request.ctes {
+        out.push_str(&format!("CTE {}:\n", cte.name));
+        match &cte.rows {
+            Some(rows) => out.push_str(&format!("  {} rows from the catalog\n", rows.rows.len())),
+            None => explain_select(&mut out, 2, worker_ports, &cte.query, &ctes),
+        }
+    }
+    if request.set_ops.is_empty() {
+        out.push_str("Query:\n");
+        explain_select(&mut out, 2, worker_ports, request, &ctes);
+        return out;
+    }
+
+    out.push_str("SELECT 1:\n");
+    explain_select(&mut out, 2, worker_ports, request, &ctes);
+    for (i, branch) in request.set_ops.iter().enumerate() {
+        out.push_str(&format!("{} SELECT {}:\n", set_op_name(branch.op), i + 2));
+        explain_select(&mut out, 2, worker_ports, &branch.query, &ctes);
+    }
+    out.push_str(&format!(
+        "Combine {} SELECTs on the coordinator",
+        request.set_ops.len() + 1
+    ));
+    if !request.order_by.is_empty() {
+        let items: Vec<String> = request.order_by.iter().map(|o| o.column.clone()).collect();
+        out.push_str(&format!(", ORDER BY {}", items.join(", ")));
+    }
+    if let Some(n) = request.limit {
+        out.push_str(&format!(", LIMIT {}", n));
+    }
+    out.push('\n');
+    out
+}
+
+fn explain_select(
+    out: &mut String,
+    indent: usize,
+    worker_ports: &[u16],
+    req: &QueryRequest,
+    ctes: &[String],
+) {
+    let pad = " ".repeat(indent);
+    let fragments = plan_query(req);
+    out.push_str(&format!("{}Coordinator:\n", pad));
+    out.push_str(&format_plan(&fragments.coordinator, indent + 2));
+    out.push_str(&format!("{}Merge: {}\n", pad, merge_description(req)));
+
+    if ctes.contains(&req.table) {
+        out.push_str(&format!(
+            "{}On the coordinator, over CTE {}:\n",
+            pad, req.table
+        ));
+        out.push_str(&format_plan(&fragments.worker, indent + 2));
+    } else {
+        out.push_str(&format!("{}Workers ({}):\n", pad, worker_ports.len()));
+        out.push_str(&format_plan(&fragments.worker, indent + 2));
+        out.push_str(&format!("{}Segments:\n", pad));
+        match fragments.worker_request(req) {
+            Some(planned) => {
+                for (segment, port) in worker_ports.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{}  seg-{:06} on worker {}: {}\n",
+                        pad,
+                        segment,
+                        port,
+                        predict_segment(&planned, segment as u32)
+                    ));
+                }
+            }
+            None => out.push_str(&format!("{}  none; no worker is contacted\n", pad)),
+        }
+    }
+
+    for f in &req.filters {
+        if let Some(inner) = &f.subquery {
+            out.push_str(&format!(
+                "{}Subquery for {} (run first):\n",
+                pad,
+                format_filter(f)
+            ));
+            explain_select(out, indent + 2, worker_ports, inner, ctes);
+        }
+    }
+}
+
+/// Whether a worker would scan a segment or skip it, judged from the
+/// segment's zone maps as the worker would.
+fn predict_segment(planned: &QueryRequest, segment: u32) -> String {
+    let dirs = segment_parts(Path::new(&planned.table), segment);
+    if dirs.is_empty() {
+        return "skipped (missing segment)".to_string();
+    }
+    let defs: HashMap<String, ColumnDef> = load_schema(&planned.table)
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+    // Subquery values are only known at run time.
+    let filters: Vec<_> = planned
+        .filters
+        .iter()
+        .filter(|f| f.subquery.is_none())
+        .cloned()
+        .collect();
+    if let Some(min_max) = compute_min_max(&dirs, &defs, &filters)
+        && let Some(f) = pruning_filter(&filters, &min_max)
+    {
+        return format!("pruned by zone map ({})", format_filter(f));
+    }
+    "scan".to_string()
+}
+
+/// How the coordinator combines the partials of the worker fragment.
+fn merge_description(req: &QueryRequest) -> String {
+    if !req.grouping_sets.is_empty() {
+        return format!(
+            "merge partial aggregates per grouping set ({} sets)",
+            req.grouping_sets.len()
+        );
+    }
+    if !req.windows.is_empty() {
+        return if windows_local(req) {
+            "concatenate rows; windows are evaluated per segment".to_string()
+        } else {
+            "concatenate rows, then evaluate windows".to_string()
+        };
+    }
+    let group_by = derive_group_by(req);
+    if group_by.is_empty() {
+        "merge partial aggregates into one row".to_string()
+    } else {
+        format!("merge partial aggregates by {}", group_by.join(", "))
+    }
+}
+
+fn set_op_name(op: SetOp) -> &'static str {
+    match op {
+        SetOp::UnionAll => "UNION ALL",
+        SetOp::Union => "UNION",
+        SetOp::Intersect => "INTERSECT",
+        SetOp::Except => "EXCEPT",
+    }
+}
+
+/// `EXPLAIN ANALYZE`: per fragment, what every worker did and the rows into
+/// and out of each operator; then the coordinator operators and totals.
+pub fn format_analysis(trace: &QueryTrace, result: &Relation, wall_us: u64) -> String {
+    let mut out = String::from("EXPLAIN ANALYZE\n");
+    for gather in &trace.gathers {
+        out.push_str(&format!(
+            "{} ({}):\n",
+            gather.query.trim(),
+            format_us(gather.elapsed_us)
+        ));
+        if let LogicalPlan::Empty { reason } = &gather.fragments.worker {
+            out.push_str(&format!("  No worker contacted: {}\n", reason));
+            continue;
+        }
+        match (&gather.cte, &gather.view) {
+            (Some(name), _) => out.push_str(&format!("  Over CTE {} on the coordinator\n", name)),
+            (None, Some(name)) => {
+                out.push_str(&format!("  From materialized view {}\n", name));
+                for p in gather.partials.iter().filter(|p| p.worker_port != 0) {
+                    out.push_str(&format!(
+                        "    changed since refresh, on worker {}: {}\n",
+                        p.worker_port,
+                        partial_summary(p)
+                    ));
+                }
+            }
+            (None, None) => {
+                out.push_str("  Workers:\n");
+                for p in &gather.partials {
+                    out.push_str(&format!(
+                        "    seg-{:06} on worker {}: {}\n",
+                        p.segment,
+                        p.worker_port,
+                        partial_summary(p)
+                    ));
+                }
+            }
+        }
+        out.push_str("  Operators (rows in -> out):\n");
+        for op in worker_operators(gather) {
+            out.push_str(&format!(
+                "    {}: {} -> {}\n",
+                op.name, op.rows_in, op.rows_out
+            ));
+        }
+    }
+    if !trace.operators.is_empty() {
+        out.push_str("Coordinator (rows in -> out):\n");
+        for op in &trace.operators {
+            out.push_str(&format!(
+                "  {}: {} -> {}\n",
+                op.name, op.rows_in, op.rows_out
+            ));
+        }
+    }
+    let stats = trace.stats(wall_us);
+    out.push_str(&format!(
+        "Result: {} rows\n\
+         Rows scanned: {}, bytes read: {}, segments pruned: {}\n\
+         Time: {} wall, {} on workers, {} network, {} serialization",
+        result.rows.len(),
+        stats.rows_scanned,
+        stats.bytes_read,
+        stats.segments_skipped,
+        format_us(stats.wall_us),
+        format_us(stats.worker_us),
+        format_us(stats.network_us),
+        format_us(stats.serialize_us)
+    ));
+    out
+}
+
+fn partial_summary(p: &PartialTrace) -> String {
+    let mut out = match &p.skip_reason {
+        Some(reason) => format!("skipped, {}", reason),
+        None => format!(
+            "scanned {}, matched {}, out {}, {} bytes",
+            p.rows_scanned, p.rows_matched, p.rows_out, p.bytes_read
+        ),
+    };
+    out.push_str(&format!(
+        "; {} on worker, {} round trip",
+        format_us(p.exec_us),
+        format_us(p.call.latency_us)
+    ));
+    if p.call.retries > 0 {
+        out.push_str(&format!(", {} retries", p.call.retries));
+    }
+    out
+}
+
+fn format_us(us: u64) -> String {
+    format!("{:.3} ms", us as f64 / 1000.0)
+}
+
+/// Rows through the worker fragment, summed over all partials, bottom-up:
+/// the scan (rows read -> rows matching its filters), the aggregate or
+/// window operator, then the merge on the coordinator.
+fn worker_operators(gather: &GatherTrace) -> Vec<OperatorTrace> {
+    let sum = |f: fn(&PartialTrace) -> u64| gather.partials.iter().map(f).sum::<u64>();
+    let (scanned, matched, partial_out) = (
+        sum(|p| p.rows_scanned),
+        sum(|p| p.rows_matched),
+        sum(|p| p.rows_out),
+    );
+
+    let mut nodes = Vec::new();
+    let mut node = Some(&gather.fragments.worker);
+    while let Some(n) = node {
+        nodes.push(n);
+        node = n.input();
+    }
+    let mut ops = Vec::new();
+    for n in nodes.into_iter().rev() {
+        let (rows_in, rows_out) = match n {
+            LogicalPlan::Scan { .. } => (scanned, matched),
+            _ => (matched, partial_out),
+        };
+        ops.push(OperatorTrace {
+            name: format_plan_node(n),
+            rows_in,
+            rows_out,
+        });
+    }
+    ops.push(OperatorTrace {
+        name: "Gather".to_string(),
+        rows_in: partial_out,
+        rows_out: gather.rows_out,
+    });
+    ops
+}
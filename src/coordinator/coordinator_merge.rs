@@ -1,29 +1,46 @@
 use crate::rpc::{AggregateState, GroupMap, PartialAggregate};
 use std::collections::HashMap;
 
+/// Merges the groups of every partial; also returns the summed rows scanned,
+/// segments skipped and worker time (µs).
 pub fn merge_partials(partials: &[PartialAggregate]) -> (GroupMap, u64, u64, u64) {
     let mut cuml: GroupMap = HashMap::new();
     let mut rows_scanned = 0u64;
     let mut segments_skipped = 0u64;
-    let mut exec_ms = 0u64;
+    let mut exec_us = 0u64;
 
     for p in partials {
         rows_scanned += p.rows_scanned;
         segments_skipped += p.segments_skipped;
-        exec_ms += p.exec_ms;
-
-        for (g_key, g_agg) in &p.groups {
-            let entry = cuml.entry(g_key.clone()).or_default();
-            for (name, state) in g_agg {
-                let agg = entry
-                    .entry(name.clone())
-                    .or_insert_with(AggregateState::default);
-                merge_state(agg, state);
-            }
+        exec_us += p.exec_us;
+
+        merge_groups(&mut cuml, &p.groups);
+    }
+
+    (cuml, rows_scanned, segments_skipped, exec_us)
+}
+
+/// Merges the per-grouping-set maps of every partial, each set on its own.
+pub fn merge_grouping_sets(partials: &[PartialAggregate], sets: usize) -> Vec<GroupMap> {
+    let mut cuml: Vec<GroupMap> = vec![HashMap::new(); sets];
+    for p in partials {
+        for (dst, src) in cuml.iter_mut().zip(&p.grouping_groups) {
+            merge_groups(dst, src);
         }
     }
+    cuml
+}
 
-    (cuml, rows_scanned, segments_skipped, exec_ms)
+pub fn merge_groups(dst: &mut GroupMap, src: &GroupMap) {
+    for (g_key, g_agg) in src {
+        let entry = dst.entry(g_key.clone()).or_default();
+        for (name, state) in g_agg {
+            let agg = entry
+                .entry(name.clone())
+                .or_insert_with(AggregateState::default);
+            merge_state(agg, state);
+        }
+    }
 }
 
 pub fn merge_state(dst: &mut AggregateState, src: &AggregateState) {
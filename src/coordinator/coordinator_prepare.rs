@@ -0,0 +1,117 @@
+use super::coordinator_route::{QueryResult, resolve_tables};
+use super::coordinator_session::{Settings, run_bound_with_settings};
+use crate::minisql::minisql_bind::bind_prepared;
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_params::{bind_params, param_count};
+use crate::minisql::minisql_parse::parse_sql;
+use crate::rpc::{QueryRequest, ScalarValue};
+use crate::storage::storage_catalog::catalog_version;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Statements kept before the oldest is evicted.
+pub const STATEMENT_CACHE_CAPACITY: usize = 256;
+
+/// A parsed, resolved and bound statement waiting for its parameters. Plans
+/// are not cached: folding and zone-map pruning depend on the values, so
+/// every execution is planned again. Views and table names are resolved at
+/// `catalog_version`; the statement is prepared again once it moves.
+#[derive(Debug)]
+pub struct PreparedStatement {
+    pub id: String,
+    pub sql: String,
+    pub request: QueryRequest,
+    pub params: usize,
+    pub catalog_version: u64,
+}
+
+/// Prepared statements by id, evicted oldest first. The id is a hash of the
+/// SQL text, so preparing the same text again reuses the entry while the
+/// catalog is unchanged.
+#[derive(Debug, Default)]
+pub struct StatementCache {
+    statements: HashMap<String, Arc<PreparedStatement>>,
+    order: VecDeque<String>,
+}
+
+impl StatementCache {
+    pub fn prepare(&mut self, sql: &str, data: &str) -> Result<Arc<PreparedStatement>, QueryError> {
+        let id = statement_id(sql, data);
+        // Read first: a change while resolving leaves the entry stale.
+        let version = catalog_version(Path::new(data));
+        if let Some(stmt) = self.statements.get(&id)
+            && stmt.catalog_version == version
+        {
+            return Ok(stmt.clone());
+        }
+
+        let mut request = parse_sql(sql)?;
+        resolve_tables(&mut request, data)?;
+        bind_prepared(&mut request)?;
+        let params = param_count(&mut request);
+        let stmt = Arc::new(PreparedStatement {
+            id: id.clone(),
+            sql: sql.to_string(),
+            request,
+            params,
+            catalog_version: version,
+        });
+
+        if self.statements.insert(id.clone(), stmt.clone()).is_none() {
+            if self.order.len() >= STATEMENT_CACHE_CAPACITY
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.statements.remove(&oldest);
+            }
+            self.order.push_back(id);
+        }
+        Ok(stmt)
+    }
+
+    /// The statement `id`, prepared again if the catalog of `data` changed
+    /// since.
+    pub fn get(&mut self, id: &str, data: &str) -> Result<Arc<PreparedStatement>, QueryError> {
+        let stmt = self
+            .statements
+            .get(id)
+            .cloned()
+            .ok_or_else(|| QueryError::NotFound {
+                message: format!("Unknown prepared statement {}", id),
+            })?;
+        if stmt.catalog_version == catalog_version(Path::new(data)) {
+            return Ok(stmt);
+        }
+        self.prepare(&stmt.sql, data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+}
+
+fn statement_id(sql: &str, data: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    sql.trim().hash(&mut hasher);
+    data.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Runs a prepared statement with `values` for `$1`, `$2`, ... (or the
+/// `?`s in order). The statement was bound when prepared; only the values
+/// are converted.
+pub async fn execute_prepared(
+    worker_ports: &[u16],
+    stmt: &PreparedStatement,
+    values: &[ScalarValue],
+    settings: &Settings,
+) -> Result<QueryResult, QueryError> {
+    let mut request = stmt.request.clone();
+    bind_params(&mut request, values)?;
+    run_bound_with_settings(worker_ports, request, settings).await
+}
@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use super::coordinator_stats::OperatorTrace;
+
+use crate::minisql::minisql_eval::{
+    compare_group_keys, compare_nullable, finalize_state, row_matches,
+};
+use crate::minisql::minisql_plan::{LogicalPlan, derive_group_by};
+use crate::minisql::minisql_print::{format_plan_node, grouping_column_name, normalize_header};
+use crate::minisql::minisql_window::window_input_columns;
+use crate::rpc::{
+    GroupKey, GroupMap, OrderByExpr, PartialAggregate, QueryRequest, Relation, ScalarValue, SetOp,
+};
+use crate::worker::worker_exec::accumulate;
+
+/// Turns merged groups into rows: one column per GROUP BY column, then one
+/// per aggregate (its alias, or the printed header name). Projection-only
+/// queries yield just their distinct projected values; `SELECT *` yields the
+/// implicit row count.
+pub fn groups_to_relation(merged: &GroupMap, req: &QueryRequest) -> Relation {
+    let group_by = derive_group_by(req);
+    let mut columns = group_by.clone();
+    let implicit_count = req.aggregates.is_empty() && group_by.is_empty();
+    if implicit_count {
+        columns.push(normalize_header("COUNT(*)"));
+    } else {
+        columns.extend(req.aggregates.iter().map(|agg| {
+            agg.alias
+                .clone()
+                .unwrap_or_else(|| normalize_header(&agg.output_name))
+        }));
+    }
+
+    let mut keys: Vec<&GroupKey> = merged.keys().collect();
+    keys.sort_by(|a, b| compare_group_keys(a, b));
+    let mut rows = Vec::with_capacity(keys.len());
+    for key in keys {
+        let states = &merged[key];
+        let mut row = Vec::with_capacity(columns.len());
+        row.extend(key.iter().cloned());
+        if implicit_count {
+            row.push(
+                states
+                    .get("COUNT(*)")
+                    .and_then(|s| finalize_state(crate::rpc::AggregateFn::Count, s)),
+            );
+        } else {
+            for agg in &req.aggregates {
+                row.push(
+                    states
+                        .get(&agg.output_name)
+                        .and_then(|s| finalize_state(agg.func, s)),
+                );
+            }
+        }
+        rows.push(row);
+    }
+    Relation { columns, rows }
+}
+
+/// Runs the coordinator fragment of a lowered plan over the merged result,
+/// recording the rows into and out of each operator in `ops`.
+pub fn finish_relation(
+    plan: &LogicalPlan,
+    rel: Relation,
+    req: &QueryRequest,
+    ops: &mut Vec<OperatorTrace>,
+) -> Result<Relation, String> {
+    let Some(input) = plan.input() else {
+        return Ok(rel);
+    };
+    let rel = finish_relation(input, rel, req, ops)?;
+    let rows_in = rel.rows.len() as u64;
+    let rel = match plan {
+        LogicalPlan::Project { columns, .. } => project_output(rel, columns)?,
+        LogicalPlan::Sort { order_by, .. } => apply_order_limit(rel, req, order_by, None)?,
+        LogicalPlan::Limit { count, .. } => {
+            let mut rel = rel;
+            rel.rows.truncate(*count as usize);
+            rel
+        }
+        _ => rel,
+    };
+    ops.push(OperatorTrace {
+        name: format_plan_node(plan),
+        rows_in,
+        rows_out: rel.rows.len() as u64,
+    });
+    Ok(rel)
+}
+
+/// Reorders a result into the SELECT list's columns; `*` keeps every column.
+pub fn project_output(rel: Relation, output: &[String]) -> Result<Relation, String> {
+    if output.is_empty() || output.iter().any(|c| c == "*") {
+        return Ok(rel);
+    }
+    let idx = output
+        .iter()
+        .map(|name| {
+            rel.columns.iter().position(|c| c == name).ok_or_else(|| {
+                format!(
+                    "Column '{}' must appear in GROUP BY or be used in an aggregate",
+                    name
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if idx.iter().copied().eq(0..rel.columns.len()) {
+        return Ok(rel);
+    }
+    Ok(Relation {
+        columns: output.to_vec(),
+        rows: rel
+            .rows
+            .into_iter()
+            .map(|row| idx.iter().map(|&i| row[i].clone()).collect())
+            .collect(),
+    })
+}
+
+/// Combines the results of a compound SELECT. INTERSECT binds tighter than
+/// UNION and EXCEPT, which apply left to right; columns are matched by
+/// position and named after the first SELECT.
+pub fn combine_relations(
+    first: Relation,
+    rest: Vec<(SetOp, Relation)>,
+) -> Result<Relation, String> {
+    for (i, (_, rel)) in rest.iter().enumerate() {
+        check_compatible(&first, rel, i + 2)?;
+    }
+
+    // Fold INTERSECT chains first...
+    let mut terms: Vec<(Option<SetOp>, Relation)> = vec![(None, first)];
+    for (op, rel) in rest {
+        if op == SetOp::Intersect {
+            let (_, last) = terms.last_mut().unwrap();
+            let right: HashSet<_> = rel.rows.iter().map(|r| row_key(r)).collect();
+            let left = std::mem::take(&mut last.rows);
+            last.rows = distinct(left)
+                .into_iter()
+                .filter(|r| right.contains(&row_key(r)))
+                .collect();
+        } else {
+            terms.push((Some(op), rel));
+        }
+    }
+
+    // ...then UNION [ALL] / EXCEPT left to right.
+    let mut terms = terms.into_iter();
+    let (_, mut acc) = terms.next().unwrap();
+    for (op, rel) in terms {
+        match op {
+            Some(SetOp::UnionAll) => acc.rows.extend(rel.rows),
+            Some(SetOp::Union) => {
+                acc.rows.extend(rel.rows);
+                acc.rows = distinct(std::mem::take(&mut acc.rows));
+            }
+            Some(SetOp::Except) => {
+                let right: HashSet<_> = rel.rows.iter().map(|r| row_key(r)).collect();
+                acc.rows = distinct(std::mem::take(&mut acc.rows))
+                    .into_iter()
+                    .filter(|r| !right.contains(&row_key(r)))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    Ok(acc)
+}
+
+fn check_compatible(first: &Relation, other: &Relation, position: usize) -> Result<(), String> {
+    if first.columns.len() != other.columns.len() {
+        return Err(format!(
+            "SELECT #{} returns {} columns, expected {}",
+            position,
+            other.columns.len(),
+            first.columns.len()
+        ));
+    }
+    for (idx, name) in first.columns.iter().enumerate() {
+        if let (Some(a), Some(b)) = (column_kind(first, idx), column_kind(other, idx))
+            && a != b
+        {
+            return Err(format!(
+                "Column '{}' of SELECT #{} is {}, expected {}",
+                name, position, b, a
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Type class of the first non-NULL value in a column; ints and floats mix.
+fn column_kind(rel: &Relation, idx: usize) -> Option<&'static str> {
+    rel.rows
+        .iter()
+        .find_map(|r| r[idx].as_ref())
+        .map(|v| match v {
+            ScalarValue::Int(_) | ScalarValue::Float(_) => "numeric",
+            ScalarValue::String(_) => "string",
+            ScalarValue::Bool(_) => "bool",
+        })
+}
+
+fn row_key(row: &[Option<ScalarValue>]) -> Vec<Option<ScalarValue>> {
+    row.iter()
+        .map(|v| v.as_ref().map(|v| v.normalized()))
+        .collect()
+}
+
+/// Removes duplicate rows, keeping the first occurrence.
+fn distinct(rows: Vec<Vec<Option<ScalarValue>>>) -> Vec<Vec<Option<ScalarValue>>> {
+    let mut seen = HashSet::new();
+    rows.into_iter()
+        .filter(|r| seen.insert(row_key(r)))
+        .collect()
+}
+
+/// Runs a query over an in-memory relation (e.g. a CTE) on the coordinator,
+/// producing the partial a worker would for a scan of it.
+pub fn relation_partial(rel: &Relation, req: &QueryRequest) -> PartialAggregate {
+    let group_by = derive_group_by(req);
+    let mut partial = PartialAggregate {
+        worker_port: 0,
+        segment: 0,
+        rows_scanned: rel.rows.len() as u64,
+        rows_matched: 0,
+        bytes_read: 0,
+        segments_skipped: 0,
+        skip_reason: None,
+        exec_us: 0,
+        groups: HashMap::new(),
+        grouping_groups: vec![HashMap::new(); req.grouping_sets.len()],
+        rows: None,
+        error: None,
+    };
+    if !req.windows.is_empty() {
+        let columns = window_input_columns(&req.projections, &req.windows);
+        let rows = rel_rows(rel)
+            .filter(|row| row_matches(&req.filters, row))
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|c| row.get(c).cloned().flatten())
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        partial.rows_matched = rows.len() as u64;
+        partial.rows = Some(Relation { columns, rows });
+        return partial;
+    }
+    for values in rel_rows(rel) {
+        if accumulate(
+            &mut partial.groups,
+            &mut partial.grouping_groups,
+            req,
+            &group_by,
+            &values,
+        ) {
+            partial.rows_matched += 1;
+        }
+    }
+    partial
+}
+
+/// Rows for GROUPING SETS results: every GROUP BY column (NULL where the set
+/// rolls it up), the GROUPING() columns, then the aggregates. Sets appear in
+/// the order they were listed.
+pub fn grouping_sets_to_relation(sets: &[GroupMap], req: &QueryRequest) -> Relation {
+    let mut columns = req.group_by.clone();
+    columns.extend(req.grouping.iter().map(grouping_column_name));
+    columns.extend(req.aggregates.iter().map(|agg| {
+        agg.alias
+            .clone()
+            .unwrap_or_else(|| normalize_header(&agg.output_name))
+    }));
+
+    let mut rows = Vec::new();
+    for (set, merged) in req.grouping_sets.iter().zip(sets) {
+        let mut keys: Vec<&GroupKey> = merged.keys().collect();
+        keys.sort_by(|a, b| compare_group_keys(a, b));
+        for key in keys {
+            let states = &merged[key];
+            let values: HashMap<&String, Option<ScalarValue>> =
+                set.iter().zip(key.iter().cloned()).collect();
+            let mut row: Vec<Option<ScalarValue>> = req
+                .group_by
+                .iter()
+                .map(|col| values.get(col).cloned().flatten())
+                .collect();
+            for g in &req.grouping {
+                let bits = g
+                    .columns
+                    .iter()
+                    .fold(0i64, |acc, col| (acc << 1) | i64::from(!set.contains(col)));
+                row.push(Some(ScalarValue::Int(bits)));
+            }
+            for agg in &req.aggregates {
+                row.push(
+                    states
+                        .get(&agg.output_name)
+                        .and_then(|s| finalize_state(agg.func, s)),
+                );
+            }
+            rows.push(row);
+        }
+    }
+    Relation { columns, rows }
+}
+
+/// Rows of a relation, keyed by column name.
+fn rel_rows(rel: &Relation) -> impl Iterator<Item = HashMap<String, Option<ScalarValue>>> + '_ {
+    rel.rows
+        .iter()
+        .map(|row| rel.columns.iter().cloned().zip(row.clone()).collect())
+}
+
+/// Applies ORDER BY and LIMIT. Columns are matched by name, or by the
+/// aggregate they were written as (`ORDER BY SUM(amount)`). NULLs sort last.
+pub fn apply_order_limit(
+    mut rel: Relation,
+    req: &QueryRequest,
+    order_by: &[OrderByExpr],
+    limit: Option<u64>,
+) -> Result<Relation, String> {
+    let mut sort_cols = Vec::with_capacity(order_by.len());
+    for item in order_by {
+        let idx = order_column(&rel, req, &item.column)
+            .ok_or_else(|| format!("ORDER BY column '{}' is not in the result", item.column))?;
+        sort_cols.push((idx, item.descending));
+    }
+
+    rel.rows.sort_by(|a, b| {
+        for &(idx, descending) in &sort_cols {
+            let ord = compare_nullable(&a[idx], &b[idx], descending);
+            if ord.is_ne() {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    });
+    if let Some(n) = limit {
+        rel.rows.truncate(n as usize);
+    }
+    Ok(rel)
+}
+
+fn order_column(rel: &Relation, req: &QueryRequest, name: &str) -> Option<usize> {
+    if let Some(idx) = rel.columns.iter().position(|c| c == name) {
+        return Some(idx);
+    }
+    let column = match req.aggregates.iter().find(|a| a.output_name == name) {
+        Some(agg) => agg
+            .alias
+            .clone()
+            .unwrap_or_else(|| normalize_header(&agg.output_name)),
+        None => normalize_header(name),
+    };
+    rel.columns.iter().position(|c| *c == column)
+}
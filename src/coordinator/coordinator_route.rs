@@ -1,60 +1,721 @@
-use super::coordinator_merge::merge_partials;
-use crate::minisql::minisql_print::format_results;
-use crate::rpc::{PartialAggregate, QueryRequest};
+use super::coordinator_catalog::{COLUMNS_TABLE, catalog_table, information_schema_columns};
+use super::coordinator_cluster::count_segments;
+use super::coordinator_explain::{explain_plan, format_analysis};
+use super::coordinator_merge::{merge_grouping_sets, merge_partials};
+use super::coordinator_relation::{
+    apply_order_limit, combine_relations, finish_relation, grouping_sets_to_relation,
+    groups_to_relation, project_output, relation_partial,
+};
+use super::coordinator_stats::{
+    GatherTrace, OperatorTrace, PartialTrace, QueryStats, QueryTrace, WORKER_UNAVAILABLE,
+    WorkerCall,
+};
+use super::coordinator_view::{answer_from_view, view_dir, view_query};
+use crate::minisql::minisql_bind::bind;
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_eval::finalize_state;
+use crate::minisql::minisql_plan::{Fragments, derive_group_by, plan_query};
+use crate::minisql::minisql_print::format_relation;
+use crate::minisql::minisql_window::{eval_windows, window_input_columns};
+use crate::rpc::{
+    AggregateState, CteDef, Explain, GroupMap, InList, JoinSpec, PartialAggregate, Predicate,
+    QueryRequest, Relation, ScalarValue,
+};
+use crate::storage::storage_load::colocation;
+use crate::storage::storage_schema::{ColumnDef, parse_schema_file};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
-pub async fn run_query(worker_ports: &[u16], request: QueryRequest) -> anyhow::Result<String> {
-    let mut partials = Vec::new();
-    for (idx, port) in worker_ports.iter().enumerate() {
-        let attempt = run_query_on_worker(*port, &request).await;
-        let result = if attempt.is_err() {
-            // one retry
-            run_query_on_worker(*port, &request).await
-        } else {
-            attempt
+/// Views reading views deeper than this are rejected, which also ends
+/// views that read themselves.
+const MAX_VIEW_DEPTH: usize = 16;
+
+/// Above this many values an `IN` list is shipped to workers as a hash set.
+const IN_LIST_HASH_THRESHOLD: usize = 64;
+
+/// Runs a bound statement. EXPLAIN returns its report in place of rows.
+pub async fn run_query(worker_ports: &[u16], request: QueryRequest) -> Result<String, QueryError> {
+    Ok(run_query_result(worker_ports, request).await?.to_text())
+}
+
+/// Result of a statement: its rows, or the report of an EXPLAIN, and the
+/// execution stats.
+#[derive(Debug)]
+pub struct QueryResult {
+    pub relation: Relation,
+    pub explain: Option<String>,
+    /// Set instead of rows by statements that only change state, e.g.
+    /// `CREATE TABLE`.
+    pub message: Option<String>,
+    pub stats: QueryStats,
+}
+
+impl QueryResult {
+    /// The result as printed by `netrepl`: a table and execution details.
+    pub fn to_text(&self) -> String {
+        if let Some(message) = &self.message {
+            return format!("{}\n", message);
+        }
+        let mut out = match &self.explain {
+            Some(report) => report.clone(),
+            None => format_relation(
+                &self.relation,
+                self.stats.rows_scanned,
+                self.stats.segments_skipped,
+                self.stats.wall_us / 1000,
+            ),
         };
+        if self.stats.segments_unavailable > 0 {
+            out.push_str(&format!(
+                "Partial result: {} segments unavailable\n",
+                self.stats.segments_unavailable
+            ));
+        }
+        out
+    }
+}
+
+pub async fn run_query_result(
+    worker_ports: &[u16],
+    request: QueryRequest,
+) -> Result<QueryResult, QueryError> {
+    run_query_traced(worker_ports, request, QueryTrace::default()).await
+}
+
+/// Like `run_query_result`, recording into a trace the caller has set up
+/// (e.g. with `allow_partial`).
+pub async fn run_query_traced(
+    worker_ports: &[u16],
+    mut request: QueryRequest,
+    trace: QueryTrace,
+) -> Result<QueryResult, QueryError> {
+    // Reject unknown columns and mistyped literals before contacting workers.
+    bind(&mut request)?;
+    run_bound_traced(worker_ports, request, trace).await
+}
+
+/// Like `run_query_traced`, for a request that is already bound.
+pub async fn run_bound_traced(
+    worker_ports: &[u16],
+    request: QueryRequest,
+    mut trace: QueryTrace,
+) -> Result<QueryResult, QueryError> {
+    let started = Instant::now();
+    let explain = request.explain;
+    let (relation, explain) = match explain {
+        Some(Explain::Plan) => (
+            Relation::default(),
+            Some(explain_plan(worker_ports, &request)),
+        ),
+        Some(Explain::Analyze) => {
+            let rel = execute_query(worker_ports, request, &mut trace).await?;
+            let wall_us = started.elapsed().as_micros() as u64;
+            let report = format_analysis(&trace, &rel, wall_us);
+            (Relation::default(), Some(report))
+        }
+        None => (
+            execute_query(worker_ports, request, &mut trace).await?,
+            None,
+        ),
+    };
+    Ok(QueryResult {
+        relation,
+        explain,
+        message: None,
+        stats: trace.stats(started.elapsed().as_micros() as u64),
+    })
+}
+
+/// The result of a statement without rows.
+pub fn command_result(message: &str) -> QueryResult {
+    QueryResult {
+        relation: Relation::default(),
+        explain: None,
+        message: Some(message.to_string()),
+        stats: QueryStats::default(),
+    }
+}
 
-        match result {
-            Ok(partial) => partials.push(partial),
-            Err(e) => {
-                println!("Worker {port} query failed after retry: {}", e);
-                partials.push(crate::rpc::PartialAggregate {
-                    worker_port: *port,
-                    segment: idx as u32,
-                    rows_scanned: 0,
-                    segments_skipped: 1,
-                    exec_ms: 0,
-                    groups: std::collections::HashMap::new(),
+/// Runs a bound query to its final relation, recording what every fragment
+/// and coordinator operator did in `trace`.
+async fn execute_query(
+    worker_ports: &[u16],
+    mut request: QueryRequest,
+    trace: &mut QueryTrace,
+) -> Result<Relation, QueryError> {
+    // CTEs are materialized in order; later ones may read earlier ones.
+    let mut ctes: HashMap<String, Relation> = HashMap::new();
+    for cte in std::mem::take(&mut request.ctes) {
+        if let Some(rows) = cte.rows {
+            ctes.insert(cte.name, rows);
+            continue;
+        }
+        let mut query = cte.query;
+        let rel = execute_relation(worker_ports, &mut query, &ctes, trace).await?;
+        ctes.insert(
+            cte.name,
+            project_output(rel, &query.output).map_err(|e| anyhow::anyhow!(e))?,
+        );
+    }
+
+    let rel = execute_relation(worker_ports, &mut request, &ctes, trace).await?;
+    let rel = if request.set_ops.is_empty() {
+        finish_relation(
+            &plan_query(&request).coordinator,
+            rel,
+            &request,
+            &mut trace.operators,
+        )
+    } else {
+        let first = project_output(rel, &request.output).map_err(|e| anyhow::anyhow!(e))?;
+        let mut rest = Vec::new();
+        for branch in std::mem::take(&mut request.set_ops) {
+            let mut query = branch.query;
+            let rel = execute_relation(worker_ports, &mut query, &ctes, trace).await?;
+            let rel = project_output(rel, &query.output).map_err(|e| anyhow::anyhow!(e))?;
+            rest.push((branch.op, rel));
+        }
+        let name = format!("Combine {} SELECTs", rest.len() + 1);
+        let rows_in =
+            (first.rows.len() + rest.iter().map(|(_, r)| r.rows.len()).sum::<usize>()) as u64;
+        combine_relations(first, rest).and_then(|rel| {
+            let combined = rel.rows.len() as u64;
+            trace.operators.push(OperatorTrace {
+                name,
+                rows_in,
+                rows_out: combined,
+            });
+            let rel = apply_order_limit(rel, &request, &request.order_by, request.limit)?;
+            if !request.order_by.is_empty() || request.limit.is_some() {
+                trace.operators.push(OperatorTrace {
+                    name: "Sort/Limit".to_string(),
+                    rows_in: combined,
+                    rows_out: rel.rows.len() as u64,
                 });
             }
+            Ok(rel)
+        })
+    }
+    .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(rel)
+}
+
+/// Like `execute`, but returns the result as rows; window queries and
+/// grouping sets are only supported here.
+pub async fn execute_relation(
+    worker_ports: &[u16],
+    request: &mut QueryRequest,
+    ctes: &HashMap<String, Relation>,
+    trace: &mut QueryTrace,
+) -> anyhow::Result<Relation> {
+    if !request.grouping_sets.is_empty() {
+        let partials = gather(worker_ports, request, ctes, trace).await?;
+        let sets = merge_grouping_sets(&partials, request.grouping_sets.len());
+        record_rows_out(trace, sets.iter().map(|s| s.len()).sum());
+        return Ok(grouping_sets_to_relation(&sets, request));
+    }
+    if request.windows.is_empty() {
+        let merged = execute(worker_ports, request, ctes, trace).await?;
+        return Ok(groups_to_relation(&merged, request));
+    }
+    if request.join.is_some() {
+        return Err(anyhow::anyhow!(
+            "Window functions are not supported with JOIN"
+        ));
+    }
+
+    request.window_local = windows_local(request);
+    let partials = gather(worker_ports, request, ctes, trace).await?;
+
+    let mut rel = Relation {
+        columns: window_input_columns(&request.projections, &request.windows),
+        rows: Vec::new(),
+    };
+    for partial in partials {
+        if let Some(part) = partial.rows {
+            rel.columns = part.columns;
+            rel.rows.extend(part.rows);
+        }
+    }
+    if !request.window_local {
+        rel = eval_windows(&rel, request);
+    } else if rel.rows.is_empty() {
+        rel.columns = eval_windows(&rel, request).columns;
+    }
+    record_rows_out(trace, rel.rows.len());
+    Ok(rel)
+}
+
+/// Partitions on the table key never span segments, so workers can evaluate
+/// those windows locally; anything else is evaluated on the coordinator.
+pub fn windows_local(request: &QueryRequest) -> bool {
+    let key = table_key(&request.table).ok().map(|k| k.name);
+    key.is_some_and(|key| {
+        request
+            .windows
+            .iter()
+            .all(|w| w.partition_by.len() == 1 && w.partition_by[0] == key)
+    })
+}
+
+/// Sets the merged row count of the fragment `gather` just recorded.
+fn record_rows_out(trace: &mut QueryTrace, rows: usize) {
+    if let Some(last) = trace.gathers.last_mut() {
+        last.rows_out = rows as u64;
+    }
+}
+
+/// Scatters the request to every worker and merges their partials. Subqueries
+/// are executed first and inlined.
+pub async fn execute(
+    worker_ports: &[u16],
+    request: &mut QueryRequest,
+    ctes: &HashMap<String, Relation>,
+    trace: &mut QueryTrace,
+) -> anyhow::Result<GroupMap> {
+    if !request.windows.is_empty() || !request.grouping_sets.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Window functions and grouping sets are not supported in subqueries"
+        ));
+    }
+    let partials = gather(worker_ports, request, ctes, trace).await?;
+    let (merged, ..) = merge_partials(&partials);
+    record_rows_out(trace, merged.len());
+    Ok(merged)
+}
+
+/// Collects the partials of a request: from the workers, or computed here
+/// when the table names a CTE. Each is recorded in `trace`.
+async fn gather(
+    worker_ports: &[u16],
+    request: &mut QueryRequest,
+    ctes: &HashMap<String, Relation>,
+    trace: &mut QueryTrace,
+) -> anyhow::Result<Vec<PartialAggregate>> {
+    if let Some(rel) = ctes.get(&request.table) {
+        if request.join.is_some() {
+            return Err(anyhow::anyhow!(
+                "JOIN is not supported on CTE '{}'",
+                request.table
+            ));
         }
+        resolve_subqueries(worker_ports, request, ctes, trace).await?;
+        let started = Instant::now();
+        let fragments = plan_query(request);
+        let partial = match fragments.worker_request(request) {
+            Some(planned) => relation_partial(rel, &planned),
+            None => relation_partial(&Relation::default(), request),
+        };
+        let calls = vec![(partial, WorkerCall::default())];
+        let cte = Some(request.table.clone());
+        return Ok(record_gather(
+            trace, request, cte, fragments, calls, started,
+        ));
     }
+    if let Some(join) = &request.join {
+        check_colocated(&request.table, join)?;
+    }
+    // Plan once subqueries are inlined, so their values fold too.
+    resolve_subqueries(worker_ports, request, ctes, trace).await?;
+    let started = Instant::now();
+    if let Some((view, calls)) =
+        answer_from_view(worker_ports, request, trace.allow_partial).await?
+    {
+        let partials = record_gather(trace, request, None, plan_query(request), calls, started);
+        if let Some(last) = trace.gathers.last_mut() {
+            last.view = Some(view);
+        }
+        return Ok(partials);
+    }
+    let fragments = plan_query(request);
+    let calls = match fragments.worker_request(request) {
+        Some(planned) => scatter(worker_ports, &planned, trace.allow_partial).await?,
+        None => {
+            // The filters contradict each other; no worker is contacted.
+            let mut partial = relation_partial(&Relation::default(), request);
+            partial.segments_skipped = worker_ports.len() as u64;
+            vec![(partial, WorkerCall::default())]
+        }
+    };
+    Ok(record_gather(
+        trace, request, None, fragments, calls, started,
+    ))
+}
+
+fn record_gather(
+    trace: &mut QueryTrace,
+    request: &QueryRequest,
+    cte: Option<String>,
+    fragments: Fragments,
+    calls: Vec<(PartialAggregate, WorkerCall)>,
+    started: Instant,
+) -> Vec<PartialAggregate> {
+    trace.gathers.push(GatherTrace {
+        query: request.query.clone(),
+        cte,
+        view: None,
+        fragments,
+        partials: calls
+            .iter()
+            .map(|(p, call)| PartialTrace::new(p, *call))
+            .collect(),
+        rows_out: 0,
+        elapsed_us: started.elapsed().as_micros() as u64,
+    });
+    calls.into_iter().map(|(p, _)| p).collect()
+}
 
-    let (merged, rows_scanned, segments_skipped, exec_ms) = merge_partials(&partials);
-    let effective_group_by = if request.aggregates.is_empty() && request.group_by.is_empty() {
-        if request.projections.len() == 1 && request.projections[0] == "*" {
-            Vec::new()
-        } else {
-            request.projections.clone()
+/// Sends the request to every worker, retrying each once. With
+/// `allow_partial` a worker that still fails leaves its segment out.
+async fn scatter(
+    worker_ports: &[u16],
+    request: &QueryRequest,
+    allow_partial: bool,
+) -> anyhow::Result<Vec<(PartialAggregate, WorkerCall)>> {
+    let mut partials = Vec::new();
+    for (idx, port) in worker_ports.iter().enumerate() {
+        match call_worker(*port, idx, request, allow_partial).await {
+            Ok(call) => partials.push(call),
+            Err(e) if request.mutation.is_some() => {
+                return Err(partially_applied(e.into(), &partials).into());
+            }
+            Err(e) => return Err(e),
         }
+    }
+    Ok(partials)
+}
+
+/// A mutation is not atomic across segments: the ones before the failed
+/// worker keep their changes, so the error names them.
+fn partially_applied(mut err: QueryError, done: &[(PartialAggregate, WorkerCall)]) -> QueryError {
+    let changed: Vec<String> = done
+        .iter()
+        .filter(|(p, _)| p.rows_matched > 0)
+        .map(|(p, _)| p.segment.to_string())
+        .collect();
+    let note = if changed.is_empty() {
+        "; no segment was changed".to_string()
     } else {
-        request.group_by.clone()
+        format!("; segments already changed: {}", changed.join(", "))
     };
+    err.message_mut().push_str(&note);
+    err
+}
 
-    Ok(format_results(
-        merged,
-        rows_scanned,
-        segments_skipped,
-        exec_ms,
-        &effective_group_by,
-    ))
+/// Sends the request to the worker of segment `idx`, retrying once; see
+/// `scatter`. Mutations are not retried, since a worker whose answer was
+/// lost may have applied them.
+pub async fn call_worker(
+    port: u16,
+    idx: usize,
+    request: &QueryRequest,
+    allow_partial: bool,
+) -> anyhow::Result<(PartialAggregate, WorkerCall)> {
+    let started = Instant::now();
+    let mut call = WorkerCall::default();
+    let attempt = run_query_on_worker(port, request, &mut call).await;
+    let result = if attempt.is_err() && request.mutation.is_none() {
+        // one retry
+        call.retries += 1;
+        run_query_on_worker(port, request, &mut call).await
+    } else {
+        attempt
+    };
+    call.latency_us = started.elapsed().as_micros() as u64;
+
+    match result {
+        // The worker answered, but could not do what was asked; not
+        // something a partial result can paper over.
+        Ok(partial) if partial.error.is_some() => Err(QueryError::Execution {
+            message: format!(
+                "Worker {} (segment {}) failed: {}",
+                port,
+                idx,
+                partial.error.unwrap_or_default()
+            ),
+        }
+        .into()),
+        Ok(partial) => Ok((partial, call)),
+        Err(e) if allow_partial => {
+            let mut partial = relation_partial(&Relation::default(), request);
+            partial.worker_port = port;
+            partial.segment = idx as u32;
+            partial.rows_scanned = 0;
+            partial.segments_skipped = 1;
+            partial.skip_reason = Some(format!("{} ({})", WORKER_UNAVAILABLE, e));
+            Ok((partial, call))
+        }
+        // A missing segment would silently change the answer.
+        Err(e) => Err(QueryError::WorkerUnavailable {
+            message: format!(
+                "Worker {} (segment {}) failed{}: {}",
+                port,
+                idx,
+                if call.retries > 0 { " after retry" } else { "" },
+                e
+            ),
+            worker_port: port,
+        }
+        .into()),
+    }
+}
+
+/// Runs every uncorrelated subquery in the WHERE clause and rewrites its
+/// filter into a literal (scalar subquery) or an `IN` list.
+async fn resolve_subqueries(
+    worker_ports: &[u16],
+    request: &mut QueryRequest,
+    ctes: &HashMap<String, Relation>,
+    trace: &mut QueryTrace,
+) -> anyhow::Result<()> {
+    for filter in request.filters.iter_mut() {
+        if let Some(mut inner) = filter.subquery.take() {
+            let merged = Box::pin(execute(worker_ports, &mut inner, ctes, trace)).await?;
+            let values = subquery_values(&merged, &inner)?;
+
+            if matches!(filter.pred, Predicate::In) {
+                filter.list = Some(InList::Values(values.into_iter().flatten().collect()));
+            } else {
+                if values.len() > 1 {
+                    return Err(anyhow::anyhow!(
+                        "Scalar subquery returned {} rows: {}",
+                        values.len(),
+                        inner.query.trim()
+                    ));
+                }
+                match values.into_iter().next().flatten() {
+                    Some(v) => filter.value = v,
+                    None => {
+                        // Comparing against NULL never matches.
+                        filter.pred = Predicate::In;
+                        filter.list = Some(InList::Values(Vec::new()));
+                    }
+                }
+            }
+        }
+
+        if let Some(InList::Values(values)) = &filter.list
+            && values.len() > IN_LIST_HASH_THRESHOLD
+        {
+            let set = values.iter().map(|v| v.normalized()).collect();
+            filter.list = Some(InList::Hashed(set));
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the single output column of a subquery.
+fn subquery_values(
+    merged: &GroupMap,
+    inner: &QueryRequest,
+) -> anyhow::Result<Vec<Option<ScalarValue>>> {
+    let group_by = derive_group_by(inner);
+    if inner.aggregates.is_empty() {
+        if group_by.len() != 1 {
+            return Err(anyhow::anyhow!(
+                "Subquery must return exactly one column: {}",
+                inner.query.trim()
+            ));
+        }
+        return Ok(merged.keys().map(|k| k[0].clone()).collect());
+    }
+
+    if inner.aggregates.len() != 1 || !inner.projections.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Subquery must return exactly one column: {}",
+            inner.query.trim()
+        ));
+    }
+    let agg = &inner.aggregates[0];
+    if merged.is_empty() && group_by.is_empty() {
+        // An ungrouped aggregate over no rows still yields one row.
+        return Ok(vec![finalize_state(agg.func, &AggregateState::default())]);
+    }
+    Ok(merged
+        .values()
+        .map(|g| {
+            g.get(&agg.output_name)
+                .and_then(|state| finalize_state(agg.func, state))
+        })
+        .collect())
+}
+
+/// Resolves the FROM tables of a query, its CTEs, set-operation branches and
+/// subqueries against the catalog of the data root `data`. Names of CTEs
+/// are left as they are; views and `information_schema.columns` become CTEs
+/// ahead of the query's own (see `expand_view`); any other name must be a
+/// table of the catalog.
+pub fn resolve_tables(request: &mut QueryRequest, data: &str) -> Result<(), QueryError> {
+    let cte_names: Vec<String> = request.ctes.iter().map(|c| c.name.clone()).collect();
+    let mut views = Vec::new();
+    resolve_from(request, data, &cte_names, &mut views)?;
+    for cte in request.ctes.iter_mut() {
+        resolve_from(&mut cte.query, data, &cte_names, &mut views)?;
+    }
+    for branch in request.set_ops.iter_mut() {
+        resolve_from(&mut branch.query, data, &cte_names, &mut views)?;
+    }
+    let mut expanded = Vec::new();
+    for name in views {
+        expand_view(data, &name, &mut expanded, 0)?;
+    }
+    request.ctes.splice(0..0, expanded);
+    Ok(())
+}
+
+fn resolve_from(
+    request: &mut QueryRequest,
+    data: &str,
+    cte_names: &[String],
+    views: &mut Vec<String>,
+) -> Result<(), QueryError> {
+    if request.table.eq_ignore_ascii_case(COLUMNS_TABLE) {
+        request.table = COLUMNS_TABLE.to_string();
+    }
+    if cte_names.contains(&request.table) {
+        // Read from the CTE.
+    } else if request.table == COLUMNS_TABLE || view_dir(data, &request.table).is_some() {
+        if !views.contains(&request.table) {
+            views.push(request.table.clone());
+        }
+    } else {
+        request.table = resolve_table(data, &request.table)?;
+    }
+    if let Some(join) = request.join.as_mut() {
+        join.right_table = resolve_table(data, &join.right_name)?;
+    }
+    for filter in request.filters.iter_mut() {
+        if let Some(inner) = filter.subquery.as_mut() {
+            resolve_from(inner, data, cte_names, views)?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends view `name` to `expanded` as a CTE, after the views it reads.
+/// Views are expanded where they are read, so they see the tables as they
+/// are now; `information_schema.columns` becomes a CTE of the catalog's
+/// columns as they are now.
+fn expand_view(
+    data: &str,
+    name: &str,
+    expanded: &mut Vec<CteDef>,
+    depth: usize,
+) -> Result<(), QueryError> {
+    if expanded.iter().any(|c| c.name == name) {
+        return Ok(());
+    }
+    if name == COLUMNS_TABLE {
+        expanded.push(CteDef::rows(name, information_schema_columns(data)?));
+        return Ok(());
+    }
+    if depth >= MAX_VIEW_DEPTH {
+        return Err(QueryError::Bind {
+            message: format!(
+                "View '{}' is nested more than {} views deep (does it read itself?)",
+                name, MAX_VIEW_DEPTH
+            ),
+        });
+    }
+    let dir = view_dir(data, name).ok_or_else(|| QueryError::NotFound {
+        message: format!("View '{}' does not exist", name),
+    })?;
+    let mut query = view_query(&dir)?;
+    let mut nested = Vec::new();
+    resolve_from(&mut query, data, &[], &mut nested)?;
+    for inner in nested {
+        expand_view(data, &inner, expanded, depth + 1)?;
+    }
+    expanded.push(CteDef {
+        name: name.to_string(),
+        query,
+        rows: None,
+    });
+    Ok(())
+}
+
+/// Resolves a table referenced by name in SQL to its directory in the data
+/// root `data`; the name must be in the catalog.
+pub fn resolve_table(data: &str, name: &str) -> Result<String, QueryError> {
+    catalog_table(data, name)
+        .map(|dir| dir.display().to_string())
+        .ok_or_else(|| QueryError::NotFound {
+            message: format!("Table '{}' does not exist", name),
+        })
+}
+
+/// A merge join runs segment-by-segment, which is only correct when both
+/// sides are keyed on the join columns and share their segment boundaries
+/// (the same `colocation` in `_table.txt`).
+fn check_colocated(left_table: &str, join: &JoinSpec) -> anyhow::Result<()> {
+    let left_key = table_key(left_table)?;
+    let right_key = table_key(&join.right_table)?;
+    if left_key.name != join.left_key || right_key.name != join.right_key {
+        return Err(anyhow::anyhow!(
+            "JOIN must be on the key columns ({}.{} = {}.{})",
+            join.left_name,
+            left_key.name,
+            join.right_name,
+            right_key.name
+        ));
+    }
+    if left_key.col_type != right_key.col_type {
+        return Err(anyhow::anyhow!(
+            "JOIN key types differ: {:?} vs {:?}",
+            left_key.col_type,
+            right_key.col_type
+        ));
+    }
+    let left_segments = count_segments(Path::new(left_table))?;
+    let right_segments = count_segments(Path::new(&join.right_table))?;
+    if left_segments != right_segments {
+        return Err(anyhow::anyhow!(
+            "Tables '{}' and '{}' are not co-located ({} vs {} segments)",
+            join.left_name,
+            join.right_name,
+            left_segments,
+            right_segments
+        ));
+    }
+    let left = colocation(Path::new(left_table));
+    if left_table != join.right_table
+        && (left.is_none() || left != colocation(Path::new(&join.right_table)))
+    {
+        return Err(anyhow::anyhow!(
+            "Tables '{}' and '{}' are not co-located (load one with --colocate-with the other)",
+            join.left_name,
+            join.right_name
+        ));
+    }
+    Ok(())
+}
+
+fn table_key(table_dir: &str) -> anyhow::Result<ColumnDef> {
+    let path = Path::new(table_dir).join("_schema.ssf");
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read schema {:?}: {}", path, e))?;
+    let schema = parse_schema_file(&contents).map_err(|e| anyhow::anyhow!(e))?;
+    schema
+        .into_iter()
+        .find(|c| c.is_key)
+        .ok_or_else(|| anyhow::anyhow!("Table {:?} has no key column", table_dir))
 }
 
-async fn run_query_on_worker(port: u16, req: &QueryRequest) -> anyhow::Result<PartialAggregate> {
+/// One request/response round trip; adds the time spent encoding the
+/// request and decoding the partial to `call`.
+async fn run_query_on_worker(
+    port: u16,
+    req: &QueryRequest,
+    call: &mut WorkerCall,
+) -> anyhow::Result<PartialAggregate> {
     let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
 
+    let encode = Instant::now();
     let payload = rmp_serde::to_vec_named(req)?;
+    call.serialize_us += encode.elapsed().as_micros() as u64;
     let len = (payload.len() as u32).to_le_bytes();
 
     stream.write_all(&len).await?;
@@ -67,6 +728,8 @@ async fn run_query_on_worker(port: u16, req: &QueryRequest) -> anyhow::Result<Pa
     let mut buf = vec![0u8; resp_len];
     stream.read_exact(&mut buf).await?;
 
+    let decode = Instant::now();
     let partial: PartialAggregate = rmp_serde::from_slice(&buf)?;
+    call.serialize_us += decode.elapsed().as_micros() as u64;
     Ok(partial)
 }
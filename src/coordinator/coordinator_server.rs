@@ -1,21 +1,44 @@
-use super::coordinator_route::run_query;
-use axum::{Router, extract::State, http::StatusCode, routing::post};
-use std::sync::Arc;
+use super::coordinator_prepare::{StatementCache, execute_prepared};
+use super::coordinator_route::QueryResult;
+use super::coordinator_session::{OutputFormat, SessionStore, Settings, run_statements};
+use super::coordinator_stats::QueryStats;
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_parse::parse_script;
+use crate::rpc::ScalarValue;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header::ACCEPT},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 struct AppState {
     worker_ports: Arc<Vec<u16>>,
-    table: String,
+    data: String, // the catalog's data root
+    statements: Arc<Mutex<StatementCache>>,
+    sessions: Arc<Mutex<SessionStore>>,
 }
 
-pub async fn serve(port: u16, worker_ports: Vec<u16>, table: &str) -> anyhow::Result<()> {
+/// Header naming the client session whose `SET`s apply to a request.
+pub const SESSION_HEADER: &str = "x-session-id";
+
+/// Serves the tables and views of the catalog in the data root `data`.
+pub async fn serve(port: u16, worker_ports: Vec<u16>, data: &str) -> anyhow::Result<()> {
     let state = AppState {
-        table: table.to_string(),
+        data: data.to_string(),
         worker_ports: Arc::new(worker_ports),
+        statements: Arc::default(),
+        sessions: Arc::default(),
     };
 
     let app = Router::new()
         .route("/query", post(handle_query))
+        .route("/prepare", post(handle_prepare))
+        .route("/execute", post(handle_execute))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
@@ -24,15 +47,215 @@ pub async fn serve(port: u16, worker_ports: Vec<u16>, table: &str) -> anyhow::Re
     Ok(())
 }
 
-async fn handle_query(State(state): State<AppState>, body: String) -> (StatusCode, String) {
-    match crate::minisql::minisql_parse::parse_sql(&body) {
-        Ok(mut req) => {
-            req.table = state.table.clone();
-            match run_query(&state.worker_ports, req).await {
-                Ok(r) => (StatusCode::OK, r),
-                Err(e) => (StatusCode::OK, format!("Error: {}", e)),
-            }
+/// Runs every statement of the body. Answers with text tables, or with an
+/// array of `JsonResult`, one per statement, when the client accepts
+/// `application/json` or the session's format is json. A failing statement
+/// answers with `ScriptError`.
+async fn handle_query(State(state): State<AppState>, headers: HeaderMap, body: String) -> Response {
+    let statements = match parse_script(&body) {
+        Ok(statements) => statements,
+        Err(e) => return error_response(&e),
+    };
+    let mut settings = session_settings(&state, &headers);
+    let mut results = Vec::new();
+    let outcome = run_statements(
+        &state.worker_ports,
+        &state.data,
+        statements,
+        &mut settings,
+        &mut results,
+    )
+    .await;
+    // Settings changed before a failing statement are kept.
+    save_session(&state, &headers, &settings);
+    match outcome {
+        Ok(()) if wants_json(&headers, &settings) => {
+            let results: Vec<JsonResult> = results.iter().map(JsonResult::new).collect();
+            (StatusCode::OK, Json(results)).into_response()
+        }
+        Ok(()) => {
+            let texts: Vec<String> = results.iter().map(QueryResult::to_text).collect();
+            (StatusCode::OK, texts.join("\n")).into_response()
+        }
+        Err(e) => {
+            let status =
+                StatusCode::from_u16(e.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            (status, Json(ScriptError::new(&e, &results))).into_response()
         }
-        Err(e) => (StatusCode::OK, format!("Error: {}", e)),
     }
 }
+
+/// The settings of the request's session; a request without a session id
+/// starts from the defaults.
+fn session_settings(state: &AppState, headers: &HeaderMap) -> Settings {
+    match session_id(headers) {
+        Some(id) => state.sessions.lock().unwrap().get(id),
+        None => Settings::default(),
+    }
+}
+
+fn save_session(state: &AppState, headers: &HeaderMap, settings: &Settings) {
+    if let Some(id) = session_id(headers) {
+        state.sessions.lock().unwrap().put(id, settings.clone());
+    }
+}
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty())
+}
+
+fn wants_json(headers: &HeaderMap, settings: &Settings) -> bool {
+    settings.format == OutputFormat::Json
+        || headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/json"))
+}
+
+/// `{"id": ..., "params": n}` for a statement with `$n` or `?` placeholders.
+#[derive(Serialize)]
+pub struct PrepareResponse {
+    pub id: String,
+    pub params: usize,
+}
+
+async fn handle_prepare(State(state): State<AppState>, body: String) -> Response {
+    let prepared = state.statements.lock().unwrap().prepare(&body, &state.data);
+    match prepared {
+        Ok(stmt) => Json(PrepareResponse {
+            id: stmt.id.clone(),
+            params: stmt.params,
+        })
+        .into_response(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Body of `/execute`: a prepared statement id and its parameter values.
+#[derive(Deserialize)]
+pub struct ExecuteRequest {
+    pub id: String,
+    #[serde(default)]
+    pub params: Vec<ParamValue>,
+}
+
+/// A parameter as a plain JSON scalar.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl From<ParamValue> for ScalarValue {
+    fn from(v: ParamValue) -> Self {
+        match v {
+            ParamValue::Int(i) => ScalarValue::Int(i),
+            ParamValue::Float(f) => ScalarValue::Float(f),
+            ParamValue::Bool(b) => ScalarValue::Bool(b),
+            ParamValue::String(s) => ScalarValue::String(s),
+        }
+    }
+}
+
+/// Answers like `/query`.
+async fn handle_execute(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ExecuteRequest>,
+) -> Response {
+    let settings = session_settings(&state, &headers);
+    let stmt = state.statements.lock().unwrap().get(&body.id, &state.data);
+    let result = match stmt {
+        Ok(stmt) => {
+            let values: Vec<ScalarValue> = body.params.into_iter().map(Into::into).collect();
+            execute_prepared(&state.worker_ports, &stmt, &values, &settings).await
+        }
+        Err(e) => Err(e),
+    };
+    let json = wants_json(&headers, &settings);
+    match result {
+        Ok(r) if json => (StatusCode::OK, Json(JsonResult::new(&r))).into_response(),
+        Ok(r) => (StatusCode::OK, r.to_text()).into_response(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// `{"columns": [...], "rows": [[...], ...], "stats": {...}}`; an EXPLAIN
+/// has its report under `"explain"` and no rows, DDL a `"message"`.
+#[derive(Serialize)]
+pub struct JsonResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explain: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    columns: &'a [String],
+    rows: Vec<Vec<JsonValue<'a>>>,
+    stats: &'a QueryStats,
+}
+
+impl<'a> JsonResult<'a> {
+    pub fn new(r: &'a QueryResult) -> Self {
+        JsonResult {
+            explain: r.explain.as_deref(),
+            message: r.message.as_deref(),
+            columns: &r.relation.columns,
+            rows: r
+                .relation
+                .rows
+                .iter()
+                .map(|row| row.iter().map(JsonValue).collect())
+                .collect(),
+            stats: &r.stats,
+        }
+    }
+}
+
+/// The error of the statement at index `statement` (from 0) of a script,
+/// with the results of the statements before it. Those already took
+/// effect.
+#[derive(Serialize)]
+pub struct ScriptError<'a> {
+    #[serde(flatten)]
+    error: &'a QueryError,
+    statement: usize,
+    results: Vec<JsonResult<'a>>,
+}
+
+impl<'a> ScriptError<'a> {
+    pub fn new(error: &'a QueryError, finished: &'a [QueryResult]) -> Self {
+        ScriptError {
+            error,
+            statement: finished.len(),
+            results: finished.iter().map(JsonResult::new).collect(),
+        }
+    }
+}
+
+/// A value as a plain JSON scalar (`null` for NULL).
+pub struct JsonValue<'a>(&'a Option<ScalarValue>);
+
+impl Serialize for JsonValue<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Some(ScalarValue::Int(i)) => s.serialize_i64(*i),
+            Some(ScalarValue::Float(f)) => s.serialize_f64(*f),
+            Some(ScalarValue::String(v)) => s.serialize_str(v),
+            Some(ScalarValue::Bool(b)) => s.serialize_bool(*b),
+            None => s.serialize_none(),
+        }
+    }
+}
+
+/// Parse errors are 400, bind errors 422, unknown prepared statements 404,
+/// unreachable workers 503, timeouts 504 and other failures 500; the body is
+/// the error as JSON.
+pub fn error_response(e: &QueryError) -> Response {
+    let status = StatusCode::from_u16(e.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, Json(e)).into_response()
+}
@@ -0,0 +1,303 @@
+use super::coordinator_catalog::{describe, show_tables};
+use super::coordinator_ddl::{alter_table, create_table, create_table_as, drop_table};
+use super::coordinator_dml::{insert, mutate};
+use super::coordinator_route::{
+    QueryResult, command_result, resolve_tables, run_bound_traced, run_query_traced,
+};
+use super::coordinator_stats::QueryTrace;
+use super::coordinator_view::{create_view, drop_view, refresh_view};
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_eval::format_scalar;
+use crate::minisql::minisql_parse::{Statement, parse_script};
+use crate::rpc::{QueryRequest, Relation, ScalarValue};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Sessions kept before the oldest is forgotten.
+pub const SESSION_CAPACITY: usize = 1024;
+
+/// Names accepted by `SET` and `SHOW`.
+pub const SETTING_NAMES: [&str; 3] = ["format", "timeout", "allow_partial_results"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Settings changed with `SET`; they apply to every later statement of the
+/// session.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub format: OutputFormat,
+    pub timeout_ms: u64, // 0 = no limit
+    pub allow_partial_results: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            format: OutputFormat::Text,
+            timeout_ms: 0,
+            allow_partial_results: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &ScalarValue) -> Result<(), QueryError> {
+        let invalid = |expected: &str| QueryError::Bind {
+            message: format!(
+                "Invalid value '{}' for {} (expected {})",
+                format_scalar(&Some(value.clone())),
+                name,
+                expected
+            ),
+        };
+        match (name, value) {
+            ("format", ScalarValue::String(s)) if s.eq_ignore_ascii_case("text") => {
+                self.format = OutputFormat::Text
+            }
+            ("format", ScalarValue::String(s)) if s.eq_ignore_ascii_case("json") => {
+                self.format = OutputFormat::Json
+            }
+            ("format", _) => return Err(invalid("text or json")),
+            ("timeout", ScalarValue::Int(ms)) if *ms >= 0 => self.timeout_ms = *ms as u64,
+            ("timeout", _) => return Err(invalid("milliseconds, 0 for none")),
+            ("allow_partial_results", ScalarValue::Bool(b)) => self.allow_partial_results = *b,
+            ("allow_partial_results", _) => return Err(invalid("true or false")),
+            _ => return Err(unknown_setting(name)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<String, QueryError> {
+        Ok(match name {
+            "format" => match self.format {
+                OutputFormat::Text => "text".to_string(),
+                OutputFormat::Json => "json".to_string(),
+            },
+            "timeout" => self.timeout_ms.to_string(),
+            "allow_partial_results" => self.allow_partial_results.to_string(),
+            _ => return Err(unknown_setting(name)),
+        })
+    }
+
+    /// `name | setting` rows for `SHOW`.
+    fn relation(&self, names: &[&str]) -> Result<Relation, QueryError> {
+        let mut rows = Vec::new();
+        for name in names {
+            rows.push(vec![
+                Some(ScalarValue::String(name.to_string())),
+                Some(ScalarValue::String(self.get(name)?)),
+            ]);
+        }
+        Ok(Relation {
+            columns: vec!["name".to_string(), "setting".to_string()],
+            rows,
+        })
+    }
+}
+
+fn unknown_setting(name: &str) -> QueryError {
+    QueryError::Bind {
+        message: format!(
+            "Unknown setting '{}' (settings: {})",
+            name,
+            SETTING_NAMES.join(", ")
+        ),
+    }
+}
+
+/// Settings by session id (the `X-Session-Id` header), forgotten oldest
+/// first.
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: HashMap<String, Settings>,
+    order: VecDeque<String>,
+}
+
+impl SessionStore {
+    pub fn get(&self, id: &str) -> Settings {
+        self.sessions.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn put(&mut self, id: &str, settings: Settings) {
+        if !self.sessions.contains_key(id) {
+            if self.order.len() >= SESSION_CAPACITY
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.sessions.remove(&oldest);
+            }
+            self.order.push_back(id.to_string());
+        }
+        self.sessions.insert(id.to_string(), settings);
+    }
+}
+
+/// Runs the statements of a script in order, stopping at the first error.
+/// Tables are looked up in the catalog of the data root `data`. `SET`
+/// updates `settings` for the statements after it; DDL takes effect
+/// immediately.
+pub async fn run_script(
+    worker_ports: &[u16],
+    data: &str,
+    sql: &str,
+    settings: &mut Settings,
+) -> Result<Vec<QueryResult>, QueryError> {
+    let mut results = Vec::new();
+    run_statements(
+        worker_ports,
+        data,
+        parse_script(sql)?,
+        settings,
+        &mut results,
+    )
+    .await?;
+    Ok(results)
+}
+
+/// Like `run_script` for parsed statements, adding one result per statement
+/// to `results`. After an error they hold the statements that finished (and
+/// stay applied), so the failing one is `statements[results.len()]`.
+pub async fn run_statements(
+    worker_ports: &[u16],
+    data: &str,
+    statements: Vec<Statement>,
+    settings: &mut Settings,
+    results: &mut Vec<QueryResult>,
+) -> Result<(), QueryError> {
+    for statement in statements {
+        let relation = match statement {
+            Statement::Query(mut request) => {
+                resolve_tables(&mut request, data)?;
+                results.push(run_with_settings(worker_ports, *request, settings).await?);
+                continue;
+            }
+            Statement::Set { name, value } => {
+                settings.set(&name, &value)?;
+                settings.relation(&[&name])?
+            }
+            Statement::Show { name: Some(name) } => settings.relation(&[&name])?,
+            Statement::Show { name: None } => settings.relation(&SETTING_NAMES)?,
+            Statement::ShowTables => show_tables(data)?,
+            Statement::Describe { name } => describe(data, &name)?,
+            Statement::CreateTable { name, columns } => {
+                // Worker i serves segment i of every table.
+                create_table(data, &name, &columns, worker_ports.len())?;
+                results.push(command_result("CREATE TABLE"));
+                continue;
+            }
+            Statement::CreateTableAs { name, key, query } => {
+                let rows =
+                    create_table_as(worker_ports, data, &name, key.as_deref(), *query, settings)
+                        .await?;
+                results.push(command_result(&format!("SELECT {}", rows)));
+                continue;
+            }
+            Statement::DropTable { name, if_exists } => {
+                drop_table(data, &name, if_exists)?;
+                results.push(command_result("DROP TABLE"));
+                continue;
+            }
+            Statement::CreateView {
+                name,
+                query,
+                materialized,
+            } => {
+                create_view(worker_ports, data, &name, *query, materialized).await?;
+                results.push(command_result(if materialized {
+                    "CREATE MATERIALIZED VIEW"
+                } else {
+                    "CREATE VIEW"
+                }));
+                continue;
+            }
+            Statement::RefreshView { name } => {
+                // The number of segments aggregated again.
+                let parts = refresh_view(worker_ports, data, &name).await?;
+                results.push(command_result(&format!("REFRESH {}", parts)));
+                continue;
+            }
+            Statement::DropView { name, if_exists } => {
+                drop_view(data, &name, if_exists)?;
+                results.push(command_result("DROP VIEW"));
+                continue;
+            }
+            Statement::AlterTable { name, change } => {
+                alter_table(data, &name, change)?;
+                results.push(command_result("ALTER TABLE"));
+                continue;
+            }
+            Statement::Insert {
+                table,
+                columns,
+                source,
+            } => {
+                let rows = insert(worker_ports, data, &table, &columns, source, settings).await?;
+                results.push(command_result(&format!("INSERT {}", rows)));
+                continue;
+            }
+            Statement::Delete(request) => {
+                let rows = mutate(worker_ports, data, *request, settings).await?;
+                results.push(command_result(&format!("DELETE {}", rows)));
+                continue;
+            }
+            Statement::Update(request) => {
+                let rows = mutate(worker_ports, data, *request, settings).await?;
+                results.push(command_result(&format!("UPDATE {}", rows)));
+                continue;
+            }
+        };
+        results.push(QueryResult {
+            relation,
+            explain: None,
+            message: None,
+            stats: Default::default(),
+        });
+    }
+    Ok(())
+}
+
+/// Runs a resolved query under the session's timeout and partial-result
+/// settings.
+pub async fn run_with_settings(
+    worker_ports: &[u16],
+    request: QueryRequest,
+    settings: &Settings,
+) -> Result<QueryResult, QueryError> {
+    let run = run_query_traced(worker_ports, request, session_trace(settings));
+    with_timeout(settings, run).await
+}
+
+/// Like `run_with_settings`, for a request that is already bound (a
+/// prepared statement with its values).
+pub async fn run_bound_with_settings(
+    worker_ports: &[u16],
+    request: QueryRequest,
+    settings: &Settings,
+) -> Result<QueryResult, QueryError> {
+    let run = run_bound_traced(worker_ports, request, session_trace(settings));
+    with_timeout(settings, run).await
+}
+
+fn session_trace(settings: &Settings) -> QueryTrace {
+    QueryTrace {
+        allow_partial: settings.allow_partial_results,
+        ..QueryTrace::default()
+    }
+}
+
+async fn with_timeout(
+    settings: &Settings,
+    run: impl Future<Output = Result<QueryResult, QueryError>>,
+) -> Result<QueryResult, QueryError> {
+    if settings.timeout_ms == 0 {
+        return run.await;
+    }
+    tokio::time::timeout(Duration::from_millis(settings.timeout_ms), run)
+        .await
+        .map_err(|_| QueryError::Timeout {
+            message: format!("Query timed out after {} ms", settings.timeout_ms),
+        })?
+}
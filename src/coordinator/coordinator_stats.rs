@@ -0,0 +1,199 @@
+use crate::minisql::minisql_plan::Fragments;
+use crate::rpc::PartialAggregate;
+use serde::Serialize;
+
+/// Skip reason of a segment whose worker failed under
+/// `allow_partial_results`.
+pub const WORKER_UNAVAILABLE: &str = "worker unavailable";
+
+/// What happened while running a query: every fragment and the coordinator
+/// operators. Feeds `QueryStats` and `EXPLAIN ANALYZE`.
+#[derive(Debug, Default)]
+pub struct QueryTrace {
+    /// Set before running: a worker that fails after its retry is recorded
+    /// as a skipped segment instead of failing the query.
+    pub allow_partial: bool,
+    /// One per scatter/gather, in execution order (subqueries and CTEs
+    /// before the queries that read them).
+    pub gathers: Vec<GatherTrace>,
+    /// Coordinator operators of the final result.
+    pub operators: Vec<OperatorTrace>,
+}
+
+/// One query fragment sent to every worker (or evaluated over a CTE).
+#[derive(Debug)]
+pub struct GatherTrace {
+    pub query: String,
+    pub cte: Option<String>,  // the CTE read instead of the workers
+    pub view: Option<String>, // the materialized view read instead of most segments
+    pub fragments: Fragments,
+    pub partials: Vec<PartialTrace>,
+    pub rows_out: u64, // rows or groups after merging the partials
+    pub elapsed_us: u64,
+}
+
+/// Timing of one request to a worker, measured on the coordinator.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WorkerCall {
+    pub retries: u64,
+    pub latency_us: u64,   // send to last byte received, retries included
+    pub serialize_us: u64, // encoding the request and decoding the partial
+}
+
+/// Stats of one worker's partial result.
+#[derive(Debug)]
+pub struct PartialTrace {
+    pub worker_port: u16,
+    pub segment: u32,
+    pub rows_scanned: u64,
+    pub rows_matched: u64,
+    pub rows_out: u64,
+    pub bytes_read: u64,
+    pub segments_skipped: u64,
+    pub skip_reason: Option<String>,
+    pub exec_us: u64,
+    pub call: WorkerCall,
+}
+
+impl PartialTrace {
+    pub fn new(p: &PartialAggregate, call: WorkerCall) -> Self {
+        let rows = p.rows.as_ref().map_or(0, |r| r.rows.len());
+        let groups = p.groups.len() + p.grouping_groups.iter().map(|g| g.len()).sum::<usize>();
+        PartialTrace {
+            worker_port: p.worker_port,
+            segment: p.segment,
+            rows_scanned: p.rows_scanned,
+            rows_matched: p.rows_matched,
+            rows_out: (rows + groups) as u64,
+            bytes_read: p.bytes_read,
+            segments_skipped: p.segments_skipped,
+            skip_reason: p.skip_reason.clone(),
+            exec_us: p.exec_us,
+            call,
+        }
+    }
+
+    /// Round trip not spent on the worker or in (de)serialization.
+    pub fn network_us(&self) -> u64 {
+        self.call
+            .latency_us
+            .saturating_sub(self.exec_us + self.call.serialize_us)
+    }
+}
+
+/// Rows into and out of one operator.
+#[derive(Debug)]
+pub struct OperatorTrace {
+    pub name: String,
+    pub rows_in: u64,
+    pub rows_out: u64,
+}
+
+/// Execution statistics returned with a result. Times are in microseconds;
+/// `wall_us` is end to end on the coordinator, the rest are summed over
+/// every request sent to a worker.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct QueryStats {
+    pub wall_us: u64,
+    pub rows_scanned: u64,
+    pub rows_matched: u64,
+    pub bytes_read: u64,
+    pub segments_skipped: u64,
+    pub segments_unavailable: u64, // left out under `allow_partial_results`
+    pub worker_us: u64,
+    pub network_us: u64,
+    pub serialize_us: u64,
+    pub workers: Vec<WorkerStats>,
+}
+
+/// One worker's share of a query. A query sends it one request per
+/// fragment (CTEs, subqueries and set-operation branches add more).
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStats {
+    pub worker_port: u16,
+    pub segment: u32,
+    pub requests: u64,
+    pub rows_scanned: u64,
+    pub rows_matched: u64,
+    pub bytes_read: u64,
+    pub segments_pruned: u64,
+    pub pruned_by: Vec<String>, // skip reason of each pruned request
+    pub retries: u64,
+    pub latency_us: u64,
+    pub exec_us: u64,
+    pub network_us: u64,
+    pub serialize_us: u64,
+}
+
+impl QueryTrace {
+    pub fn stats(&self, wall_us: u64) -> QueryStats {
+        let mut stats = QueryStats {
+            wall_us,
+            ..QueryStats::default()
+        };
+        for gather in &self.gathers {
+            for p in &gather.partials {
+                stats.rows_scanned += p.rows_scanned;
+                stats.rows_matched += p.rows_matched;
+                stats.bytes_read += p.bytes_read;
+                stats.segments_skipped += p.segments_skipped;
+                if p.skip_reason
+                    .as_deref()
+                    .is_some_and(|r| r.starts_with(WORKER_UNAVAILABLE))
+                {
+                    stats.segments_unavailable += 1;
+                }
+                // CTEs and empty plans are evaluated on the coordinator.
+                if gather.cte.is_some() || p.worker_port == 0 {
+                    continue;
+                }
+                stats.worker_us += p.exec_us;
+                stats.network_us += p.network_us();
+                stats.serialize_us += p.call.serialize_us;
+                worker_entry(&mut stats.workers, p).add(p);
+            }
+        }
+        stats
+    }
+}
+
+fn worker_entry<'a>(workers: &'a mut Vec<WorkerStats>, p: &PartialTrace) -> &'a mut WorkerStats {
+    let idx = match workers.iter().position(|w| w.worker_port == p.worker_port) {
+        Some(idx) => idx,
+        None => {
+            workers.push(WorkerStats {
+                worker_port: p.worker_port,
+                segment: p.segment,
+                requests: 0,
+                rows_scanned: 0,
+                rows_matched: 0,
+                bytes_read: 0,
+                segments_pruned: 0,
+                pruned_by: Vec::new(),
+                retries: 0,
+                latency_us: 0,
+                exec_us: 0,
+                network_us: 0,
+                serialize_us: 0,
+            });
+            workers.len() - 1
+        }
+    };
+    &mut workers[idx]
+}
+
+impl WorkerStats {
+    fn add(&mut self, p: &PartialTrace) {
+        self.requests += 1;
+        self.rows_scanned += p.rows_scanned;
+        self.rows_matched += p.rows_matched;
+        self.bytes_read += p.bytes_read;
+        self.segments_pruned += p.segments_skipped;
+        self.pruned_by.extend(p.skip_reason.clone());
+        self.retries += p.call.retries;
+        self.latency_us += p.call.latency_us;
+        self.exec_us += p.exec_us;
+        self.network_us += p.network_us();
+        self.serialize_us += p.call.serialize_us;
+    }
+}
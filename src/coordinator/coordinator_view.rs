@@ -0,0 +1,392 @@
+use super::coordinator_ddl::{new_table_dir, publish_table, unpublish_table};
+use super::coordinator_merge::merge_groups;
+use super::coordinator_relation::relation_partial;
+use super::coordinator_route::{call_worker, resolve_tables};
+use super::coordinator_stats::WorkerCall;
+use crate::minisql::minisql_bind::bind;
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_eval::in_list_values;
+use crate::minisql::minisql_parse::parse_sql;
+use crate::minisql::minisql_plan::{derive_group_by, plan_query};
+use crate::rpc::{
+    AggregateExpr, AggregateFn, FilterExpr, GroupMap, PartialAggregate, Predicate, QueryRequest,
+    Relation, ScalarValue,
+};
+use crate::storage::storage_catalog::{catalog_names, catalog_version, is_registered};
+use crate::storage::storage_view::{
+    StoredPart, ViewPartials, is_materialized, is_view, read_partials, read_view_query,
+    table_parts, write_partials, write_view_query,
+};
+use crate::worker::worker_exec::load_schema;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Materialized views of a data root by name, bound, in catalog order.
+type BoundViews = Arc<Vec<(String, QueryRequest)>>;
+
+/// `BoundViews` by data root, with the catalog version they were bound at;
+/// any change to the catalog binds them again (see `materialized_views`).
+static MATERIALIZED: LazyLock<Mutex<HashMap<PathBuf, (u64, BoundViews)>>> =
+    LazyLock::new(Mutex::default);
+
+/// Directory of view `name` if the catalog of `data` has such a view.
+pub fn view_dir(data: &str, name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return None;
+    }
+    let dir = Path::new(data).join(name);
+    (is_view(&dir) && is_registered(Path::new(data), name)).then_some(dir)
+}
+
+/// The stored query of a view, parsed but not resolved.
+pub fn view_query(dir: &Path) -> Result<QueryRequest, QueryError> {
+    let invalid = |e: String| QueryError::Execution {
+        message: format!("Invalid view {:?}: {}", dir, e),
+    };
+    let sql = read_view_query(dir).map_err(invalid)?;
+    parse_sql(&sql).map_err(|e| invalid(e.to_string()))
+}
+
+/// A view's query, resolved against the catalog of `data` and bound.
+fn bound_view(dir: &Path, data: &str) -> Result<QueryRequest, QueryError> {
+    let mut request = view_query(dir)?;
+    resolve_tables(&mut request, data)?;
+    bind(&mut request)?;
+    Ok(request)
+}
+
+/// `CREATE [MATERIALIZED] VIEW`: checks the query and stores its text. A
+/// materialized view also aggregates every segment of its table and stores
+/// the groups per segment (see `refresh_parts`).
+pub async fn create_view(
+    worker_ports: &[u16],
+    data: &str,
+    name: &str,
+    request: QueryRequest,
+    materialized: bool,
+) -> Result<(), QueryError> {
+    let dir = new_table_dir(data, name)?;
+    let text = request.query.clone();
+    let mut bound = request;
+    resolve_tables(&mut bound, data)?;
+    bind(&mut bound)?;
+    if !materialized {
+        return publish_table(&dir, name, |tmp| write_view_query(tmp, &text));
+    }
+    check_materializable(&bound)?;
+    let (partials, _) = refresh_parts(worker_ports, &bound, ViewPartials::default(), false).await?;
+    publish_table(&dir, name, |tmp| {
+        write_view_query(tmp, &text).and_then(|_| write_partials(tmp, &partials))
+    })
+}
+
+/// `REFRESH MATERIALIZED VIEW`: aggregates the segments written or changed
+/// since the last refresh and stores their groups. Returns the number of
+/// segments aggregated.
+pub async fn refresh_view(
+    worker_ports: &[u16],
+    data: &str,
+    name: &str,
+) -> Result<usize, QueryError> {
+    let dir = view_dir(data, name)
+        .filter(|d| is_materialized(d))
+        .ok_or_else(|| QueryError::NotFound {
+            message: format!("Materialized view '{}' does not exist", name),
+        })?;
+    let view = bound_view(&dir, data)?;
+    check_materializable(&view)?;
+    let execution = |message| QueryError::Execution { message };
+    let stored = read_partials(&dir).map_err(execution)?;
+    let (partials, scans) = refresh_parts(worker_ports, &view, stored, false).await?;
+    write_partials(&dir, &partials).map_err(execution)?;
+    Ok(scans.len())
+}
+
+/// `DROP [MATERIALIZED] VIEW`, removed like a table (see `unpublish_table`).
+pub fn drop_view(data: &str, name: &str, if_exists: bool) -> Result<(), QueryError> {
+    let Some(dir) = view_dir(data, name) else {
+        if if_exists {
+            return Ok(());
+        }
+        return Err(QueryError::NotFound {
+            message: format!("View '{}' does not exist", name),
+        });
+    };
+    unpublish_table(&dir, name)
+}
+
+/// A materialized view keeps the groups of every segment, to be merged when
+/// it is read: an aggregate over one table, without joins, windows,
+/// grouping sets or subqueries.
+fn check_materializable(request: &QueryRequest) -> Result<(), QueryError> {
+    let subquery = request
+        .filters
+        .iter()
+        .chain(request.aggregates.iter().flat_map(|a| &a.filters))
+        .any(|f| f.subquery.is_some());
+    let problem =
+        if !request.ctes.is_empty() || !Path::new(&request.table).join("_schema.ssf").is_file() {
+            "must read a table"
+        } else if request.aggregates.is_empty() {
+            "needs an aggregate"
+        } else if request.join.is_some() {
+            "cannot use JOIN"
+        } else if !request.windows.is_empty() {
+            "cannot use window functions"
+        } else if !request.grouping_sets.is_empty() {
+            "cannot use ROLLUP, CUBE or GROUPING SETS"
+        } else if subquery {
+            "cannot use subqueries"
+        } else {
+            return Ok(());
+        };
+    Err(QueryError::Bind {
+        message: format!("A materialized view {}", problem),
+    })
+}
+
+/// Brings stored partials up to date with the view's table: segments that
+/// are new or changed (by fingerprint, see `PartFingerprint`) are
+/// aggregated again, one request each to the worker of their base
+/// segment; segments that are gone are dropped. Also returns the partials
+/// of those requests, by segment number.
+async fn refresh_parts(
+    worker_ports: &[u16],
+    view: &QueryRequest,
+    stored: ViewPartials,
+    allow_partial: bool,
+) -> anyhow::Result<(ViewPartials, Vec<(u32, PartialAggregate, WorkerCall)>)> {
+    let key = load_schema(&view.table)
+        .into_iter()
+        .find(|c| c.is_key)
+        .ok_or_else(|| anyhow::anyhow!("Table {:?} has no key column", view.table))?;
+    let (schema_version, parts) =
+        table_parts(Path::new(&view.table), &key).map_err(|e| anyhow::anyhow!(e))?;
+    // Groups taken under another schema may read columns that changed.
+    let mut kept: HashMap<u32, StoredPart> = if stored.schema_version == schema_version {
+        stored.parts.into_iter().map(|p| (p.segment, p)).collect()
+    } else {
+        HashMap::new()
+    };
+    let planned = plan_query(view).worker_request(view);
+
+    let mut fresh = ViewPartials {
+        schema_version,
+        parts: Vec::with_capacity(parts.len()),
+    };
+    let mut scans = Vec::new();
+    for part in parts {
+        if let Some(stored) = kept
+            .remove(&part.segment)
+            .filter(|p| p.fingerprint == part.fingerprint)
+        {
+            fresh.parts.push(stored);
+            continue;
+        }
+        let groups = match &planned {
+            // The view's filters contradict each other.
+            None => GroupMap::new(),
+            Some(planned) => {
+                let port = worker_ports
+                    .get(part.base as usize)
+                    .ok_or_else(|| anyhow::anyhow!("No worker serves segment {}", part.base))?;
+                let mut request = planned.clone();
+                request.parts = Some(vec![part.segment]);
+                let (partial, call) =
+                    call_worker(*port, part.base as usize, &request, allow_partial).await?;
+                let groups = partial.groups.clone();
+                scans.push((part.segment, partial, call));
+                groups
+            }
+        };
+        fresh.parts.push(StoredPart {
+            segment: part.segment,
+            fingerprint: part.fingerprint,
+            groups,
+        });
+    }
+    Ok((fresh, scans))
+}
+
+/// How a query reads the groups of a view: the position in the view's
+/// GROUP BY of each of the query's group columns, and the view aggregate
+/// (by output name) holding each of the query's aggregates.
+struct ViewMatch {
+    group_idx: Vec<usize>,
+    aggregates: Vec<(String, String)>,
+}
+
+/// A query can be answered from a view of the same table with the same
+/// filters whose GROUP BY covers the query's and whose aggregates keep the
+/// states the query needs.
+fn match_view(view: &QueryRequest, request: &QueryRequest) -> Option<ViewMatch> {
+    if Path::new(&view.table) != Path::new(&request.table)
+        || !same_filters(&view.filters, &request.filters)
+    {
+        return None;
+    }
+    let view_groups = derive_group_by(view);
+    let group_idx = derive_group_by(request)
+        .iter()
+        .map(|g| view_groups.iter().position(|v| v == g))
+        .collect::<Option<Vec<_>>>()?;
+    let aggregates = request
+        .aggregates
+        .iter()
+        .map(|agg| {
+            view.aggregates
+                .iter()
+                .find(|v| same_state(v, agg))
+                .map(|v| (agg.output_name.clone(), v.output_name.clone()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(ViewMatch {
+        group_idx,
+        aggregates,
+    })
+}
+
+/// Whether two aggregates accumulate the same state; SUM and AVG both keep
+/// the sum and count of their column.
+fn same_state(a: &AggregateExpr, b: &AggregateExpr) -> bool {
+    let state = |f: AggregateFn| match f {
+        AggregateFn::Avg => AggregateFn::Sum,
+        f => f,
+    };
+    state(a.func) == state(b.func) && a.column == b.column && same_filters(&a.filters, &b.filters)
+}
+
+/// What a filter keeps, equal for filters that keep the same rows however
+/// they were written or shipped: values are `normalized`, and an IN list
+/// is a set whether it is still a list or already hashed.
+#[derive(PartialEq)]
+struct Condition<'a> {
+    column: &'a str,
+    pred: Predicate,
+    value: Option<ScalarValue>, // None for IN
+    value_hi: Option<ScalarValue>,
+    list: Option<HashSet<ScalarValue>>,
+}
+
+fn condition(f: &FilterExpr) -> Condition<'_> {
+    Condition {
+        column: &f.column,
+        pred: f.pred,
+        value: (f.pred != Predicate::In).then(|| f.value.normalized()),
+        value_hi: f.value_hi.as_ref().map(ScalarValue::normalized),
+        list: f.list.as_ref().map(|l| {
+            in_list_values(l)
+                .into_iter()
+                .map(|v| v.normalized())
+                .collect()
+        }),
+    }
+}
+
+/// Whether two lists of filters hold the same conditions, in any order.
+/// Filters with an unresolved subquery never match.
+fn same_filters(a: &[FilterExpr], b: &[FilterExpr]) -> bool {
+    if a.iter().chain(b).any(|f| f.subquery.is_some()) {
+        return false;
+    }
+    let a: Vec<Condition> = a.iter().map(condition).collect();
+    let b: Vec<Condition> = b.iter().map(condition).collect();
+    a.len() == b.len() && a.iter().all(|c| b.contains(c)) && b.iter().all(|c| a.contains(c))
+}
+
+/// Groups of a view as the query groups them, merging the view groups
+/// that only differ in columns the query does not group by.
+fn regroup(groups: &GroupMap, m: &ViewMatch) -> GroupMap {
+    let mut out = GroupMap::new();
+    for (key, states) in groups {
+        let key = m.group_idx.iter().map(|&i| key[i].clone()).collect();
+        let mut renamed = HashMap::new();
+        for (name, view_name) in &m.aggregates {
+            if let Some(state) = states.get(view_name) {
+                renamed.insert(name.clone(), state.clone());
+            }
+        }
+        merge_groups(&mut out, &GroupMap::from([(key, renamed)]));
+    }
+    out
+}
+
+/// Answers an aggregate query from the first materialized view (by name)
+/// that matches it (see `match_view`). Segments changed since the view was
+/// refreshed are aggregated again, but not stored; the others are read from
+/// the view, as one partial computed on the coordinator. `None` if no view
+/// matches.
+pub async fn answer_from_view(
+    worker_ports: &[u16],
+    request: &QueryRequest,
+    allow_partial: bool,
+) -> anyhow::Result<Option<(String, Vec<(PartialAggregate, WorkerCall)>)>> {
+    if request.aggregates.is_empty()
+        || request.join.is_some()
+        || !request.windows.is_empty()
+        || !request.grouping_sets.is_empty()
+        || request.mutation.is_some()
+        || request.parts.is_some()
+    {
+        return Ok(None);
+    }
+    // Tables and views share the data root.
+    let root = Path::new(&request.table)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    for (name, view) in materialized_views(root).iter() {
+        let Some(m) = match_view(view, request) else {
+            continue;
+        };
+        let stored = read_partials(&root.join(name)).map_err(|e| anyhow::anyhow!(e))?;
+        let (fresh, scans) = refresh_parts(worker_ports, view, stored, allow_partial).await?;
+
+        let mut from_view = relation_partial(&Relation::default(), request);
+        from_view.rows_scanned = 0;
+        for part in &fresh.parts {
+            if !scans.iter().any(|(segment, ..)| *segment == part.segment) {
+                merge_groups(&mut from_view.groups, &regroup(&part.groups, &m));
+            }
+        }
+        let mut calls = vec![(from_view, WorkerCall::default())];
+        for (_, mut partial, call) in scans {
+            partial.groups = regroup(&partial.groups, &m);
+            calls.push((partial, call));
+        }
+        return Ok(Some((name.clone(), calls)));
+    }
+    Ok(None)
+}
+
+/// The materialized views of `root`, bound once per catalog version rather
+/// than on every aggregate query. Views that no longer bind, e.g. after
+/// ALTER TABLE, are left out.
+fn materialized_views(root: &Path) -> BoundViews {
+    // Read first: a change while binding leaves the entry stale.
+    let version = catalog_version(root);
+    if let Some((bound_at, views)) = MATERIALIZED.lock().unwrap().get(root)
+        && *bound_at == version
+    {
+        return views.clone();
+    }
+    let data = root.display().to_string();
+    let views: BoundViews = Arc::new(
+        catalog_names(root)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| is_materialized(&root.join(name)))
+            .filter_map(|name| {
+                let view = bound_view(&root.join(&name), &data).ok()?;
+                Some((name, view))
+            })
+            .collect(),
+    );
+    MATERIALIZED
+        .lock()
+        .unwrap()
+        .insert(root.to_path_buf(), (version, views.clone()));
+    views
+}
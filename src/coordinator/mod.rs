@@ -1,4 +1,13 @@
+pub mod coordinator_catalog;
 pub mod coordinator_cluster;
+pub mod coordinator_ddl;
+pub mod coordinator_dml;
+pub mod coordinator_explain;
 pub mod coordinator_merge;
+pub mod coordinator_prepare;
+pub mod coordinator_relation;
 pub mod coordinator_route;
 pub mod coordinator_server;
+pub mod coordinator_session;
+pub mod coordinator_stats;
+pub mod coordinator_view;
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::minisql::minisql_error::QueryError;
+use crate::minisql::minisql_params::param_count;
+use crate::minisql::minisql_print::{grouping_column_name, normalize_header};
+use crate::minisql::minisql_window::window_column_name;
+use crate::rpc::{
+    AggregateFn, FilterExpr, InList, ParamSlot, Predicate, QueryRequest, ScalarValue,
+};
+use crate::storage::storage_schema::{ColumnType, parse_schema_file};
+
+/// Columns a query can refer to, with their types where known (columns
+/// computed by a CTE, like aggregates, carry none).
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub table: String,
+    pub columns: Vec<(String, Option<ColumnType>)>,
+}
+
+impl Scope {
+    fn lookup(&self, name: &str) -> Option<&Option<ColumnType>> {
+        self.columns.iter().find(|(c, _)| c == name).map(|(_, t)| t)
+    }
+
+    fn names(&self) -> String {
+        self.columns
+            .iter()
+            .map(|(c, _)| c.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Checks a parsed query against the schemas of the tables it reads, after
+/// `resolve_tables`: every column must exist, literals must suit the column
+/// they are compared with (date and timestamp strings are converted to the
+/// stored day/millisecond numbers), and bare columns next to aggregates must
+/// be grouped.
+pub fn bind(req: &mut QueryRequest) -> Result<(), QueryError> {
+    let unbound = param_count(req);
+    if unbound > 0 {
+        return Err(QueryError::Bind {
+            message: format!(
+                "Query has {} unbound parameters; prepare it and execute it with values",
+                unbound
+            ),
+        });
+    }
+    bind_prepared(req)
+}
+
+/// Like `bind`, for a statement being prepared: placeholders are left for
+/// `bind_params`, and their values are checked when it is executed.
+pub fn bind_prepared(req: &mut QueryRequest) -> Result<(), QueryError> {
+    bind_all(req).map_err(|message| QueryError::Bind { message })
+}
+
+fn bind_all(req: &mut QueryRequest) -> Result<(), String> {
+    let mut ctes: HashMap<String, Scope> = HashMap::new();
+    for cte in req.ctes.iter_mut() {
+        let columns = match &cte.rows {
+            Some(rows) => rows.columns.iter().map(|c| (c.clone(), None)).collect(),
+            None => bind_select(&mut cte.query, &ctes)?.columns,
+        };
+        ctes.insert(
+            cte.name.clone(),
+            Scope {
+                table: cte.name.clone(),
+                columns,
+            },
+        );
+    }
+    bind_select(req, &ctes)?;
+    for branch in req.set_ops.iter_mut() {
+        bind_select(&mut branch.query, &ctes)?;
+    }
+    Ok(())
+}
+
+/// Binds one SELECT and returns the scope of its result columns.
+fn bind_select(req: &mut QueryRequest, ctes: &HashMap<String, Scope>) -> Result<Scope, String> {
+    let scope = input_scope(req, ctes)?;
+    let check = |name: &str, clause: &str| -> Result<Option<ColumnType>, String> {
+        scope.lookup(name).cloned().ok_or_else(|| {
+            format!(
+                "Unknown column '{}' in {} (table '{}' has: {})",
+                name,
+                clause,
+                scope.table,
+                scope.names()
+            )
+        })
+    };
+
+    for p in &req.projections {
+        if p != "*" {
+            check(p, "SELECT")?;
+        }
+    }
+    for g in &req.group_by {
+        check(g, "GROUP BY")?;
+    }
+    for agg in req.aggregates.iter_mut() {
+        if let Some(col) = &agg.column {
+            let ty = check(col, "SELECT")?;
+            if agg.func != AggregateFn::Count
+                && let Some(ty) = &ty
+                && !is_numeric(ty)
+            {
+                return Err(format!(
+                    "{} needs a numeric column, '{}' is {}",
+                    format!("{:?}", agg.func).to_ascii_uppercase(),
+                    col,
+                    type_name(ty)
+                ));
+            }
+        }
+        bind_filters(&mut agg.filters, &scope, "FILTER", ctes)?;
+    }
+    bind_filters(&mut req.filters, &scope, "WHERE", ctes)?;
+    for w in &req.windows {
+        if let Some(col) = &w.column {
+            check(col, "window function")?;
+        }
+        for col in &w.partition_by {
+            check(col, "PARTITION BY")?;
+        }
+        for o in &w.order_by {
+            check(&o.column, "ORDER BY")?;
+        }
+    }
+
+    if !req.aggregates.is_empty() || !req.group_by.is_empty() {
+        for p in &req.projections {
+            if p == "*" {
+                return Err("SELECT * cannot be combined with aggregates or GROUP BY".into());
+            }
+            if !req.group_by.contains(p) {
+                return Err(format!(
+                    "Column '{}' must appear in GROUP BY or be used in an aggregate",
+                    p
+                ));
+            }
+        }
+    }
+
+    let output = output_scope(req, &scope);
+    // Sorting happens on the result, so ORDER BY names its columns (or the
+    // aggregates they came from).
+    for o in &req.order_by {
+        let found = output.lookup(&o.column).is_some()
+            || output.lookup(&normalize_header(&o.column)).is_some()
+            || req.aggregates.iter().any(|a| a.output_name == o.column);
+        if !found {
+            return Err(format!(
+                "Unknown column '{}' in ORDER BY (result has: {})",
+                o.column,
+                output.names()
+            ));
+        }
+    }
+    Ok(output)
+}
+
+/// Columns of the FROM clause: a CTE's result, or the table's schema (for a
+/// join, every column as `table.column` and, unless shadowed by the left
+/// table, by its bare name).
+fn input_scope(req: &QueryRequest, ctes: &HashMap<String, Scope>) -> Result<Scope, String> {
+    if let Some(scope) = ctes.get(&req.table) {
+        return Ok(scope.clone());
+    }
+    let dir = Path::new(&req.table);
+    let table = table_name(dir);
+    let mut scope = Scope {
+        table: table.clone(),
+        columns: Vec::new(),
+    };
+    let Some(join) = &req.join else {
+        scope.columns = table_columns(dir)?;
+        return Ok(scope);
+    };
+
+    let right_dir = Path::new(&join.right_table);
+    let left = table_columns(dir)?;
+    let right = table_columns(right_dir)?;
+    for (name, ty) in &left {
+        scope
+            .columns
+            .push((format!("{}.{}", join.left_name, name), ty.clone()));
+        scope.columns.push((name.clone(), ty.clone()));
+    }
+    for (name, ty) in &right {
+        scope
+            .columns
+            .push((format!("{}.{}", join.right_name, name), ty.clone()));
+        if !left.iter().any(|(c, _)| c == name) {
+            scope.columns.push((name.clone(), ty.clone()));
+        }
+    }
+    scope.table = format!("{} JOIN {}", join.left_name, join.right_name);
+    Ok(scope)
+}
+
+fn table_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| dir.display().to_string())
+}
+
+fn table_columns(dir: &Path) -> Result<Vec<(String, Option<ColumnType>)>, String> {
+    let contents = std::fs::read_to_string(dir.join("_schema.ssf"))
+        .map_err(|_| format!("Table '{}' not found", table_name(dir)))?;
+    let defs = parse_schema_file(&contents)
+        .map_err(|e| format!("Bad schema for table '{}': {}", table_name(dir), e))?;
+    Ok(defs
+        .into_iter()
+        .map(|d| (d.name, Some(d.col_type)))
+        .collect())
+}
+
+/// Result columns of a bound SELECT, named as `project_output` produces them.
+fn output_scope(req: &QueryRequest, input: &Scope) -> Scope {
+    let mut columns: Vec<(String, Option<ColumnType>)> = Vec::new();
+    let typed = |name: &str| input.lookup(name).cloned().flatten();
+    for p in &req.projections {
+        if p == "*" {
+            columns.push((normalize_header("COUNT(*)"), Some(ColumnType::Int64)));
+        } else {
+            columns.push((p.clone(), typed(p)));
+        }
+    }
+    for agg in &req.aggregates {
+        let name = agg
+            .alias
+            .clone()
+            .unwrap_or_else(|| normalize_header(&agg.output_name));
+        let ty = match agg.func {
+            AggregateFn::Count => Some(ColumnType::Int64),
+            AggregateFn::Avg => Some(ColumnType::Float64),
+            _ => None,
+        };
+        columns.push((name, ty));
+    }
+    for g in &req.grouping {
+        columns.push((grouping_column_name(g), Some(ColumnType::Int64)));
+    }
+    for w in &req.windows {
+        columns.push((window_column_name(w), None));
+    }
+
+    // Keep SELECT order where the parser recorded it.
+    if !req.output.is_empty() && !req.output.iter().any(|c| c == "*") {
+        columns = req
+            .output
+            .iter()
+            .map(|name| {
+                let ty = columns
+                    .iter()
+                    .find(|(c, _)| c == name)
+                    .and_then(|(_, t)| t.clone());
+                (name.clone(), ty)
+            })
+            .collect();
+    }
+    Scope {
+        table: input.table.clone(),
+        columns,
+    }
+}
+
+fn bind_filters(
+    filters: &mut [FilterExpr],
+    scope: &Scope,
+    clause: &str,
+    ctes: &HashMap<String, Scope>,
+) -> Result<(), String> {
+    for f in filters.iter_mut() {
+        let Some(ty) = scope.lookup(&f.column).cloned() else {
+            return Err(format!(
+                "Unknown column '{}' in {} (table '{}' has: {})",
+                f.column,
+                clause,
+                scope.table,
+                scope.names()
+            ));
+        };
+        if let Some(sub) = f.subquery.as_mut() {
+            bind_select(sub, ctes)?;
+            continue;
+        }
+        let Some(ty) = ty else { continue };
+        for p in f.params.iter_mut() {
+            p.column_type = Some(ty.clone());
+        }
+        let is_param = |slot: ParamSlot| f.params.iter().any(|p| p.slot == slot);
+        // IN keeps its values in `list`; `value` is a placeholder.
+        if !matches!(f.pred, Predicate::In) && !is_param(ParamSlot::Value) {
+            f.value = coerce(&f.value, &ty, &f.column)?;
+        }
+        if let Some(hi) = &f.value_hi
+            && !is_param(ParamSlot::ValueHi)
+        {
+            f.value_hi = Some(coerce(hi, &ty, &f.column)?);
+        }
+        if let Some(InList::Values(values)) = &f.list {
+            let values = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| match is_param(ParamSlot::ListItem(i)) {
+                    true => Ok(v.clone()),
+                    false => coerce(v, &ty, &f.column),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            f.list = Some(InList::Values(values));
+        }
+    }
+    Ok(())
+}
+
+/// Converts a literal to the representation of `ty`, or explains why it
+/// cannot be compared with the column.
+pub fn coerce(value: &ScalarValue, ty: &ColumnType, column: &str) -> Result<ScalarValue, String> {
+    convert(value, ty, column)?.ok_or_else(|| {
+        format!(
+            "Cannot compare column '{}' ({}) with {}",
+            column,
+            type_name(ty),
+            describe(value)
+        )
+    })
+}
+
+/// Converts a value to what a column of type `ty` stores (`INSERT`):
+/// integers widen to floats, nothing narrows.
+pub fn store_value(
+    value: &ScalarValue,
+    ty: &ColumnType,
+    column: &str,
+) -> Result<ScalarValue, String> {
+    let mismatch = || {
+        format!(
+            "Cannot store {} in column '{}' ({})",
+            describe(value),
+            column,
+            type_name(ty)
+        )
+    };
+    match (ty, convert(value, ty, column)?.ok_or_else(mismatch)?) {
+        (ColumnType::Float64, ScalarValue::Int(i)) => Ok(ScalarValue::Float(i as f64)),
+        (ColumnType::Float64, v) => Ok(v),
+        (_, ScalarValue::Float(_)) => Err(mismatch()),
+        (ColumnType::Int32 | ColumnType::Date, ScalarValue::Int(i))
+            if i32::try_from(i).is_err() =>
+        {
+            Err(format!(
+                "Value {} is out of range for column '{}' ({})",
+                i,
+                column,
+                type_name(ty)
+            ))
+        }
+        (_, v) => Ok(v),
+    }
+}
+
+/// `value` in the representation of `ty`, or `None` if the two do not mix.
+fn convert(
+    value: &ScalarValue,
+    ty: &ColumnType,
+    column: &str,
+) -> Result<Option<ScalarValue>, String> {
+    let ok = match (ty, value) {
+        (ColumnType::Int32 | ColumnType::Int64 | ColumnType::Float64, ScalarValue::Int(_))
+        | (ColumnType::Int32 | ColumnType::Int64 | ColumnType::Float64, ScalarValue::Float(_))
+        | (ColumnType::Bool, ScalarValue::Bool(_))
+        | (ColumnType::String, ScalarValue::String(_))
+        | (ColumnType::Date | ColumnType::TimestampMs, ScalarValue::Int(_)) => true,
+        (ColumnType::Date, ScalarValue::String(s)) => {
+            return parse_date(s)
+                .map(|d| Some(ScalarValue::Int(d)))
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid date '{}' for column '{}' (expected YYYY-MM-DD)",
+                        s, column
+                    )
+                });
+        }
+        (ColumnType::TimestampMs, ScalarValue::String(s)) => {
+            return parse_timestamp(s)
+                .map(|t| Some(ScalarValue::Int(t)))
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid timestamp '{}' for column '{}' (expected YYYY-MM-DD[ HH:MM:SS])",
+                        s, column
+                    )
+                });
+        }
+        _ => false,
+    };
+    Ok(ok.then(|| value.clone()))
+}
+
+/// Days since 1970-01-01, as the loader stores dates.
+fn parse_date(s: &str) -> Option<i64> {
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1)?;
+    Some((date - epoch).num_days())
+}
+
+/// Milliseconds since the epoch; a bare date means midnight.
+fn parse_timestamp(s: &str) -> Option<i64> {
+    for fmt in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(ts) = chrono::NaiveDateTime::parse_from_str(s, fmt) {
+            return Some(ts.and_utc().timestamp_millis());
+        }
+    }
+    parse_date(s).map(|days| days * 86_400_000)
+}
+
+/// Types SUM/AVG/MIN/MAX accept.
+fn is_numeric(ty: &ColumnType) -> bool {
+    matches!(
+        ty,
+        ColumnType::Int32 | ColumnType::Int64 | ColumnType::Float64
+    )
+}
+
+/// Type name as written in `_schema.ssf`.
+pub fn type_name(ty: &ColumnType) -> &'static str {
+    match ty {
+        ColumnType::Int32 => "int32",
+        ColumnType::Int64 => "int64",
+        ColumnType::Float64 => "float64",
+        ColumnType::Bool => "bool",
+        ColumnType::String => "string",
+        ColumnType::Date => "date",
+        ColumnType::TimestampMs => "timestamp(ms)",
+    }
+}
+
+fn describe(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Int(i) => i.to_string(),
+        ScalarValue::Float(f) => f.to_string(),
+        ScalarValue::String(s) => format!("'{}'", s),
+        ScalarValue::Bool(b) => b.to_string(),
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+
+/// 1-based position in the SQL text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Why a query failed. `/query` answers with the matching HTTP status and
+/// this value as JSON (`{"kind": "parse", "message": ..., ...}`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueryError {
+    /// The text is not valid MiniSQL.
+    Parse {
+        message: String,
+        start: Option<Position>,
+        end: Option<Position>,
+    },
+    /// The query names unknown columns or mixes incompatible types.
+    Bind { message: String },
+    /// The query was valid but could not be evaluated.
+    Execution { message: String },
+    /// A worker did not answer, even after a retry.
+    WorkerUnavailable { message: String, worker_port: u16 },
+    /// The request names a prepared statement that is not cached.
+    NotFound { message: String },
+    /// The query ran longer than the session's `timeout`.
+    Timeout { message: String },
+}
+
+impl QueryError {
+    /// A parse error pointing at `span` of the SQL text.
+    pub fn parse_at(span: pest::Span, message: impl Into<String>) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        let (end_line, end_column) = span.end_pos().line_col();
+        QueryError::Parse {
+            message: message.into(),
+            start: Some(Position { line, column }),
+            end: Some(Position {
+                line: end_line,
+                column: end_column,
+            }),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            QueryError::Parse { message, .. }
+            | QueryError::Bind { message }
+            | QueryError::Execution { message }
+            | QueryError::WorkerUnavailable { message, .. }
+            | QueryError::NotFound { message }
+            | QueryError::Timeout { message } => message,
+        }
+    }
+
+    pub fn message_mut(&mut self) -> &mut String {
+        match self {
+            QueryError::Parse { message, .. }
+            | QueryError::Bind { message }
+            | QueryError::Execution { message }
+            | QueryError::WorkerUnavailable { message, .. }
+            | QueryError::NotFound { message }
+            | QueryError::Timeout { message } => message,
+        }
+    }
+
+    /// HTTP status for `/query`.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            QueryError::Parse { .. } => 400,
+            QueryError::Bind { .. } => 422,
+            QueryError::Execution { .. } => 500,
+            QueryError::WorkerUnavailable { .. } => 503,
+            QueryError::NotFound { .. } => 404,
+            QueryError::Timeout { .. } => 504,
+        }
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Parse {
+                message,
+                start: Some(pos),
+                ..
+            } => write!(f, "{} (line {}, column {})", message, pos.line, pos.column),
+            other => f.write_str(other.message()),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<anyhow::Error> for QueryError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<QueryError>() {
+            Ok(e) => e,
+            Err(e) => QueryError::Execution {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+impl<R: pest::RuleType> From<pest::error::Error<R>> for QueryError {
+    fn from(e: pest::error::Error<R>) -> Self {
+        let (start, end) = match e.line_col {
+            pest::error::LineColLocation::Pos((line, column)) => ((line, column), None),
+            pest::error::LineColLocation::Span(start, end) => (start, Some(end)),
+        };
+        QueryError::Parse {
+            message: e.variant.message().to_string(),
+            start: Some(Position {
+                line: start.0,
+                column: start.1,
+            }),
+            end: end.map(|(line, column)| Position { line, column }),
+        }
+    }
+}
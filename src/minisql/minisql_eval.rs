@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
 
 use crate::rpc::{
-    AggregateExpr, AggregateFn, AggregateState, FilterExpr, Predicate, ScalarValue, ValueType,
+    AggregateExpr, AggregateFn, AggregateState, FilterExpr, InList, Predicate, ScalarValue,
+    ValueType,
 };
 use crate::storage::storage_schema::{ColumnDef, ColumnType};
 
@@ -26,6 +27,10 @@ pub fn eval_filter(val: Option<ScalarValue>, f: &FilterExpr) -> bool {
     let Some(v) = val else {
         return false;
     };
+    if f.subquery.is_some() {
+        // Unresolved subqueries never match; the coordinator inlines them.
+        return false;
+    }
     match f.pred {
         Predicate::Eq => cmp_eq(&v, &f.value),
         Predicate::Lt => cmp_order(&v, &f.value, |o| o.is_lt()),
@@ -39,6 +44,19 @@ pub fn eval_filter(val: Option<ScalarValue>, f: &FilterExpr) -> bool {
                 false
             }
         }
+        Predicate::In => match &f.list {
+            Some(InList::Values(values)) => values.iter().any(|x| cmp_eq(&v, x)),
+            Some(InList::Hashed(set)) => set.contains(&v.normalized()),
+            None => false,
+        },
+    }
+}
+
+/// Values of an `IN` list, regardless of how it was shipped.
+pub fn in_list_values(list: &InList) -> Vec<&ScalarValue> {
+    match list {
+        InList::Values(values) => values.iter().collect(),
+        InList::Hashed(set) => set.iter().collect(),
     }
 }
 
@@ -47,6 +65,9 @@ pub fn apply_agg(
     expr: &AggregateExpr,
     row: &HashMap<String, Option<ScalarValue>>,
 ) {
+    if !row_matches(&expr.filters, row) {
+        return;
+    }
     match expr.func {
         AggregateFn::Count => {
             if let Some(col) = &expr.column {
@@ -161,6 +182,44 @@ where
     }
 }
 
+pub fn compare_scalar(a: &ScalarValue, b: &ScalarValue) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (ScalarValue::Int(x), ScalarValue::Int(y)) => Some(x.cmp(y)),
+        (ScalarValue::Float(x), ScalarValue::Float(y)) => x.partial_cmp(y),
+        (ScalarValue::Int(x), ScalarValue::Float(y)) => (*x as f64).partial_cmp(y),
+        (ScalarValue::Float(x), ScalarValue::Int(y)) => x.partial_cmp(&(*y as f64)),
+        (ScalarValue::String(x), ScalarValue::String(y)) => Some(x.cmp(y)),
+        (ScalarValue::Bool(x), ScalarValue::Bool(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+/// Sort order for possibly-NULL values; NULLs sort last in either direction.
+pub fn compare_nullable(
+    a: &Option<ScalarValue>,
+    b: &Option<ScalarValue>,
+    descending: bool,
+) -> Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => {
+            let ord = compare_scalar(x, y).unwrap_or(Ordering::Equal);
+            if descending { ord.reverse() } else { ord }
+        }
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+    }
+}
+
+/// Ascending order of group keys, column by column.
+pub fn compare_group_keys(a: &[Option<ScalarValue>], b: &[Option<ScalarValue>]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| compare_nullable(x, y, false))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
 pub fn as_f64(v: &ScalarValue) -> Option<f64> {
     match v {
         ScalarValue::Int(i) => Some(*i as f64),
@@ -180,6 +239,23 @@ pub fn format_scalar(v: &Option<ScalarValue>) -> String {
     }
 }
 
+/// Final value of an aggregate, as rendered by the printer.
+pub fn finalize_state(func: AggregateFn, state: &AggregateState) -> Option<ScalarValue> {
+    let typed = |v: f64| match state.value_type {
+        ValueType::Int => ScalarValue::Int(v as i64),
+        ValueType::Float => ScalarValue::Float(v),
+    };
+    match func {
+        AggregateFn::Count => Some(ScalarValue::Int(state.count as i64)),
+        AggregateFn::Sum if state.count == 0 => None,
+        AggregateFn::Sum => Some(typed(state.sum)),
+        AggregateFn::Avg if state.count == 0 => None,
+        AggregateFn::Avg => Some(ScalarValue::Float(state.sum / state.count as f64)),
+        AggregateFn::Min => state.min.map(typed),
+        AggregateFn::Max => state.max.map(typed),
+    }
+}
+
 pub enum ReadError {
     Eof,
     Io,
@@ -187,6 +263,8 @@ pub enum ReadError {
 
 pub enum ReaderState {
     Raw(BufReader<File>),
+    /// A column without a file in this segment: every row has this value.
+    Constant(Option<ScalarValue>),
     Rle {
         reader: BufReader<File>,
         remaining: u32,
@@ -216,6 +294,7 @@ pub fn read_value(
 ) -> Result<Option<ScalarValue>, ReadError> {
     match state {
         ReaderState::Raw(reader) => read_value_raw(reader, col),
+        ReaderState::Constant(value) => Ok(value.clone()),
         ReaderState::Rle {
             reader,
             remaining,
@@ -0,0 +1,72 @@
+use crate::minisql::minisql_bind::coerce;
+use crate::minisql::minisql_error::QueryError;
+use crate::rpc::{FilterExpr, InList, ParamSlot, QueryRequest, ScalarValue};
+
+/// Calls `f` on every filter of a statement: WHERE and aggregate FILTER
+/// clauses of every SELECT, its CTEs, set-operation branches and subqueries.
+pub fn for_each_filter_mut(req: &mut QueryRequest, f: &mut dyn FnMut(&mut FilterExpr)) {
+    for cte in req.ctes.iter_mut() {
+        for_each_filter_mut(&mut cte.query, f);
+    }
+    for branch in req.set_ops.iter_mut() {
+        for_each_filter_mut(&mut branch.query, f);
+    }
+    for agg in req.aggregates.iter_mut() {
+        agg.filters.iter_mut().for_each(&mut *f);
+    }
+    for filter in req.filters.iter_mut() {
+        if let Some(sub) = filter.subquery.as_mut() {
+            for_each_filter_mut(sub, f);
+        }
+        f(filter);
+    }
+}
+
+/// Number of parameters a statement takes: the highest `$n` (or the count
+/// of `?`).
+pub fn param_count(req: &mut QueryRequest) -> usize {
+    let mut count = 0;
+    for_each_filter_mut(req, &mut |f| {
+        for p in &f.params {
+            count = count.max(p.index + 1);
+        }
+    });
+    count
+}
+
+/// Substitutes `values` for the placeholders of a prepared statement,
+/// converted to the types of their columns (`ParamRef::column_type`), so
+/// the statement needs no second `bind`.
+pub fn bind_params(req: &mut QueryRequest, values: &[ScalarValue]) -> Result<(), QueryError> {
+    let expected = param_count(req);
+    if values.len() != expected {
+        return Err(QueryError::Bind {
+            message: format!("Expected {} parameters, got {}", expected, values.len()),
+        });
+    }
+    let mut error = None;
+    for_each_filter_mut(req, &mut |f| {
+        for p in std::mem::take(&mut f.params) {
+            let value = match &p.column_type {
+                Some(ty) => match coerce(&values[p.index], ty, &f.column) {
+                    Ok(v) => v,
+                    Err(message) => {
+                        error.get_or_insert(QueryError::Bind { message });
+                        continue;
+                    }
+                },
+                None => values[p.index].clone(),
+            };
+            match p.slot {
+                ParamSlot::Value => f.value = value,
+                ParamSlot::ValueHi => f.value_hi = Some(value),
+                ParamSlot::ListItem(i) => {
+                    if let Some(InList::Values(list)) = f.list.as_mut() {
+                        list[i] = value;
+                    }
+                }
+            }
+        }
+    });
+    error.map_or(Ok(()), Err)
+}
@@ -1,4 +1,14 @@
-use crate::rpc::{AggregateExpr, AggregateFn, FilterExpr, Predicate, QueryRequest, ScalarValue};
+use crate::minisql::minisql_error::{Position, QueryError};
+use crate::minisql::minisql_params::{for_each_filter_mut, param_count};
+use crate::minisql::minisql_print::{grouping_column_name, normalize_header};
+use crate::minisql::minisql_window::window_column_name;
+use crate::rpc::{
+    AggregateExpr, AggregateFn, ArithOp, AssignedValue, Assignment, CteDef, Explain, FilterExpr,
+    GroupingExpr, InList, JoinSpec, Mutation, OrderByExpr, ParamRef, ParamSlot, Predicate,
+    QueryRequest, ScalarValue, SetOp, SetOpBranch, WindowExpr, WindowFn,
+};
+use crate::storage::storage_alter::SchemaChange;
+use crate::storage::storage_schema::{ColumnDef, ColumnType};
 use pest::Parser;
 use pest_derive::Parser;
 
@@ -6,101 +16,823 @@ use pest_derive::Parser;
 #[grammar = "minisql/grammar/minisql.pest"]
 struct SqlParser;
 
-pub fn parse_sql(sql: &str) -> Result<QueryRequest, String> {
-    let mut pairs = SqlParser::parse(Rule::sql, sql).map_err(|e| e.to_string())?;
-    let sql_pair = pairs
+/// Parses a single query; see `parse_script` for several statements.
+pub fn parse_sql(sql: &str) -> Result<QueryRequest, QueryError> {
+    let mut pairs = SqlParser::parse(Rule::sql, sql)?;
+    let sql_pair = pairs.next().ok_or_else(no_statement)?;
+    let span = sql_pair.as_span();
+    let query = sql_pair
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::query)
+        .ok_or_else(|| QueryError::parse_at(span, "Expected SELECT statement"))?;
+    parse_query(query)
+}
+
+/// The text parsed to nothing at all.
+fn no_statement() -> QueryError {
+    QueryError::Parse {
+        message: "Expected SQL statement".to_string(),
+        start: Some(Position { line: 1, column: 1 }),
+        end: None,
+    }
+}
+
+/// One statement of a script.
+#[derive(Debug)]
+pub enum Statement {
+    Query(Box<QueryRequest>),
+    /// `SET name = value` (or `TO value`); a bare word is a string.
+    Set {
+        name: String,
+        value: ScalarValue,
+    },
+    /// `SHOW name`, or every setting for `SHOW ALL`.
+    Show {
+        name: Option<String>,
+    },
+    /// `SHOW TABLES`: the tables and views of the catalog.
+    ShowTables,
+    /// `DESCRIBE table`: its columns.
+    Describe {
+        name: String,
+    },
+    CreateTable {
+        name: String,
+        columns: Vec<ColumnDef>,
+    },
+    /// `CREATE TABLE name [KEY (column)] AS query`.
+    CreateTableAs {
+        name: String,
+        key: Option<String>,
+        query: Box<QueryRequest>,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    /// `CREATE [MATERIALIZED] VIEW name AS SELECT ...`; the query's text is
+    /// what the view stores.
+    CreateView {
+        name: String,
+        query: Box<QueryRequest>,
+        materialized: bool,
+    },
+    /// `REFRESH MATERIALIZED VIEW name`.
+    RefreshView {
+        name: String,
+    },
+    /// `DROP [MATERIALIZED] VIEW [IF EXISTS] name`.
+    DropView {
+        name: String,
+        if_exists: bool,
+    },
+    AlterTable {
+        name: String,
+        change: SchemaChange,
+    },
+    /// `INSERT INTO table [(columns)] VALUES ... | SELECT ...`; no
+    /// columns means all of them, in schema order.
+    Insert {
+        table: String,
+        columns: Vec<String>,
+        source: InsertSource,
+    },
+    /// `DELETE FROM table [WHERE ...]` as the scan that finds the rows.
+    Delete(Box<QueryRequest>),
+    /// `UPDATE table SET column = value, ... [WHERE ...]` likewise, with
+    /// the assignments in the request's mutation.
+    Update(Box<QueryRequest>),
+}
+
+#[derive(Debug)]
+pub enum InsertSource {
+    Values(Vec<Vec<Option<ScalarValue>>>),
+    Query(Box<QueryRequest>),
+}
+
+/// Parses `;`-separated statements. Positions in errors are relative to
+/// the whole script.
+pub fn parse_script(sql: &str) -> Result<Vec<Statement>, QueryError> {
+    let mut pairs = SqlParser::parse(Rule::script, sql)?;
+    let script = pairs.next().ok_or_else(no_statement)?;
+    let span = script.as_span();
+    let statements = script
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::statement)
+        .map(parse_statement)
+        .collect::<Result<Vec<_>, _>>()?;
+    if statements.is_empty() {
+        return Err(QueryError::parse_at(span, "Expected SQL statement"));
+    }
+    Ok(statements)
+}
+
+fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, QueryError> {
+    let span = pair.as_span();
+    let inner = pair
+        .into_inner()
         .next()
-        .ok_or_else(|| "Expected SQL statement".to_string())?;
-    let mut select_pair = None;
-    for p in sql_pair.into_inner() {
-        if p.as_rule() == Rule::select_stmt {
-            select_pair = Some(p);
-            break;
+        .ok_or_else(|| QueryError::parse_at(span, "Empty statement"))?;
+    let span = inner.as_span();
+    match inner.as_rule() {
+        Rule::set_stmt => {
+            let mut parts = inner
+                .into_inner()
+                .filter(|p| !matches!(p.as_rule(), Rule::kw_set | Rule::kw_to));
+            let name = name_of(
+                &parts
+                    .next()
+                    .ok_or_else(|| QueryError::parse_at(span, "Missing setting name"))?,
+            );
+            let value = parts
+                .next()
+                .and_then(|v| v.into_inner().next())
+                .ok_or_else(|| QueryError::parse_at(span, "Missing setting value"))?;
+            let value = match value.as_rule() {
+                Rule::literal if param_at(&value).is_some() => {
+                    return Err(QueryError::parse_at(
+                        value.as_span(),
+                        "Parameters are not allowed in SET",
+                    ));
+                }
+                Rule::literal => parse_literal(value)?,
+                _ => ScalarValue::String(value.as_str().to_string()),
+            };
+            Ok(Statement::Set {
+                name: name.to_lowercase(),
+                value,
+            })
+        }
+        Rule::show_stmt => {
+            let target = inner
+                .into_inner()
+                .find(|p| p.as_rule() != Rule::kw_show)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing setting name"))?;
+            if target.as_rule() == Rule::show_tables {
+                return Ok(Statement::ShowTables);
+            }
+            Ok(Statement::Show {
+                name: (target.as_rule() != Rule::show_all).then(|| name_of(&target).to_lowercase()),
+            })
+        }
+        Rule::describe_stmt => {
+            let name = inner
+                .into_inner()
+                .find(|p| p.as_rule() == Rule::table_name)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing table name"))?;
+            Ok(Statement::Describe {
+                name: name_of(&name),
+            })
         }
+        Rule::create_table => {
+            reject_params(&inner, "CREATE TABLE")?;
+            let mut name = String::new();
+            let mut columns = Vec::new();
+            for p in inner.into_inner() {
+                match p.as_rule() {
+                    Rule::table_name => name = name_of(&p),
+                    Rule::column_def => columns.push(parse_column_def(p, false)?),
+                    _ => {}
+                }
+            }
+            Ok(Statement::CreateTable { name, columns })
+        }
+        Rule::create_table_as => {
+            let mut name = String::new();
+            let mut key = None;
+            let mut query = None;
+            for p in inner.into_inner() {
+                match p.as_rule() {
+                    Rule::table_name => name = name_of(&p),
+                    Rule::table_key => key = p.into_inner().next().map(|k| name_of(&k)),
+                    Rule::query => query = Some(plain_query(p, "CREATE TABLE ... AS")?),
+                    _ => {}
+                }
+            }
+            Ok(Statement::CreateTableAs {
+                name,
+                key,
+                query: Box::new(query.ok_or_else(|| QueryError::parse_at(span, "Missing query"))?),
+            })
+        }
+        Rule::drop_table => {
+            let mut parts = inner.into_inner();
+            let if_exists = parts.clone().any(|p| p.as_rule() == Rule::if_exists);
+            let name = parts
+                .find(|p| p.as_rule() == Rule::table_name)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing table name"))?;
+            Ok(Statement::DropTable {
+                name: name_of(&name),
+                if_exists,
+            })
+        }
+        Rule::create_view => {
+            reject_params(&inner, "CREATE VIEW")?;
+            let mut name = String::new();
+            let mut materialized = false;
+            let mut query = None;
+            for p in inner.into_inner() {
+                match p.as_rule() {
+                    Rule::materialized => materialized = true,
+                    Rule::table_name => name = name_of(&p),
+                    Rule::select_stmt => {
+                        let text = p.as_str().trim_end().to_string();
+                        query = Some(parse_select(p, &text)?);
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Statement::CreateView {
+                name,
+                query: Box::new(query.ok_or_else(|| QueryError::parse_at(span, "Missing query"))?),
+                materialized,
+            })
+        }
+        Rule::refresh_view => {
+            let name = inner
+                .into_inner()
+                .find(|p| p.as_rule() == Rule::table_name)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing view name"))?;
+            Ok(Statement::RefreshView {
+                name: name_of(&name),
+            })
+        }
+        Rule::drop_view => {
+            let mut parts = inner.into_inner();
+            let if_exists = parts.clone().any(|p| p.as_rule() == Rule::if_exists);
+            let name = parts
+                .find(|p| p.as_rule() == Rule::table_name)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing view name"))?;
+            Ok(Statement::DropView {
+                name: name_of(&name),
+                if_exists,
+            })
+        }
+        Rule::alter_table => {
+            reject_params(&inner, "ALTER TABLE")?;
+            let mut parts = inner.into_inner();
+            let name = parts
+                .find(|p| p.as_rule() == Rule::table_name)
+                .ok_or_else(|| QueryError::parse_at(span, "Missing table name"))?;
+            let action = parts
+                .next()
+                .ok_or_else(|| QueryError::parse_at(span, "Missing ALTER TABLE action"))?;
+            let rule = action.as_rule();
+            let mut args = action
+                .into_inner()
+                .filter(|p| matches!(p.as_rule(), Rule::column_def | Rule::ident));
+            let mut next = || {
+                args.next()
+                    .ok_or_else(|| QueryError::parse_at(span, "Missing column"))
+            };
+            let change = match rule {
+                // Added columns are nullable unless marked NOT NULL.
+                Rule::add_column => SchemaChange::AddColumn(parse_column_def(next()?, true)?),
+                Rule::drop_column => SchemaChange::DropColumn(name_of(&next()?)),
+                _ => SchemaChange::RenameColumn {
+                    from: name_of(&next()?),
+                    to: name_of(&next()?),
+                },
+            };
+            Ok(Statement::AlterTable {
+                name: name_of(&name),
+                change,
+            })
+        }
+        Rule::insert_stmt => parse_insert(inner),
+        Rule::delete_stmt => {
+            reject_params(&inner, "DELETE")?;
+            let text = inner.as_str().trim_end().to_string();
+            let mut table = String::new();
+            let mut filters = Vec::new();
+            for p in inner.into_inner() {
+                match p.as_rule() {
+                    Rule::table_name => table = name_of(&p),
+                    Rule::where_clause => filters = parse_where(p)?,
+                    _ => {}
+                }
+            }
+            Ok(Statement::Delete(Box::new(mutation_request(
+                text,
+                table,
+                filters,
+                Mutation::Delete,
+            ))))
+        }
+        Rule::update_stmt => {
+            reject_params(&inner, "UPDATE")?;
+            let text = inner.as_str().trim_end().to_string();
+            let mut table = String::new();
+            let mut assignments = Vec::new();
+            let mut filters = Vec::new();
+            for p in inner.into_inner() {
+                match p.as_rule() {
+                    Rule::table_name => table = name_of(&p),
+                    Rule::assignment => assignments.push(parse_assignment(p)?),
+                    Rule::where_clause => filters = parse_where(p)?,
+                    _ => {}
+                }
+            }
+            Ok(Statement::Update(Box::new(mutation_request(
+                text,
+                table,
+                filters,
+                Mutation::Update(assignments),
+            ))))
+        }
+        _ => Ok(Statement::Query(Box::new(parse_query(inner)?))),
+    }
+}
+
+fn reject_params(pair: &pest::iterators::Pair<Rule>, statement: &str) -> Result<(), QueryError> {
+    match pair
+        .clone()
+        .into_inner()
+        .flatten()
+        .find(|p| p.as_rule() == Rule::param)
+    {
+        Some(p) => Err(QueryError::parse_at(
+            p.as_span(),
+            format!("Parameters are not allowed in {}", statement),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_assignment(pair: pest::iterators::Pair<Rule>) -> Result<Assignment, QueryError> {
+    let span = pair.as_span();
+    let mut parts = pair.into_inner();
+    let column = name_of(
+        &parts
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing column name"))?,
+    );
+    let value = parts
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing assigned value"))?;
+    let value = match value.as_rule() {
+        Rule::null_lit => AssignedValue::Value(None),
+        Rule::literal => AssignedValue::Value(Some(parse_literal(value)?)),
+        Rule::arith_expr => {
+            let mut inner = value.into_inner();
+            let mut next = || {
+                inner
+                    .next()
+                    .ok_or_else(|| QueryError::parse_at(span, "Incomplete expression"))
+            };
+            let column = name_of(&next()?);
+            let op = match next()?.as_str() {
+                "+" => ArithOp::Add,
+                "-" => ArithOp::Sub,
+                "*" => ArithOp::Mul,
+                _ => ArithOp::Div,
+            };
+            AssignedValue::Arithmetic {
+                column,
+                op,
+                value: parse_literal(next()?)?,
+            }
+        }
+        _ => AssignedValue::Column(name_of(&value)),
+    };
+    Ok(Assignment { column, value })
+}
+
+/// The scan a DELETE or UPDATE sends to the workers: `SELECT COUNT(*) FROM
+/// table WHERE ...`, which changes the rows it counts.
+fn mutation_request(
+    query: String,
+    table: String,
+    filters: Vec<FilterExpr>,
+    mutation: Mutation,
+) -> QueryRequest {
+    QueryRequest {
+        query,
+        projections: Vec::new(),
+        aggregates: vec![AggregateExpr {
+            func: AggregateFn::Count,
+            column: None,
+            output_name: "COUNT(*)".to_string(),
+            alias: None,
+            filters: Vec::new(),
+        }],
+        table,
+        filters,
+        group_by: Vec::new(),
+        join: None,
+        grouping_sets: Vec::new(),
+        grouping: Vec::new(),
+        windows: Vec::new(),
+        window_local: false,
+        output: vec![normalize_header("COUNT(*)")],
+        ctes: Vec::new(),
+        set_ops: Vec::new(),
+        order_by: Vec::new(),
+        limit: None,
+        scan_columns: Vec::new(),
+        explain: None,
+        mutation: Some(mutation),
+        parts: None,
     }
-    let select = select_pair.ok_or_else(|| "Expected SELECT statement".to_string())?;
-    let mut projections = Vec::new();
-    let mut aggregates = Vec::new();
+}
+
+fn parse_insert(pair: pest::iterators::Pair<Rule>) -> Result<Statement, QueryError> {
+    let span = pair.as_span();
+    let mut table = String::new();
+    let mut columns = Vec::new();
+    let mut source = None;
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::table_name => table = name_of(&p),
+            Rule::insert_columns => columns = p.into_inner().map(|c| name_of(&c)).collect(),
+            Rule::values_clause => {
+                let mut rows = Vec::new();
+                for row in p.into_inner().filter(|r| r.as_rule() == Rule::values_row) {
+                    let mut values = Vec::new();
+                    for value in row.into_inner().filter_map(|v| v.into_inner().next()) {
+                        if value.as_rule() == Rule::null_lit {
+                            values.push(None);
+                        } else if param_at(&value).is_some() {
+                            return Err(QueryError::parse_at(
+                                value.as_span(),
+                                "Parameters are not allowed in INSERT",
+                            ));
+                        } else {
+                            values.push(Some(parse_literal(value)?));
+                        }
+                    }
+                    rows.push(values);
+                }
+                source = Some(InsertSource::Values(rows));
+            }
+            Rule::query => {
+                source = Some(InsertSource::Query(Box::new(plain_query(
+                    p,
+                    "INSERT ... SELECT",
+                )?)));
+            }
+            _ => {}
+        }
+    }
+    Ok(Statement::Insert {
+        table,
+        columns,
+        source: source.ok_or_else(|| QueryError::parse_at(span, "Missing VALUES or SELECT"))?,
+    })
+}
+
+/// A query run for a statement: no EXPLAIN and no parameters.
+fn plain_query(
+    pair: pest::iterators::Pair<Rule>,
+    statement: &str,
+) -> Result<QueryRequest, QueryError> {
+    let span = pair.as_span();
+    let mut request = parse_query(pair)?;
+    if request.explain.is_some() || param_count(&mut request) > 0 {
+        return Err(QueryError::parse_at(
+            span,
+            format!("{} takes a plain query", statement),
+        ));
+    }
+    Ok(request)
+}
+
+/// `name type [KEY | PRIMARY KEY] [NULL | NOT NULL] [DEFAULT value]`;
+/// `nullable` applies to columns marked neither NULL nor NOT NULL. The
+/// default is converted to the column type by the coordinator.
+fn parse_column_def(
+    pair: pest::iterators::Pair<Rule>,
+    nullable: bool,
+) -> Result<ColumnDef, QueryError> {
+    let span = pair.as_span();
+    let mut parts = pair.into_inner();
+    let name = name_of(
+        &parts
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing column name"))?,
+    );
+    let type_pair = parts
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing column type"))?;
+    let col_type = column_type(type_pair.as_str())
+        .map_err(|e| QueryError::parse_at(type_pair.as_span(), e))?;
+    let mut column = ColumnDef {
+        name,
+        col_type,
+        nullable,
+        is_key: false,
+        default: None,
+        file: None,
+    };
+    for flag in parts.filter_map(|p| p.into_inner().next()) {
+        match flag.as_rule() {
+            Rule::key_flag => column.is_key = true,
+            Rule::null_flag => column.nullable = true,
+            Rule::default_flag => {
+                let value = flag
+                    .into_inner()
+                    .last()
+                    .ok_or_else(|| QueryError::parse_at(span, "Missing default value"))?;
+                column.default = match value.as_rule() {
+                    Rule::literal => Some(parse_literal(value)?),
+                    _ => None,
+                };
+            }
+            _ => column.nullable = false,
+        }
+    }
+    Ok(column)
+}
+
+/// A storage type by its `.ssf` name or a common SQL alias.
+fn column_type(name: &str) -> Result<ColumnType, String> {
+    let name = name.to_lowercase();
+    let name = match name.as_str() {
+        "int" | "integer" => "int32",
+        "bigint" => "int64",
+        "double" | "float" | "real" => "float64",
+        "boolean" => "bool",
+        "text" | "varchar" => "string",
+        "timestamp" => "timestamp(ms)",
+        other => other,
+    };
+    name.parse()
+}
+
+fn parse_query(query: pest::iterators::Pair<Rule>) -> Result<QueryRequest, QueryError> {
+    let params = number_params(query.clone())?;
+    let mut ctes = Vec::new();
+    let mut request = None;
+    let mut set_ops = Vec::new();
+    let mut pending_op = None;
+    let mut order_by = Vec::new();
+    let mut limit = None;
+    let mut explain = None;
+    // The query text of an EXPLAIN is the statement being explained.
+    let span = query.as_span();
+    let (start, mut text) = (span.start(), query.as_str().trim_end());
+    for p in query.into_inner() {
+        match p.as_rule() {
+            Rule::explain => {
+                let analyze = p
+                    .clone()
+                    .into_inner()
+                    .any(|k| k.as_rule() == Rule::kw_analyze);
+                explain = Some(if analyze {
+                    Explain::Analyze
+                } else {
+                    Explain::Plan
+                });
+                text = text[p.as_span().end() - start..].trim_start();
+            }
+            Rule::with_clause => {
+                for cte in p.into_inner() {
+                    ctes.push(parse_cte(cte)?);
+                }
+            }
+            Rule::select_stmt if request.is_none() => request = Some(parse_select(p, text)?),
+            Rule::select_stmt => {
+                let text = p.as_str().to_string();
+                set_ops.push(SetOpBranch {
+                    op: pending_op
+                        .take()
+                        .ok_or_else(|| QueryError::parse_at(p.as_span(), "Missing set operator"))?,
+                    query: parse_select(p, &text)?,
+                });
+            }
+            Rule::set_op => pending_op = Some(parse_set_op(p)?),
+            Rule::order_by_clause => {
+                for item in p.into_inner() {
+                    order_by.push(parse_order_item(item)?);
+                }
+            }
+            Rule::limit_clause => {
+                let clause = p.as_span();
+                let n = p
+                    .into_inner()
+                    .next()
+                    .ok_or_else(|| QueryError::parse_at(clause, "Missing LIMIT count"))?;
+                limit = Some(n.as_str().parse::<u64>().map_err(|_| {
+                    QueryError::parse_at(n.as_span(), format!("Invalid LIMIT: {}", n.as_str()))
+                })?);
+            }
+            _ => {}
+        }
+    }
+    let mut request =
+        request.ok_or_else(|| QueryError::parse_at(span, "Expected SELECT statement"))?;
+    request.ctes = ctes;
+    request.set_ops = set_ops;
+    request.order_by = order_by;
+    request.limit = limit;
+    request.explain = explain;
+    // Placeholders were recorded by their offset in the text.
+    for_each_filter_mut(&mut request, &mut |f| {
+        for p in f.params.iter_mut() {
+            p.index = params[&p.index];
+        }
+    });
+    Ok(request)
+}
+
+/// Numbers the placeholders of a query by their offset in the text:
+/// `$n` is parameter n, `?` the next one from the left. The two styles
+/// cannot be mixed.
+fn number_params(
+    query: pest::iterators::Pair<Rule>,
+) -> Result<std::collections::HashMap<usize, usize>, QueryError> {
+    let mut numbered = std::collections::HashMap::new();
+    let mut style = None;
+    let mut highest: Option<(pest::Span, usize)> = None;
+    for p in query
+        .into_inner()
+        .flatten()
+        .filter(|p| p.as_rule() == Rule::param)
+    {
+        let text = p.as_str();
+        let positional = text == "?";
+        if *style.get_or_insert(positional) != positional {
+            return Err(QueryError::parse_at(
+                p.as_span(),
+                "Cannot mix $n and ? parameters",
+            ));
+        }
+        let index = if positional {
+            numbered.len()
+        } else {
+            match text[1..].parse::<usize>() {
+                Ok(n) if n >= 1 => n - 1,
+                _ => {
+                    return Err(QueryError::parse_at(
+                        p.as_span(),
+                        format!("Invalid parameter {}", text),
+                    ));
+                }
+            }
+        };
+        numbered.insert(p.as_span().start(), index);
+        if highest.is_none_or(|(_, n)| index > n) {
+            highest = Some((p.as_span(), index));
+        }
+    }
+    // `$2` without `$1` would take a value nothing uses or checks.
+    if let Some((span, n)) = highest
+        && let Some(missing) = (0..n).find(|i| !numbered.values().any(|v| v == i))
+    {
+        return Err(QueryError::parse_at(
+            span,
+            format!("Parameter ${} is used but ${} is not", n + 1, missing + 1),
+        ));
+    }
+    Ok(numbered)
+}
+
+fn parse_cte(pair: pest::iterators::Pair<Rule>) -> Result<CteDef, QueryError> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let name = name_of(
+        &inner
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing CTE name"))?,
+    );
+    let select = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing CTE query"))?;
+    let text = select.as_str().to_string();
+    Ok(CteDef {
+        name,
+        query: parse_select(select, &text)?,
+        rows: None,
+    })
+}
+
+fn parse_set_op(pair: pest::iterators::Pair<Rule>) -> Result<SetOp, QueryError> {
+    let span = pair.as_span();
+    let rules: Vec<Rule> = pair.into_inner().map(|p| p.as_rule()).collect();
+    match rules.as_slice() {
+        [Rule::kw_union, Rule::kw_all] => Ok(SetOp::UnionAll),
+        [Rule::kw_union] => Ok(SetOp::Union),
+        [Rule::kw_intersect] => Ok(SetOp::Intersect),
+        [Rule::kw_except] => Ok(SetOp::Except),
+        _ => Err(QueryError::parse_at(span, "Unsupported set operator")),
+    }
+}
+
+fn parse_order_item(pair: pest::iterators::Pair<Rule>) -> Result<OrderByExpr, QueryError> {
+    let span = pair.as_span();
+    let mut column = None;
+    let mut descending = false;
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::aggregate_expr => column = Some(parse_agg(p)?.2),
+            Rule::column_ref => column = Some(name_of(&p)),
+            Rule::sort_dir => descending = p.as_str().eq_ignore_ascii_case("DESC"),
+            _ => {}
+        }
+    }
+    Ok(OrderByExpr {
+        column: column.ok_or_else(|| QueryError::parse_at(span, "Missing ORDER BY column"))?,
+        descending,
+    })
+}
+
+/// Items of the SELECT list, split by kind; `output` keeps their order.
+#[derive(Default)]
+struct SelectList {
+    projections: Vec<String>,
+    aggregates: Vec<AggregateExpr>,
+    windows: Vec<WindowExpr>,
+    grouping: Vec<GroupingExpr>,
+    output: Vec<String>,
+}
+
+fn parse_select(
+    select: pest::iterators::Pair<Rule>,
+    sql: &str,
+) -> Result<QueryRequest, QueryError> {
+    let span = select.as_span();
+    let mut list = SelectList::default();
     let mut filters = Vec::new();
     let mut group_by = Vec::new();
+    let mut grouping_sets: Vec<Vec<String>> = Vec::new();
     let mut table: Option<String> = None;
+    let mut join = None;
 
     for element in select.into_inner() {
         match element.as_rule() {
             Rule::projection => {
                 for proj_item in element.into_inner() {
-                    if proj_item.as_rule() != Rule::projection_item {
-                        continue;
-                    }
-                    let proj_text = proj_item.as_str().to_string();
-                    let mut inner_iter = proj_item.into_inner();
-                    if let Some(inner) = inner_iter.next() {
-                        match inner.as_rule() {
-                            Rule::aggregate_expr => {
-                                let (func, column, output_name) = parse_agg(inner)?;
-                                aggregates.push(AggregateExpr {
-                                    func,
-                                    column,
-                                    output_name,
-                                });
-                            }
-                            Rule::ident => projections.push(inner.as_str().to_string()),
-                            Rule::star => projections.push("*".into()),
-                            _ => {}
-                        }
-                    } else {
-                        projections.push(proj_text);
+                    if proj_item.as_rule() == Rule::projection_item {
+                        parse_projection_item(proj_item, &mut list)?;
                     }
                 }
             }
-            Rule::projection_item => {
-                let proj_text = element.as_str().to_string();
-                let mut inner_iter = element.into_inner();
-                if let Some(inner) = inner_iter.next() {
-                    match inner.as_rule() {
-                        Rule::aggregate_expr => {
-                            let (func, column, output_name) = parse_agg(inner)?;
-                            aggregates.push(AggregateExpr {
-                                func,
-                                column,
-                                output_name,
-                            });
-                        }
-                        Rule::ident => projections.push(inner.as_str().to_string()),
-                        Rule::star => projections.push("*".into()),
-                        _ => {}
-                    }
-                } else {
-                    projections.push(proj_text);
-                }
-            }
+            Rule::projection_item => parse_projection_item(element, &mut list)?,
             Rule::table_name => {
-                table = Some(element.as_str().to_string());
+                table = Some(name_of(&element));
             }
-            Rule::where_clause => {
-                if let Some(boolean_expr) = element.into_inner().next() {
-                    for pred in boolean_expr.into_inner() {
-                        if pred.as_rule() == Rule::predicate {
-                            filters.push(parse_predicate(pred)?);
-                        }
-                    }
-                }
+            Rule::join_clause => {
+                let left_name = table
+                    .clone()
+                    .ok_or_else(|| QueryError::parse_at(element.as_span(), "Table name missing"))?;
+                join = Some(parse_join(element, left_name)?);
             }
+            Rule::where_clause => filters = parse_where(element)?,
             Rule::group_by_clause => {
-                for ident in element.into_inner() {
-                    if ident.as_rule() == Rule::group_item {
-                        group_by.push(ident.as_str().to_string());
+                for item in element.into_inner() {
+                    match item.as_rule() {
+                        Rule::group_item => group_by.push(name_of(&item)),
+                        Rule::rollup | Rule::cube | Rule::grouping_sets => {
+                            grouping_sets = parse_grouping_sets(item)?;
+                            for col in grouping_sets.iter().flatten() {
+                                if !group_by.contains(col) {
+                                    group_by.push(col.clone());
+                                }
+                            }
+                        }
+                        _ => {}
                     }
                 }
             }
             Rule::group_item => {
-                group_by.push(element.as_str().to_string());
+                group_by.push(name_of(&element));
             }
             _ => {}
         }
     }
 
-    let table = table.ok_or_else(|| "Table name missing".to_string())?;
+    let table = table.ok_or_else(|| QueryError::parse_at(span, "Table name missing"))?;
+    let SelectList {
+        projections,
+        aggregates,
+        windows,
+        grouping,
+        output,
+    } = list;
+    if !grouping.is_empty() {
+        for col in grouping.iter().flat_map(|g: &GroupingExpr| &g.columns) {
+            if !group_by.contains(col) {
+                return Err(QueryError::Bind {
+                    message: format!("GROUPING argument '{}' is not a GROUP BY column", col),
+                });
+            }
+        }
+        if grouping_sets.is_empty() {
+            grouping_sets.push(group_by.clone());
+        }
+    }
+    if !windows.is_empty() {
+        if !aggregates.is_empty() || !group_by.is_empty() {
+            return Err(QueryError::Bind {
+                message: "Window functions cannot be combined with aggregates or GROUP BY".into(),
+            });
+        }
+        if projections.iter().any(|p| p == "*") {
+            return Err(QueryError::Bind {
+                message: "Window functions cannot be combined with *".into(),
+            });
+        }
+    }
 
     Ok(QueryRequest {
         query: sql.to_string(),
@@ -109,12 +841,267 @@ pub fn parse_sql(sql: &str) -> Result<QueryRequest, String> {
         table,
         filters,
         group_by,
+        join,
+        grouping_sets,
+        grouping,
+        windows,
+        window_local: false,
+        output,
+        ctes: Vec::new(),
+        set_ops: Vec::new(),
+        order_by: Vec::new(),
+        limit: None,
+        scan_columns: Vec::new(),
+        explain: None,
+        mutation: None,
+        parts: None,
+    })
+}
+
+fn parse_projection_item(
+    item: pest::iterators::Pair<Rule>,
+    list: &mut SelectList,
+) -> Result<(), QueryError> {
+    let proj_text = item.as_str().to_string();
+    let mut inner_iter = item.into_inner();
+    let Some(inner) = inner_iter.next() else {
+        list.output.push(proj_text.clone());
+        list.projections.push(proj_text);
+        return Ok(());
+    };
+    match inner.as_rule() {
+        Rule::aggregate_expr => {
+            let (func, column, mut output_name) = parse_agg(inner)?;
+            let mut filters = Vec::new();
+            let mut alias = None;
+            for p in inner_iter {
+                match p.as_rule() {
+                    Rule::agg_filter => {
+                        let span = p.as_span();
+                        let where_clause = p
+                            .into_inner()
+                            .next()
+                            .ok_or_else(|| QueryError::parse_at(span, "Empty FILTER"))?;
+                        let text = where_clause.as_str().split_whitespace().collect::<Vec<_>>();
+                        output_name = format!("{} FILTER ({})", output_name, text.join(" "));
+                        let span = where_clause.as_span();
+                        filters = parse_where(where_clause)?;
+                        if filters.iter().any(|f| f.subquery.is_some()) {
+                            return Err(QueryError::parse_at(
+                                span,
+                                "Subqueries are not supported in FILTER",
+                            ));
+                        }
+                    }
+                    Rule::alias => {
+                        alias = p.into_inner().next().map(|i| name_of(&i));
+                    }
+                    _ => {}
+                }
+            }
+            list.output.push(
+                alias
+                    .clone()
+                    .unwrap_or_else(|| normalize_header(&output_name)),
+            );
+            list.aggregates.push(AggregateExpr {
+                func,
+                column,
+                output_name,
+                alias,
+                filters,
+            });
+        }
+        Rule::window_expr => {
+            let mut window = parse_window(inner)?;
+            window.alias = inner_iter
+                .next()
+                .and_then(|a| a.into_inner().next())
+                .map(|ident| name_of(&ident));
+            list.output.push(window_column_name(&window));
+            list.windows.push(window);
+        }
+        Rule::grouping_fn => {
+            let grouping = GroupingExpr {
+                columns: inner.into_inner().map(|c| name_of(&c)).collect(),
+                alias: inner_iter
+                    .next()
+                    .and_then(|a| a.into_inner().next())
+                    .map(|ident| name_of(&ident)),
+            };
+            list.output.push(grouping_column_name(&grouping));
+            list.grouping.push(grouping);
+        }
+        Rule::column_ref => {
+            list.output.push(name_of(&inner));
+            list.projections.push(name_of(&inner));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expands ROLLUP/CUBE/GROUPING SETS into the list of grouping sets.
+fn parse_grouping_sets(pair: pest::iterators::Pair<Rule>) -> Result<Vec<Vec<String>>, QueryError> {
+    let rule = pair.as_rule();
+    let span = pair.as_span();
+    let items = |p: pest::iterators::Pair<Rule>| -> Vec<String> {
+        p.into_inner().map(|c| name_of(&c)).collect()
+    };
+    match rule {
+        Rule::rollup => {
+            let cols = items(pair);
+            Ok((0..=cols.len()).rev().map(|n| cols[..n].to_vec()).collect())
+        }
+        Rule::cube => {
+            let cols = items(pair);
+            if cols.len() > 8 {
+                return Err(QueryError::parse_at(
+                    span,
+                    "CUBE supports at most 8 columns",
+                ));
+            }
+            let n = cols.len();
+            Ok((0..1u32 << n)
+                .rev()
+                .map(|mask| {
+                    (0..n)
+                        .filter(|i| mask & (1 << (n - 1 - i)) != 0)
+                        .map(|i| cols[i].clone())
+                        .collect()
+                })
+                .collect())
+        }
+        _ => Ok(pair.into_inner().map(items).collect()),
+    }
+}
+
+fn parse_window(pair: pest::iterators::Pair<Rule>) -> Result<WindowExpr, QueryError> {
+    let span = pair.as_span();
+    let mut window = WindowExpr {
+        func: WindowFn::RowNumber,
+        column: None,
+        offset: 1,
+        partition_by: Vec::new(),
+        order_by: Vec::new(),
+        alias: None,
+    };
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::window_fn => {
+                let f = p
+                    .into_inner()
+                    .next()
+                    .ok_or_else(|| QueryError::parse_at(span, "Missing window function"))?;
+                match f.as_rule() {
+                    Rule::rank_fn => {
+                        let name = f
+                            .into_inner()
+                            .next()
+                            .ok_or_else(|| QueryError::parse_at(span, "Missing window function"))?;
+                        window.func = if name.as_str().eq_ignore_ascii_case("RANK") {
+                            WindowFn::Rank
+                        } else {
+                            WindowFn::RowNumber
+                        };
+                    }
+                    Rule::offset_fn => {
+                        let mut inner = f.into_inner();
+                        let name = inner
+                            .next()
+                            .ok_or_else(|| QueryError::parse_at(span, "Missing window function"))?;
+                        window.func = if name.as_str().eq_ignore_ascii_case("LAG") {
+                            WindowFn::Lag
+                        } else {
+                            WindowFn::Lead
+                        };
+                        window.column = inner.next().map(|c| name_of(&c));
+                        if let Some(n) = inner.next() {
+                            window.offset = n.as_str().parse().map_err(|_| {
+                                QueryError::parse_at(
+                                    n.as_span(),
+                                    format!("Invalid offset: {}", n.as_str()),
+                                )
+                            })?;
+                        }
+                    }
+                    Rule::aggregate_expr => {
+                        let (func, column, _) = parse_agg(f)?;
+                        window.func = WindowFn::Aggregate(func);
+                        window.column = column;
+                    }
+                    _ => {}
+                }
+            }
+            Rule::partition_by => {
+                window.partition_by = p.into_inner().map(|c| name_of(&c)).collect();
+            }
+            Rule::window_order => {
+                for item in p.into_inner() {
+                    window.order_by.push(parse_order_item(item)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(window)
+}
+
+fn parse_join(
+    pair: pest::iterators::Pair<Rule>,
+    left_name: String,
+) -> Result<JoinSpec, QueryError> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let right_name = name_of(
+        &inner
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing join table"))?,
+    );
+    let first = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing join column"))?;
+    let second = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing join column"))?;
+
+    // ON accepts the two sides in either order; unqualified columns are
+    // taken as written (left table first).
+    let (left_ref, right_ref) = match ident_parts(&first).as_slice() {
+        [qualifier, _] if *qualifier == right_name && *qualifier != left_name => (second, first),
+        _ => (first, second),
+    };
+    let left_key = unqualified(left_ref, &left_name)?;
+    let right_key = unqualified(right_ref, &right_name)?;
+
+    Ok(JoinSpec {
+        right_table: right_name.clone(),
+        left_name,
+        right_name,
+        left_key,
+        right_key,
     })
 }
 
+fn unqualified(pair: pest::iterators::Pair<Rule>, table: &str) -> Result<String, QueryError> {
+    match ident_parts(&pair).as_slice() {
+        [qualifier, name] if qualifier == table => Ok(name.clone()),
+        [qualifier, _] => Err(QueryError::parse_at(
+            pair.as_span(),
+            format!(
+                "Join column '{}' does not belong to table '{}'",
+                name_of(&pair),
+                qualifier
+            ),
+        )),
+        _ => Ok(name_of(&pair)),
+    }
+}
+
 fn parse_agg(
     pair: pest::iterators::Pair<Rule>,
-) -> Result<(AggregateFn, Option<String>, String), String> {
+) -> Result<(AggregateFn, Option<String>, String), QueryError> {
+    let span = pair.as_span();
     let mut func = None;
     let mut column: Option<String> = None;
 
@@ -127,11 +1114,11 @@ fn parse_agg(
                     "AVG" => AggregateFn::Avg,
                     "MIN" => AggregateFn::Min,
                     "MAX" => AggregateFn::Max,
-                    _ => return Err("Unsupported aggregate".into()),
+                    _ => return Err(QueryError::parse_at(p.as_span(), "Unsupported aggregate")),
                 });
             }
-            Rule::ident => {
-                column = Some(p.as_str().to_string());
+            Rule::column_ref => {
+                column = Some(name_of(&p));
             }
             Rule::star => {
                 column = None;
@@ -140,7 +1127,7 @@ fn parse_agg(
         }
     }
 
-    let func = func.ok_or_else(|| "Aggregate function missing".to_string())?;
+    let func = func.ok_or_else(|| QueryError::parse_at(span, "Aggregate function missing"))?;
     let output = match &column {
         Some(col) => format!("{}({})", format!("{:?}", func).to_uppercase(), col),
         None => format!("{}(*)", format!("{:?}", func).to_uppercase()),
@@ -149,36 +1136,99 @@ fn parse_agg(
     Ok((func, column, output))
 }
 
-fn parse_predicate(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, String> {
+fn parse_where(pair: pest::iterators::Pair<Rule>) -> Result<Vec<FilterExpr>, QueryError> {
+    let mut filters = Vec::new();
+    if let Some(boolean_expr) = pair.into_inner().next() {
+        for pred in boolean_expr.into_inner() {
+            if pred.as_rule() == Rule::predicate {
+                filters.push(parse_predicate(pred)?);
+            }
+        }
+    }
+    Ok(filters)
+}
+
+fn parse_predicate(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, QueryError> {
+    let span = pair.as_span();
     let mut inner = pair.into_inner();
-    let first = inner.next().ok_or_else(|| "Empty predicate".to_string())?;
+    let first = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Empty predicate"))?;
 
     match first.as_rule() {
         Rule::comparison_expr => parse_comparison(first),
         Rule::between_expr => parse_between(first),
-        _ => Err("Unsupported predicate".into()),
+        Rule::in_expr => parse_in(first),
+        // A bare boolean column, e.g. `WHERE active`.
+        Rule::bool_column => Ok(FilterExpr {
+            column: name_of(&first),
+            pred: Predicate::Eq,
+            value: ScalarValue::Bool(true),
+            value_hi: None,
+            list: None,
+            subquery: None,
+            params: Vec::new(),
+        }),
+        _ => Err(QueryError::parse_at(
+            first.as_span(),
+            "Unsupported predicate",
+        )),
     }
 }
 
-fn parse_comparison(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, String> {
+fn parse_comparison(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, QueryError> {
+    let span = pair.as_span();
     let mut inner = pair.into_inner();
-    let column = inner
-        .next()
-        .ok_or_else(|| "Missing column".to_string())?
-        .as_str()
-        .to_string();
+    let column = name_of(
+        &inner
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing column"))?,
+    );
 
-    let pred_pair = inner.next().ok_or_else(|| "Missing operator".to_string())?;
+    let pred_pair = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing operator"))?;
     let pred = match pred_pair.as_str() {
         "=" => Predicate::Eq,
         "<" => Predicate::Lt,
         ">" => Predicate::Gt,
         "<=" => Predicate::Le,
         ">=" => Predicate::Ge,
-        _ => return Err("Unsupported operator".into()),
+        _ => {
+            return Err(QueryError::parse_at(
+                pred_pair.as_span(),
+                "Unsupported operator",
+            ));
+        }
     };
 
-    let value_pair = inner.next().ok_or_else(|| "Missing literal".to_string())?;
+    let value_pair = inner
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Missing literal"))?;
+    if value_pair.as_rule() == Rule::subquery {
+        let select = value_pair
+            .into_inner()
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Empty subquery"))?;
+        let text = select.as_str().to_string();
+        return Ok(FilterExpr {
+            column,
+            pred,
+            value: ScalarValue::Int(0), // placeholder until the subquery runs
+            value_hi: None,
+            list: None,
+            subquery: Some(Box::new(parse_select(select, &text)?)),
+            params: Vec::new(),
+        });
+    }
+    let params = param_at(&value_pair)
+        .map(|index| ParamRef {
+            slot: ParamSlot::Value,
+            index,
+            column_type: None,
+        })
+        .into_iter()
+        .collect();
     let value = parse_literal(value_pair)?;
 
     Ok(FilterExpr {
@@ -186,34 +1236,106 @@ fn parse_comparison(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, Str
         pred,
         value,
         value_hi: None,
+        list: None,
+        subquery: None,
+        params,
     })
 }
 
-fn parse_between(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, String> {
+fn parse_in(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, QueryError> {
+    let span = pair.as_span();
     let mut inner = pair.into_inner();
-    let column = inner
+    let column = name_of(
+        &inner
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing column"))?,
+    );
+    let source = inner
         .next()
-        .ok_or_else(|| "Missing column".to_string())?
-        .as_str()
-        .to_string();
+        .ok_or_else(|| QueryError::parse_at(span, "Missing IN list"))?;
+
+    let mut filter = FilterExpr {
+        column,
+        pred: Predicate::In,
+        value: ScalarValue::Int(0), // unused for IN
+        value_hi: None,
+        list: None,
+        subquery: None,
+        params: Vec::new(),
+    };
+    match source.as_rule() {
+        Rule::select_stmt => {
+            let text = source.as_str().to_string();
+            filter.subquery = Some(Box::new(parse_select(source, &text)?));
+        }
+        _ => {
+            let mut values = Vec::new();
+            for (i, literal) in source.into_inner().enumerate() {
+                if let Some(index) = param_at(&literal) {
+                    filter.params.push(ParamRef {
+                        slot: ParamSlot::ListItem(i),
+                        index,
+                        column_type: None,
+                    });
+                }
+                values.push(parse_literal(literal)?);
+            }
+            filter.list = Some(InList::Values(values));
+        }
+    }
+    Ok(filter)
+}
+
+fn parse_between(pair: pest::iterators::Pair<Rule>) -> Result<FilterExpr, QueryError> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let column = name_of(
+        &inner
+            .next()
+            .ok_or_else(|| QueryError::parse_at(span, "Missing column"))?,
+    );
 
     let low = inner
         .next()
-        .ok_or_else(|| "Missing low bound".to_string())?;
+        .ok_or_else(|| QueryError::parse_at(span, "Missing low bound"))?;
     let high = inner
         .next()
-        .ok_or_else(|| "Missing high bound".to_string())?;
+        .ok_or_else(|| QueryError::parse_at(span, "Missing high bound"))?;
 
+    let mut params = Vec::new();
+    for (pair, slot) in [(&low, ParamSlot::Value), (&high, ParamSlot::ValueHi)] {
+        if let Some(index) = param_at(pair) {
+            params.push(ParamRef {
+                slot,
+                index,
+                column_type: None,
+            });
+        }
+    }
     Ok(FilterExpr {
         column,
         pred: Predicate::Between,
         value: parse_literal(low)?,
         value_hi: Some(parse_literal(high)?),
+        list: None,
+        subquery: None,
+        params,
     })
 }
 
-fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<ScalarValue, String> {
-    let p = pair.into_inner().next().ok_or("Invalid literal")?;
+/// Offset of a placeholder literal in the text; `parse_sql` turns it into
+/// the parameter number.
+fn param_at(literal: &pest::iterators::Pair<Rule>) -> Option<usize> {
+    let inner = literal.clone().into_inner().next()?;
+    (inner.as_rule() == Rule::param).then(|| inner.as_span().start())
+}
+
+fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<ScalarValue, QueryError> {
+    let span = pair.as_span();
+    let p = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Invalid literal"))?;
     match p.as_rule() {
         Rule::number => {
             let s = p.as_str();
@@ -222,14 +1344,79 @@ fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<ScalarValue, Strin
             } else if let Ok(f) = s.parse::<f64>() {
                 Ok(ScalarValue::Float(f))
             } else {
-                Err("Invalid number".into())
+                Err(QueryError::parse_at(p.as_span(), "Invalid number"))
             }
         }
-        Rule::string_lit => {
-            let s = p.as_str();
-            let inner = &s[1..s.len().saturating_sub(1)];
-            Ok(ScalarValue::String(inner.to_string()))
+        Rule::string_lit => Ok(ScalarValue::String(decode_string(p)?)),
+        Rule::bool_lit => Ok(ScalarValue::Bool(p.as_str().eq_ignore_ascii_case("TRUE"))),
+        // Placeholder until the parameter is bound.
+        Rule::param => Ok(ScalarValue::Int(0)),
+        _ => Err(QueryError::parse_at(p.as_span(), "Unknown literal type")),
+    }
+}
+
+/// The parts of an identifier or column reference, unquoted: `t."a b"` is
+/// `["t", "a b"]`.
+fn ident_parts(pair: &pest::iterators::Pair<Rule>) -> Vec<String> {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .filter_map(|p| match p.as_rule() {
+            Rule::bare_ident => Some(p.as_str().to_string()),
+            Rule::quoted_ident => {
+                let s = p.as_str();
+                Some(s[1..s.len() - 1].replace("\"\"", "\""))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The name an identifier, table or column reference stands for.
+fn name_of(pair: &pest::iterators::Pair<Rule>) -> String {
+    ident_parts(pair).join(".")
+}
+
+/// The text of a string literal: `''` in single quotes is a quote, double
+/// quotes take JSON escapes.
+fn decode_string(pair: pest::iterators::Pair<Rule>) -> Result<String, QueryError> {
+    let span = pair.as_span();
+    let p = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| QueryError::parse_at(span, "Invalid string"))?;
+    let s = p.as_str();
+    let body = &s[1..s.len() - 1];
+    if p.as_rule() == Rule::sq_string {
+        return Ok(body.replace("''", "'"));
+    }
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => out.push('\u{8}'),
+            Some('f') => out.push('\u{c}'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        QueryError::parse_at(p.as_span(), format!("Invalid escape \\u{}", hex))
+                    })?;
+                out.push(c);
+            }
+            // `\"`, `\\` and `\/` stand for themselves.
+            Some(c) => out.push(c),
+            None => {}
         }
-        _ => Err("Unknown literal type".into()),
     }
+    Ok(out)
 }
@@ -0,0 +1,545 @@
+use std::cmp::Ordering;
+
+use crate::minisql::minisql_eval::compare_scalar;
+use crate::minisql::minisql_window::window_input_columns;
+use crate::rpc::{
+    AggregateExpr, FilterExpr, InList, JoinSpec, OrderByExpr, Predicate, QueryRequest, ScalarValue,
+    WindowExpr,
+};
+
+/// Logical plan of one SELECT, built bottom-up from a `QueryRequest`.
+#[derive(Debug, Clone)]
+pub enum LogicalPlan {
+    /// Reads `columns` of a table (or a join of two co-located tables);
+    /// `filters` are checked while scanning and drive zone-map pruning.
+    Scan {
+        table: String,
+        join: Option<JoinSpec>,
+        columns: Vec<String>,
+        filters: Vec<FilterExpr>,
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicates: Vec<FilterExpr>,
+    },
+    /// Groups rows; projection-only queries group by their columns.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group_by: Vec<String>,
+        aggregates: Vec<AggregateExpr>,
+        grouping_sets: Vec<Vec<String>>,
+    },
+    Window {
+        input: Box<LogicalPlan>,
+        projections: Vec<String>,
+        windows: Vec<WindowExpr>,
+    },
+    Project {
+        input: Box<LogicalPlan>,
+        columns: Vec<String>,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        order_by: Vec<OrderByExpr>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        count: u64,
+    },
+    /// No row can match; replaces the subtree that was proven empty.
+    Empty {
+        reason: String,
+    },
+    /// Coordinator side of a lowered plan: the merged partials of the worker
+    /// fragment.
+    Gather,
+}
+
+impl LogicalPlan {
+    pub fn input(&self) -> Option<&LogicalPlan> {
+        match self {
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Window { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => Some(input),
+            _ => None,
+        }
+    }
+
+    fn input_mut(&mut self) -> Option<&mut Box<LogicalPlan>> {
+        match self {
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Window { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => Some(input),
+            _ => None,
+        }
+    }
+}
+
+/// A plan split by where it runs: `worker` on every segment, `coordinator`
+/// over the merged partials (its `Gather` leaf).
+#[derive(Debug, Clone)]
+pub struct Fragments {
+    pub worker: LogicalPlan,
+    pub coordinator: LogicalPlan,
+}
+
+impl Fragments {
+    /// The request shipped to workers: the optimized scan filters and the
+    /// pruned column list. `None` when the plan is known to be empty.
+    pub fn worker_request(&self, req: &QueryRequest) -> Option<QueryRequest> {
+        let mut node = &self.worker;
+        loop {
+            match node {
+                LogicalPlan::Empty { .. } => return None,
+                LogicalPlan::Scan {
+                    columns, filters, ..
+                } => {
+                    let mut out = req.clone();
+                    out.filters = filters.clone();
+                    out.scan_columns = columns.clone();
+                    return Some(out);
+                }
+                other => node = other.input()?,
+            }
+        }
+    }
+}
+
+/// Builds, optimizes and lowers the plan of a request.
+pub fn plan_query(req: &QueryRequest) -> Fragments {
+    lower(optimize(build_plan(req)))
+}
+
+/// Columns a worker reads for `req`: the planner's list, or the pruned scan
+/// of a fresh plan for requests that were not planned (e.g. `minilocal`).
+pub fn scan_columns(req: &QueryRequest) -> Vec<String> {
+    if !req.scan_columns.is_empty() {
+        return req.scan_columns.clone();
+    }
+    let mut node = &plan_query(req).worker;
+    loop {
+        match node {
+            LogicalPlan::Scan { columns, .. } => return columns.clone(),
+            other => match other.input() {
+                Some(input) => node = input,
+                None => return Vec::new(),
+            },
+        }
+    }
+}
+
+/// Grouping columns of a request; projection-only queries group by their
+/// projections, `SELECT *` is not grouped.
+pub fn derive_group_by(req: &QueryRequest) -> Vec<String> {
+    if req.aggregates.is_empty() && req.group_by.is_empty() {
+        if req.projections.len() == 1 && req.projections[0] == "*" {
+            Vec::new()
+        } else {
+            req.projections.clone()
+        }
+    } else {
+        req.group_by.clone()
+    }
+}
+
+pub fn build_plan(req: &QueryRequest) -> LogicalPlan {
+    let mut plan = LogicalPlan::Scan {
+        table: req.table.clone(),
+        join: req.join.clone(),
+        columns: Vec::new(),
+        filters: Vec::new(),
+    };
+    if !req.filters.is_empty() {
+        plan = LogicalPlan::Filter {
+            input: Box::new(plan),
+            predicates: req.filters.clone(),
+        };
+    }
+    plan = if req.windows.is_empty() {
+        LogicalPlan::Aggregate {
+            input: Box::new(plan),
+            group_by: derive_group_by(req),
+            aggregates: req.aggregates.clone(),
+            grouping_sets: req.grouping_sets.clone(),
+        }
+    } else {
+        LogicalPlan::Window {
+            input: Box::new(plan),
+            projections: req.projections.clone(),
+            windows: req.windows.clone(),
+        }
+    };
+    // ORDER BY/LIMIT of a compound SELECT apply to the combined result.
+    if req.set_ops.is_empty() {
+        if !req.order_by.is_empty() {
+            plan = LogicalPlan::Sort {
+                input: Box::new(plan),
+                order_by: req.order_by.clone(),
+            };
+        }
+        if let Some(count) = req.limit {
+            plan = LogicalPlan::Limit {
+                input: Box::new(plan),
+                count,
+            };
+        }
+    }
+    if !req.output.is_empty() && !req.output.iter().any(|c| c == "*") {
+        plan = LogicalPlan::Project {
+            input: Box::new(plan),
+            columns: req.output.clone(),
+        };
+    }
+    plan
+}
+
+/// Applies the rewrite rules: constant folding and contradiction detection
+/// on filters, predicate pushdown into the scan, then projection pruning.
+pub fn optimize(plan: LogicalPlan) -> LogicalPlan {
+    let plan = fold_filters(plan);
+    let plan = push_down_filters(plan);
+    prune_columns(plan, Vec::new())
+}
+
+/// Splits a plan below its coordinator-only operators (Project, Sort,
+/// Limit); everything from the Aggregate/Window down runs on workers.
+pub fn lower(plan: LogicalPlan) -> Fragments {
+    match plan {
+        LogicalPlan::Project { .. } | LogicalPlan::Sort { .. } | LogicalPlan::Limit { .. } => {
+            let mut coordinator = plan;
+            let slot = coordinator.input_mut().expect("has input");
+            let inner = std::mem::replace(slot.as_mut(), LogicalPlan::Gather);
+            let lowered = lower(inner);
+            **slot = lowered.coordinator;
+            Fragments {
+                worker: lowered.worker,
+                coordinator,
+            }
+        }
+        worker => Fragments {
+            worker,
+            coordinator: LogicalPlan::Gather,
+        },
+    }
+}
+
+fn fold_filters(plan: LogicalPlan) -> LogicalPlan {
+    let mut plan = plan;
+    if let Some(input) = plan.input_mut() {
+        let inner = std::mem::replace(input.as_mut(), LogicalPlan::Gather);
+        **input = fold_filters(inner);
+    }
+    match plan {
+        LogicalPlan::Filter { input, predicates } => match fold_predicates(predicates) {
+            Err(reason) => LogicalPlan::Empty { reason },
+            Ok(predicates) if predicates.is_empty() => *input,
+            Ok(predicates) => LogicalPlan::Filter { input, predicates },
+        },
+        other => other,
+    }
+}
+
+fn push_down_filters(plan: LogicalPlan) -> LogicalPlan {
+    let mut plan = plan;
+    if let Some(input) = plan.input_mut() {
+        let inner = std::mem::replace(input.as_mut(), LogicalPlan::Gather);
+        **input = push_down_filters(inner);
+    }
+    match plan {
+        LogicalPlan::Filter { input, predicates } => match *input {
+            LogicalPlan::Scan {
+                table,
+                join,
+                columns,
+                mut filters,
+            } => {
+                filters.extend(predicates);
+                LogicalPlan::Scan {
+                    table,
+                    join,
+                    columns,
+                    filters,
+                }
+            }
+            input => LogicalPlan::Filter {
+                input: Box::new(input),
+                predicates,
+            },
+        },
+        other => other,
+    }
+}
+
+/// Narrows the scan to the columns operators above it read.
+fn prune_columns(plan: LogicalPlan, mut needed: Vec<String>) -> LogicalPlan {
+    let push = |cols: &mut Vec<String>, c: &str| {
+        if !cols.iter().any(|x| x == c) {
+            cols.push(c.to_string());
+        }
+    };
+    match &plan {
+        LogicalPlan::Aggregate {
+            group_by,
+            aggregates,
+            ..
+        } => {
+            for g in group_by {
+                push(&mut needed, g);
+            }
+            for agg in aggregates {
+                if let Some(c) = &agg.column {
+                    push(&mut needed, c);
+                }
+                for f in &agg.filters {
+                    push(&mut needed, &f.column);
+                }
+            }
+        }
+        LogicalPlan::Window {
+            projections,
+            windows,
+            ..
+        } => {
+            for c in window_input_columns(projections, windows) {
+                push(&mut needed, &c);
+            }
+        }
+        LogicalPlan::Filter { predicates, .. } => {
+            for f in predicates {
+                push(&mut needed, &f.column);
+            }
+        }
+        _ => {}
+    }
+    match plan {
+        LogicalPlan::Scan {
+            table,
+            join,
+            filters,
+            ..
+        } => {
+            for f in &filters {
+                push(&mut needed, &f.column);
+            }
+            LogicalPlan::Scan {
+                table,
+                join,
+                columns: needed,
+                filters,
+            }
+        }
+        mut other => {
+            if let Some(input) = other.input_mut() {
+                let inner = std::mem::replace(input.as_mut(), LogicalPlan::Gather);
+                **input = prune_columns(inner, needed);
+            }
+            other
+        }
+    }
+}
+
+/// Range/value set a column is restricted to by AND-ed predicates.
+#[derive(Default)]
+struct ColumnRange {
+    lo: Option<(ScalarValue, bool)>, // (bound, inclusive)
+    hi: Option<(ScalarValue, bool)>,
+    values: Option<Vec<ScalarValue>>,
+}
+
+/// Merges the simple predicates on each column into the tightest equivalent
+/// ones (`a > 1 AND a >= 5` -> `a >= 5`, `a IN (1, 2) AND a = 2` -> `a = 2`),
+/// or explains why no row can match. Predicates with subqueries, unbound
+/// parameters, hashed `IN` lists or incomparable literals are kept as written.
+pub fn fold_predicates(predicates: Vec<FilterExpr>) -> Result<Vec<FilterExpr>, String> {
+    let foldable = |f: &FilterExpr| {
+        f.subquery.is_none()
+            && f.params.is_empty()
+            && match f.pred {
+                Predicate::In => matches!(f.list, Some(InList::Values(_))),
+                Predicate::Between => f.value_hi.is_some(),
+                _ => true,
+            }
+    };
+
+    let mut columns: Vec<String> = Vec::new();
+    for f in predicates.iter().filter(|f| foldable(f)) {
+        if !columns.contains(&f.column) {
+            columns.push(f.column.clone());
+        }
+    }
+
+    let mut out: Vec<FilterExpr> = predicates
+        .iter()
+        .filter(|f| !foldable(f))
+        .cloned()
+        .collect();
+    for column in columns {
+        let preds: Vec<&FilterExpr> = predicates
+            .iter()
+            .filter(|f| f.column == column && foldable(f))
+            .collect();
+        match column_range(&preds) {
+            None => out.extend(preds.into_iter().cloned()),
+            Some(range) => out.extend(range_predicates(&column, range)?),
+        }
+    }
+    Ok(out)
+}
+
+/// `None` when some literals cannot be ordered against each other.
+fn column_range(preds: &[&FilterExpr]) -> Option<ColumnRange> {
+    let mut range = ColumnRange::default();
+    for f in preds {
+        match f.pred {
+            Predicate::Eq => restrict_values(&mut range, vec![f.value.clone()])?,
+            Predicate::In => {
+                let Some(InList::Values(values)) = &f.list else {
+                    return None;
+                };
+                restrict_values(&mut range, values.clone())?
+            }
+            Predicate::Gt => tighten(&mut range.lo, &f.value, false, Ordering::Greater)?,
+            Predicate::Ge => tighten(&mut range.lo, &f.value, true, Ordering::Greater)?,
+            Predicate::Lt => tighten(&mut range.hi, &f.value, false, Ordering::Less)?,
+            Predicate::Le => tighten(&mut range.hi, &f.value, true, Ordering::Less)?,
+            Predicate::Between => {
+                tighten(&mut range.lo, &f.value, true, Ordering::Greater)?;
+                tighten(&mut range.hi, f.value_hi.as_ref()?, true, Ordering::Less)?;
+            }
+        }
+    }
+    Some(range)
+}
+
+fn restrict_values(range: &mut ColumnRange, values: Vec<ScalarValue>) -> Option<()> {
+    let mut distinct: Vec<ScalarValue> = Vec::new();
+    for v in values {
+        if !distinct.iter().any(|d| d.normalized() == v.normalized()) {
+            distinct.push(v);
+        }
+    }
+    range.values = Some(match range.values.take() {
+        None => distinct,
+        Some(current) => {
+            let mut kept = Vec::new();
+            for v in current {
+                let mut found = false;
+                for d in &distinct {
+                    if compare_scalar(&v, d)? == Ordering::Equal {
+                        found = true;
+                    }
+                }
+                if found {
+                    kept.push(v);
+                }
+            }
+            kept
+        }
+    });
+    Some(())
+}
+
+/// Replaces `bound` with `value` when that is stricter (`tighter` is the
+/// ordering a stricter bound has against the current one).
+fn tighten(
+    bound: &mut Option<(ScalarValue, bool)>,
+    value: &ScalarValue,
+    inclusive: bool,
+    tighter: Ordering,
+) -> Option<()> {
+    let replace = match bound {
+        None => true,
+        Some((current, current_inclusive)) => match compare_scalar(value, current)? {
+            Ordering::Equal => *current_inclusive && !inclusive,
+            ord => ord == tighter,
+        },
+    };
+    if replace {
+        *bound = Some((value.clone(), inclusive));
+    }
+    Some(())
+}
+
+fn range_predicates(column: &str, range: ColumnRange) -> Result<Vec<FilterExpr>, String> {
+    let empty = || format!("no value of '{}' satisfies the WHERE clause", column);
+    let filter = |pred: Predicate, value: ScalarValue| FilterExpr {
+        column: column.to_string(),
+        pred,
+        value,
+        value_hi: None,
+        list: None,
+        subquery: None,
+        params: Vec::new(),
+    };
+
+    if let (Some((lo, lo_inc)), Some((hi, hi_inc))) = (&range.lo, &range.hi) {
+        match compare_scalar(lo, hi) {
+            Some(Ordering::Greater) => return Err(empty()),
+            Some(Ordering::Equal) if !(*lo_inc && *hi_inc) => return Err(empty()),
+            _ => {}
+        }
+    }
+
+    if let Some(values) = range.values {
+        let in_range = |v: &ScalarValue| {
+            let above = range.lo.as_ref().is_none_or(|(lo, inc)| {
+                compare_scalar(v, lo).is_some_and(|o| o.is_gt() || (*inc && o.is_eq()))
+            });
+            let below = range.hi.as_ref().is_none_or(|(hi, inc)| {
+                compare_scalar(v, hi).is_some_and(|o| o.is_lt() || (*inc && o.is_eq()))
+            });
+            above && below
+        };
+        let mut values: Vec<ScalarValue> = values.into_iter().filter(in_range).collect();
+        return match values.len() {
+            0 => Err(empty()),
+            1 => Ok(vec![filter(Predicate::Eq, values.remove(0))]),
+            _ => {
+                let mut f = filter(Predicate::In, ScalarValue::Int(0));
+                f.list = Some(InList::Values(values));
+                Ok(vec![f])
+            }
+        };
+    }
+
+    match (range.lo, range.hi) {
+        (Some((lo, true)), Some((hi, true))) => {
+            if compare_scalar(&lo, &hi) == Some(Ordering::Equal) {
+                return Ok(vec![filter(Predicate::Eq, lo)]);
+            }
+            let mut f = filter(Predicate::Between, lo);
+            f.value_hi = Some(hi);
+            Ok(vec![f])
+        }
+        (lo, hi) => {
+            let mut out = Vec::new();
+            if let Some((v, inclusive)) = lo {
+                out.push(filter(
+                    if inclusive {
+                        Predicate::Ge
+                    } else {
+                        Predicate::Gt
+                    },
+                    v,
+                ));
+            }
+            if let Some((v, inclusive)) = hi {
+                out.push(filter(
+                    if inclusive {
+                        Predicate::Le
+                    } else {
+                        Predicate::Lt
+                    },
+                    v,
+                ));
+            }
+            Ok(out)
+        }
+    }
+}
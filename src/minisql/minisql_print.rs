@@ -1,136 +1,227 @@
-use crate::rpc::{AggregateState, GroupMap};
+use crate::minisql::minisql_eval::format_scalar;
+use crate::minisql::minisql_plan::LogicalPlan;
+use crate::minisql::minisql_window::window_column_name;
+use crate::rpc::{FilterExpr, GroupingExpr, InList, Predicate, Relation, ScalarValue};
 
-pub fn format_results(
-    cuml: GroupMap,
+/// Prints a result relation as a text table followed by execution details.
+pub fn format_relation(
+    rel: &Relation,
     rows_scanned: u64,
     segments_skipped: u64,
     exec_ms: u64,
-    group_by: &[String],
 ) -> String {
     let mut out = String::new();
-
-    if cuml.is_empty() {
+    if rel.rows.is_empty() {
         out.push_str("empty result\n");
     } else {
-        let mut group_keys: Vec<_> = cuml.keys().cloned().collect();
-        group_keys.sort();
-
-        // Determine headers
-        let mut agg_headers: Vec<String> = Vec::new();
-        if let Some(first_key) = group_keys.first() {
-            if let Some(first_map) = cuml.get(first_key) {
-                let mut keys: Vec<_> = first_map.keys().cloned().collect();
-                keys.sort();
-                agg_headers = keys;
-            }
-        }
-
-        let mut headers = Vec::new();
-        let include_group = !(group_keys.len() == 1 && group_keys[0] == "all");
-        if include_group {
-            let label = if group_by.is_empty() {
-                "group".to_string()
-            } else {
-                group_by.join(",")
-            };
-            headers.push(label);
-        }
-        headers.extend(
-            agg_headers
-                .iter()
-                .map(|h| normalize_header(h))
-                .collect::<Vec<_>>(),
-        );
-
-        // Build rows
-        let mut rows: Vec<Vec<String>> = Vec::new();
-        for gk in group_keys {
-            let agg_map = cuml.get(&gk).unwrap();
-            let mut row_vals: Vec<String> = Vec::new();
-            if include_group {
-                row_vals.push(gk.clone());
-            }
-            for raw_name in &agg_headers {
-                if let Some(state) = agg_map.get(raw_name) {
-                    row_vals.push(render_state_value(raw_name, state));
-                } else {
-                    row_vals.push(String::new());
-                }
-            }
-            rows.push(row_vals);
-        }
+        let rows: Vec<Vec<String>> = rel
+            .rows
+            .iter()
+            .map(|row| row.iter().map(render_scalar).collect())
+            .collect();
+        out.push_str(&render_table(&rel.columns, &rows));
+    }
+    out.push_str(&footer(rows_scanned, segments_skipped, exec_ms));
+    out
+}
 
-        // Column widths
-        let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
-        for row in &rows {
-            for (i, val) in row.iter().enumerate() {
-                widths[i] = widths[i].max(val.len());
-            }
-        }
+fn render_scalar(v: &Option<ScalarValue>) -> String {
+    match v {
+        Some(ScalarValue::Float(f)) => format!("{:.3}", f),
+        other => format_scalar(other),
+    }
+}
 
-        // Header row
-        out.push_str(&format_row(&headers, &widths));
-        // Separator
-        let sep: Vec<String> = widths.iter().map(|w| "-".repeat(*w.max(&3))).collect();
-        out.push_str(&sep.join("-+-"));
-        out.push('\n');
-        // Data rows
-        for row in rows {
-            out.push_str(&format_row(&row, &widths));
+fn render_table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut out = String::new();
+    // Column widths
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (i, val) in row.iter().enumerate() {
+            widths[i] = widths[i].max(val.len());
         }
     }
 
+    // Header row
+    out.push_str(&format_row(headers, &widths));
+    // Separator
+    let sep: Vec<String> = widths.iter().map(|w| "-".repeat(*w.max(&3))).collect();
+    out.push_str(&sep.join("-+-"));
     out.push('\n');
-    out.push_str(&format!(
-        "Execution Details:\n\
+    // Data rows
+    for row in rows {
+        out.push_str(&format_row(row, &widths));
+    }
+    out
+}
+
+fn footer(rows_scanned: u64, segments_skipped: u64, exec_ms: u64) -> String {
+    format!(
+        "\nExecution Details:\n\
          Rows scanned:       {}\n\
          Segments skipped:   {}\n\
          Execution time:     {} ms",
         rows_scanned, segments_skipped, exec_ms
-    ));
-    out
+    )
 }
 
-fn render_state_value(name: &str, state: &AggregateState) -> String {
-    let upper = name.to_ascii_uppercase();
-    if upper.starts_with("COUNT") {
-        return state.count.to_string();
-    }
-    if upper.starts_with("SUM") {
-        return match state.value_type {
-            crate::rpc::ValueType::Int => format!("{}", state.sum as i64),
-            crate::rpc::ValueType::Float => format!("{:.3}", state.sum),
-        };
+/// A plan as an indented tree, one operator per line, root first.
+pub fn format_plan(plan: &LogicalPlan, indent: usize) -> String {
+    let mut out = String::new();
+    let mut node = Some(plan);
+    let mut depth = indent;
+    while let Some(n) = node {
+        out.push_str(&" ".repeat(depth));
+        out.push_str(&format_plan_node(n));
+        out.push('\n');
+        node = n.input();
+        depth += 2;
     }
-    if upper.starts_with("AVG") {
-        if state.count == 0 {
-            return "NULL".into();
+    out
+}
+
+/// One plan operator, e.g. `Scan sales [region, amount] WHERE amount > 20`.
+pub fn format_plan_node(plan: &LogicalPlan) -> String {
+    match plan {
+        LogicalPlan::Scan {
+            table,
+            join,
+            columns,
+            filters,
+        } => {
+            let mut out = format!("Scan {}", table_name(table));
+            if let Some(join) = join {
+                out.push_str(&format!(
+                    " JOIN {} ON {}.{} = {}.{}",
+                    join.right_name, join.left_name, join.left_key, join.right_name, join.right_key
+                ));
+            }
+            out.push_str(&format!(" [{}]", columns.join(", ")));
+            if !filters.is_empty() {
+                out.push_str(&format!(" WHERE {}", format_filters(filters)));
+            }
+            out
+        }
+        LogicalPlan::Filter { predicates, .. } => format!("Filter {}", format_filters(predicates)),
+        LogicalPlan::Aggregate {
+            group_by,
+            aggregates,
+            grouping_sets,
+            ..
+        } => {
+            let mut out = "Aggregate".to_string();
+            if !aggregates.is_empty() {
+                let aggs: Vec<String> = aggregates
+                    .iter()
+                    .map(|a| match &a.alias {
+                        Some(alias) => format!("{} AS {}", a.output_name, alias),
+                        None => a.output_name.clone(),
+                    })
+                    .collect();
+                out.push_str(&format!(" [{}]", aggs.join(", ")));
+            }
+            if !grouping_sets.is_empty() {
+                let sets: Vec<String> = grouping_sets
+                    .iter()
+                    .map(|s| format!("({})", s.join(", ")))
+                    .collect();
+                out.push_str(&format!(" GROUPING SETS ({})", sets.join(", ")));
+            } else if !group_by.is_empty() {
+                out.push_str(&format!(" GROUP BY {}", group_by.join(", ")));
+            }
+            out
+        }
+        LogicalPlan::Window { windows, .. } => {
+            let names: Vec<String> = windows.iter().map(window_column_name).collect();
+            format!("Window [{}]", names.join(", "))
+        }
+        LogicalPlan::Project { columns, .. } => format!("Project [{}]", columns.join(", ")),
+        LogicalPlan::Sort { order_by, .. } => {
+            let items: Vec<String> = order_by
+                .iter()
+                .map(|o| {
+                    let dir = if o.descending { " DESC" } else { "" };
+                    format!("{}{}", o.column, dir)
+                })
+                .collect();
+            format!("Sort [{}]", items.join(", "))
         }
-        return format!("{:.3}", state.sum / state.count as f64);
+        LogicalPlan::Limit { count, .. } => format!("Limit {}", count),
+        LogicalPlan::Empty { reason } => format!("Empty ({})", reason),
+        LogicalPlan::Gather => "Gather".to_string(),
     }
-    if upper.starts_with("MIN") {
-        return match state.min {
-            Some(v) => match state.value_type {
-                crate::rpc::ValueType::Int => format!("{}", v as i64),
-                crate::rpc::ValueType::Float => format!("{:.3}", v),
-            },
-            None => "NULL".into(),
-        };
+}
+
+/// Last path component of a resolved table directory.
+fn table_name(table: &str) -> &str {
+    std::path::Path::new(table)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(table)
+}
+
+fn format_filters(filters: &[FilterExpr]) -> String {
+    let parts: Vec<String> = filters.iter().map(format_filter).collect();
+    parts.join(" AND ")
+}
+
+/// A filter as SQL, e.g. `amount > 20` or `region IN ('EU', 'US')`.
+pub fn format_filter(f: &FilterExpr) -> String {
+    let value = |v: &ScalarValue| match v {
+        ScalarValue::String(s) => format!("'{}'", s),
+        other => format_scalar(&Some(other.clone())),
+    };
+    if f.subquery.is_some() {
+        let op = if f.pred == Predicate::In { "IN" } else { "=" };
+        return format!("{} {} (subquery)", f.column, op);
     }
-    if upper.starts_with("MAX") {
-        return match state.max {
-            Some(v) => match state.value_type {
-                crate::rpc::ValueType::Int => format!("{}", v as i64),
-                crate::rpc::ValueType::Float => format!("{:.3}", v),
-            },
-            None => "NULL".into(),
-        };
+    match f.pred {
+        Predicate::Eq => format!("{} = {}", f.column, value(&f.value)),
+        Predicate::Lt => format!("{} < {}", f.column, value(&f.value)),
+        Predicate::Gt => format!("{} > {}", f.column, value(&f.value)),
+        Predicate::Le => format!("{} <= {}", f.column, value(&f.value)),
+        Predicate::Ge => format!("{} >= {}", f.column, value(&f.value)),
+        Predicate::Between => match &f.value_hi {
+            Some(hi) => format!("{} BETWEEN {} AND {}", f.column, value(&f.value), value(hi)),
+            None => format!("{} >= {}", f.column, value(&f.value)),
+        },
+        Predicate::In => {
+            let mut values: Vec<String> = match &f.list {
+                Some(InList::Values(vs)) => vs.iter().map(value).collect(),
+                Some(InList::Hashed(vs)) => vs.iter().map(value).collect(),
+                None => Vec::new(),
+            };
+            // Hashed lists have no order; keep the output stable.
+            if matches!(f.list, Some(InList::Hashed(_))) {
+                values.sort();
+            }
+            format!("{} IN ({})", f.column, values.join(", "))
+        }
     }
-    // Fallback: show count
-    state.count.to_string()
 }
 
-fn normalize_header(raw: &str) -> String {
+/// Column name for `GROUPING(a, b)`: its alias, or `grouping_a_b`.
+pub fn grouping_column_name(g: &GroupingExpr) -> String {
+    g.alias
+        .clone()
+        .unwrap_or_else(|| format!("grouping_{}", g.columns.join("_")))
+}
+
+/// Column name for an aggregate output, e.g. `SUM(amount)` -> `sum_amount`,
+/// `COUNT(*) FILTER (WHERE active)` -> `count_star_where_active`.
+pub fn normalize_header(raw: &str) -> String {
+    if let Some((agg, filter)) = raw.split_once(" FILTER ") {
+        let mut out = normalize_header(agg);
+        for word in filter
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .filter(|w| !w.is_empty())
+        {
+            out.push('_');
+            out.push_str(word);
+        }
+        return out;
+    }
     let lower = raw.to_ascii_lowercase();
     if lower.contains('(') {
         lower
@@ -0,0 +1,192 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::minisql::minisql_eval::{apply_agg, compare_nullable, finalize_state};
+use crate::rpc::{
+    AggregateExpr, AggregateState, QueryRequest, Relation, ScalarValue, WindowExpr, WindowFn,
+};
+
+type Row = Vec<Option<ScalarValue>>;
+
+/// Columns a window query reads: the projected columns, then every column
+/// the window functions refer to.
+pub fn window_input_columns(projections: &[String], windows: &[WindowExpr]) -> Vec<String> {
+    let mut cols: Vec<String> = Vec::new();
+    let mut push = |c: &str| {
+        if !cols.iter().any(|x| x == c) {
+            cols.push(c.to_string());
+        }
+    };
+    for p in projections {
+        push(p);
+    }
+    for w in windows {
+        if let Some(c) = &w.column {
+            push(c);
+        }
+        for c in &w.partition_by {
+            push(c);
+        }
+        for o in &w.order_by {
+            push(&o.column);
+        }
+    }
+    cols
+}
+
+/// Result column name of a window function: its alias, or e.g. `rank`,
+/// `lag_amount`, `sum_amount`.
+pub fn window_column_name(w: &WindowExpr) -> String {
+    if let Some(alias) = &w.alias {
+        return alias.clone();
+    }
+    let func = match w.func {
+        WindowFn::RowNumber => "row_number".to_string(),
+        WindowFn::Rank => "rank".to_string(),
+        WindowFn::Lag => "lag".to_string(),
+        WindowFn::Lead => "lead".to_string(),
+        WindowFn::Aggregate(f) => format!("{:?}", f).to_ascii_lowercase(),
+    };
+    match &w.column {
+        Some(c) => format!("{}_{}", func, c.to_ascii_lowercase()),
+        None if matches!(w.func, WindowFn::Aggregate(_)) => format!("{}_star", func),
+        None => func,
+    }
+}
+
+/// Evaluates the window functions of `req` over `input` (laid out as
+/// `window_input_columns`). Rows keep their input order; the output holds the
+/// projected columns followed by one column per window function.
+pub fn eval_windows(input: &Relation, req: &QueryRequest) -> Relation {
+    let idx = |name: &str| input.columns.iter().position(|c| c == name);
+    let computed: Vec<Vec<Option<ScalarValue>>> = req
+        .windows
+        .iter()
+        .map(|w| eval_window(&input.rows, w, &idx))
+        .collect();
+
+    let proj_idx: Vec<Option<usize>> = req.projections.iter().map(|p| idx(p)).collect();
+    let mut columns = req.projections.clone();
+    columns.extend(req.windows.iter().map(window_column_name));
+    let rows = input
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            let mut out: Row = proj_idx
+                .iter()
+                .map(|i| i.and_then(|i| row[i].clone()))
+                .collect();
+            out.extend(computed.iter().map(|values| values[r].clone()));
+            out
+        })
+        .collect();
+    Relation { columns, rows }
+}
+
+fn eval_window(
+    rows: &[Row],
+    w: &WindowExpr,
+    idx: &dyn Fn(&str) -> Option<usize>,
+) -> Vec<Option<ScalarValue>> {
+    let part: Vec<Option<usize>> = w.partition_by.iter().map(|c| idx(c)).collect();
+    let order: Vec<(Option<usize>, bool)> = w
+        .order_by
+        .iter()
+        .map(|o| (idx(&o.column), o.descending))
+        .collect();
+    let arg = w.column.as_deref().and_then(idx);
+    let value = |row: &Row, i: Option<usize>| i.and_then(|i| row[i].clone());
+
+    let cmp_part = |a: &Row, b: &Row| cmp_keys(a, b, part.iter().map(|i| (*i, false)));
+    let cmp_order = |a: &Row, b: &Row| cmp_keys(a, b, order.iter().copied());
+
+    // Stable sort, so rows that tie keep their scan (key) order.
+    let mut sorted: Vec<usize> = (0..rows.len()).collect();
+    sorted
+        .sort_by(|&a, &b| cmp_part(&rows[a], &rows[b]).then_with(|| cmp_order(&rows[a], &rows[b])));
+
+    let mut out = vec![None; rows.len()];
+    let mut start = 0;
+    while start < sorted.len() {
+        let mut end = start + 1;
+        while end < sorted.len() && cmp_part(&rows[sorted[start]], &rows[sorted[end]]).is_eq() {
+            end += 1;
+        }
+        let partition = &sorted[start..end];
+
+        match w.func {
+            WindowFn::RowNumber => {
+                for (pos, &r) in partition.iter().enumerate() {
+                    out[r] = Some(ScalarValue::Int(pos as i64 + 1));
+                }
+            }
+            WindowFn::Rank => {
+                let mut rank = 1;
+                for (pos, &r) in partition.iter().enumerate() {
+                    if pos > 0 && cmp_order(&rows[partition[pos - 1]], &rows[r]).is_ne() {
+                        rank = pos + 1;
+                    }
+                    out[r] = Some(ScalarValue::Int(rank as i64));
+                }
+            }
+            WindowFn::Lag | WindowFn::Lead => {
+                for (pos, &r) in partition.iter().enumerate() {
+                    let other = if w.func == WindowFn::Lag {
+                        pos.checked_sub(w.offset)
+                    } else {
+                        Some(pos + w.offset).filter(|p| *p < partition.len())
+                    };
+                    out[r] = other.and_then(|p| value(&rows[partition[p]], arg));
+                }
+            }
+            WindowFn::Aggregate(func) => {
+                // Running aggregate; rows that tie on ORDER BY (peers) share
+                // the value reached after the last of them.
+                let expr = AggregateExpr {
+                    func,
+                    column: w.column.clone(),
+                    output_name: String::new(),
+                    alias: None,
+                    filters: Vec::new(),
+                };
+                let mut state = AggregateState::default();
+                let mut peer_start = 0;
+                while peer_start < partition.len() {
+                    let mut peer_end = peer_start + 1;
+                    while peer_end < partition.len()
+                        && cmp_order(&rows[partition[peer_start]], &rows[partition[peer_end]])
+                            .is_eq()
+                    {
+                        peer_end += 1;
+                    }
+                    for &r in &partition[peer_start..peer_end] {
+                        let mut row = HashMap::new();
+                        if let Some(c) = &w.column {
+                            row.insert(c.clone(), value(&rows[r], arg));
+                        }
+                        apply_agg(&mut state, &expr, &row);
+                    }
+                    let result = finalize_state(func, &state);
+                    for &r in &partition[peer_start..peer_end] {
+                        out[r] = result.clone();
+                    }
+                    peer_start = peer_end;
+                }
+            }
+        }
+        start = end;
+    }
+    out
+}
+
+fn cmp_keys(a: &Row, b: &Row, keys: impl Iterator<Item = (Option<usize>, bool)>) -> Ordering {
+    for (i, descending) in keys {
+        let Some(i) = i else { continue };
+        let ord = compare_nullable(&a[i], &b[i], descending);
+        if ord.is_ne() {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
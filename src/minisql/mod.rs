@@ -1,3 +1,8 @@
+pub mod minisql_bind;
+pub mod minisql_error;
 pub mod minisql_eval;
+pub mod minisql_params;
 pub mod minisql_parse;
+pub mod minisql_plan;
 pub mod minisql_print;
+pub mod minisql_window;
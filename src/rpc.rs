@@ -1,3 +1,4 @@
+use crate::storage::storage_schema::ColumnType;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,7 +16,49 @@ pub enum ScalarValue {
     Bool(bool),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+// Structural equality (floats by bit pattern) so values can be used as hash
+// keys; SQL comparison semantics live in `minisql_eval::cmp_eq`.
+impl PartialEq for ScalarValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ScalarValue::Int(a), ScalarValue::Int(b)) => a == b,
+            (ScalarValue::Float(a), ScalarValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (ScalarValue::String(a), ScalarValue::String(b)) => a == b,
+            (ScalarValue::Bool(a), ScalarValue::Bool(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ScalarValue {}
+
+impl std::hash::Hash for ScalarValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            ScalarValue::Int(i) => i.hash(state),
+            ScalarValue::Float(f) => f.to_bits().hash(state),
+            ScalarValue::String(s) => s.hash(state),
+            ScalarValue::Bool(b) => b.hash(state),
+        }
+    }
+}
+
+impl ScalarValue {
+    /// Integral floats become ints, so that `1` and `1.0` hash alike.
+    pub fn normalized(&self) -> ScalarValue {
+        match self {
+            ScalarValue::Float(f)
+                if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f <= i64::MAX as f64 =>
+            {
+                ScalarValue::Int(*f as i64)
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum Predicate {
     Eq,
     Lt,
@@ -23,9 +66,18 @@ pub enum Predicate {
     Le,
     Ge,
     Between,
+    In,
+}
+
+/// Values of an `IN (...)` predicate. Large lists are shipped to workers as
+/// a hash set of normalized values.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum InList {
+    Values(Vec<ScalarValue>),
+    Hashed(std::collections::HashSet<ScalarValue>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum AggregateFn {
     Count,
     Sum,
@@ -34,12 +86,35 @@ pub enum AggregateFn {
     Max,
 }
 
+/// Which literal of a filter a `$n`/`?` placeholder stands for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ParamSlot {
+    Value,
+    ValueHi,
+    ListItem(usize),
+}
+
+/// A placeholder in a filter; `index` is 0-based (`$1` is 0).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ParamRef {
+    pub slot: ParamSlot,
+    pub index: usize,
+    // Type of the filtered column, set by the binder; `bind_params`
+    // converts the value to it.
+    pub column_type: Option<ColumnType>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FilterExpr {
     pub column: String,
     pub pred: Predicate,
     pub value: ScalarValue,
     pub value_hi: Option<ScalarValue>, // used for BETWEEN
+    pub list: Option<InList>,          // used for IN
+    // Uncorrelated subquery, replaced by `value`/`list` on the coordinator.
+    pub subquery: Option<Box<QueryRequest>>,
+    // Placeholders still waiting for a value (see `minisql_params`).
+    pub params: Vec<ParamRef>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,9 +122,105 @@ pub struct AggregateExpr {
     pub func: AggregateFn,
     pub column: Option<String>, // None for COUNT(*)
     pub output_name: String,
+    pub alias: Option<String>,
+    pub filters: Vec<FilterExpr>, // FILTER (WHERE ...)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFn {
+    RowNumber,
+    Rank,
+    Lag,
+    Lead,
+    Aggregate(AggregateFn), // running aggregate over the window
+}
+
+/// `func(...) OVER (PARTITION BY ... ORDER BY ...)`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WindowExpr {
+    pub func: WindowFn,
+    pub column: Option<String>,
+    pub offset: usize, // LAG/LEAD distance
+    pub partition_by: Vec<String>,
+    pub order_by: Vec<OrderByExpr>,
+    pub alias: Option<String>,
+}
+
+/// `GROUPING(a, b, ...)`: bit i (from the left) is set when the i-th
+/// column is rolled up in the current grouping set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupingExpr {
+    pub columns: Vec<String>,
+    pub alias: Option<String>,
+}
+
+/// `ORDER BY` item, naming a column of the result.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderByExpr {
+    pub column: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    UnionAll,
+    Union,
+    Intersect,
+    Except,
 }
 
+/// A SELECT combined with the preceding ones, e.g. `UNION ALL SELECT ...`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetOpBranch {
+    pub op: SetOp,
+    pub query: QueryRequest,
+}
+
+/// Non-recursive common table expression (`WITH name AS (...)`).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CteDef {
+    pub name: String,
+    pub query: QueryRequest,
+    // Rows the coordinator already has, e.g. `information_schema.columns`;
+    // `query` is then not run.
+    #[serde(default)]
+    pub rows: Option<Relation>,
+}
+
+impl CteDef {
+    /// A CTE over known rows.
+    pub fn rows(name: &str, relation: Relation) -> CteDef {
+        CteDef {
+            name: name.to_string(),
+            query: QueryRequest {
+                table: name.to_string(),
+                output: relation.columns.clone(),
+                ..QueryRequest::default()
+            },
+            rows: Some(relation),
+        }
+    }
+}
+
+/// Inner equi-join on the key columns of two co-located tables.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JoinSpec {
+    pub left_name: String,
+    pub right_name: String,
+    pub right_table: String, // resolved to a directory by the coordinator
+    pub left_key: String,
+    pub right_key: String,
+}
+
+/// `EXPLAIN` shows the plan; `EXPLAIN ANALYZE` runs the query and reports
+/// what each worker and operator did.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Explain {
+    Plan,
+    Analyze,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct QueryRequest {
     pub query: String,
     pub projections: Vec<String>,
@@ -57,6 +228,70 @@ pub struct QueryRequest {
     pub table: String,
     pub filters: Vec<FilterExpr>,
     pub group_by: Vec<String>,
+    pub join: Option<JoinSpec>,
+    pub grouping_sets: Vec<Vec<String>>, // empty unless ROLLUP/CUBE/GROUPING SETS
+    pub grouping: Vec<GroupingExpr>,
+    pub windows: Vec<WindowExpr>,
+    pub window_local: bool,  // windows evaluated per segment by workers
+    pub output: Vec<String>, // result column names, in SELECT order
+    pub ctes: Vec<CteDef>,
+    pub set_ops: Vec<SetOpBranch>,
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<u64>,
+    pub scan_columns: Vec<String>, // set by the planner; empty = derive on the worker
+    pub explain: Option<Explain>,
+    pub mutation: Option<Mutation>, // rows matching the filters are changed
+    pub parts: Option<Vec<u32>>,    // segment dirs to scan; None = base and deltas
+}
+
+/// What a worker does to the rows a `DELETE` or `UPDATE` matches. The
+/// request still aggregates `COUNT(*)`, which becomes the number of rows
+/// changed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum Mutation {
+    Delete,
+    Update(Vec<Assignment>),
+}
+
+/// `column = value` of an `UPDATE`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Assignment {
+    pub column: String,
+    pub value: AssignedValue,
+}
+
+/// New value of an updated column, computed from the row before the update.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum AssignedValue {
+    Value(Option<ScalarValue>), // converted to the column type; None is NULL
+    Column(String),             // the row's value of another column
+    // `column op value` on a numeric column; NULL stays NULL
+    Arithmetic {
+        column: String,
+        op: ArithOp,
+        value: ScalarValue,
+    },
+}
+
+/// Operator of `SET c = column op value`. Integers stay integers (`/`
+/// truncates) unless either side is a float.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -68,27 +303,38 @@ pub struct AggregateState {
     pub value_type: ValueType,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub enum ValueType {
     Int,
+    #[default]
     Float,
 }
 
-impl Default for ValueType {
-    fn default() -> Self {
-        ValueType::Float
-    }
+/// Materialized result set held on the coordinator, e.g. for a CTE.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Relation {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Option<ScalarValue>>>,
 }
 
 pub type GroupAggregate = std::collections::HashMap<String, AggregateState>;
-pub type GroupMap = std::collections::HashMap<String, GroupAggregate>;
+/// Values of the GROUP BY columns, in order (empty when not grouping).
+pub type GroupKey = Vec<Option<ScalarValue>>;
+pub type GroupMap = std::collections::HashMap<GroupKey, GroupAggregate>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PartialAggregate {
     pub worker_port: u16,
     pub segment: u32,
     pub rows_scanned: u64,
+    pub rows_matched: u64, // rows that passed the filters
+    pub bytes_read: u64,   // size of the column files scanned
     pub segments_skipped: u64,
-    pub exec_ms: u64,
+    pub skip_reason: Option<String>, // why the segment was not scanned
+    pub exec_us: u64,                // time spent on the worker
     pub groups: GroupMap,
+    pub grouping_groups: Vec<GroupMap>, // one per grouping set
+    pub rows: Option<Relation>,         // row output of window queries
+    #[serde(default)]
+    pub error: Option<String>, // the worker failed, e.g. writing a mutation
 }
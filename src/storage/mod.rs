@@ -1,4 +1,11 @@
+pub mod storage_alter;
+pub mod storage_append;
+pub mod storage_catalog;
+pub mod storage_deletion;
 pub mod storage_init;
 pub mod storage_inspect;
 pub mod storage_load;
+pub mod storage_rewrite;
 pub mod storage_schema;
+pub mod storage_segment;
+pub mod storage_view;
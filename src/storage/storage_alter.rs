@@ -0,0 +1,151 @@
+use crate::storage::storage_deletion::DELETED_FILE;
+use crate::storage::storage_schema::{ColumnDef, format_schema, validate_schema};
+use crate::storage::storage_segment::{data_dir, list_segments, segment_dir_name};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// A change `ALTER TABLE` makes to a schema.
+#[derive(Debug, Clone)]
+pub enum SchemaChange {
+    AddColumn(ColumnDef),
+    DropColumn(String),
+    RenameColumn { from: String, to: String },
+}
+
+/// The schema after `change`. Segments are never rewritten for it: an
+/// added column has no file in existing segments (scans read its default),
+/// a dropped column's files stay until their segment is rewritten, and a
+/// renamed column keeps its file (`ColumnDef::file`).
+pub fn altered_schema(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    change: &SchemaChange,
+) -> Result<Vec<ColumnDef>, String> {
+    let mut columns = schema.to_vec();
+    let position = |name: &str| {
+        schema
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| format!("Unknown column '{}'", name))
+    };
+    match change {
+        SchemaChange::AddColumn(col) => {
+            if schema.iter().any(|c| c.name == col.name) {
+                return Err(format!("Column '{}' already exists", col.name));
+            }
+            if col.is_key {
+                return Err("Cannot add a key column".into());
+            }
+            if !col.nullable && col.default.is_none() {
+                return Err(format!(
+                    "Column '{}' is NOT NULL, so existing rows need a DEFAULT",
+                    col.name
+                ));
+            }
+            let mut col = col.clone();
+            // Files of a dropped or renamed column may still use the name.
+            let used = file_stems(table_dir, schema)?;
+            let mut stem = col.name.clone();
+            for n in 1.. {
+                if !used.contains(&stem) {
+                    break;
+                }
+                stem = format!("{}~{}", col.name, n);
+            }
+            col.file = (stem != col.name).then_some(stem);
+            columns.push(col);
+        }
+        SchemaChange::DropColumn(name) => {
+            let idx = position(name)?;
+            if schema[idx].is_key {
+                return Err(format!("Cannot drop the key column '{}'", name));
+            }
+            columns.remove(idx);
+        }
+        SchemaChange::RenameColumn { from, to } => {
+            let idx = position(from)?;
+            if from != to && schema.iter().any(|c| &c.name == to) {
+                return Err(format!("Column '{}' already exists", to));
+            }
+            let col = &mut columns[idx];
+            let stem = col.file.take().unwrap_or_else(|| col.name.clone());
+            col.file = (&stem != to).then_some(stem);
+            col.name = to.clone();
+        }
+    }
+    validate_schema(&columns)?;
+    Ok(columns)
+}
+
+/// Replaces `_schema.ssf` in one rename and bumps `schema_version` in
+/// `_table.txt`. Returns the new version.
+pub fn write_schema(table_dir: &Path, schema: &[ColumnDef]) -> Result<u64, String> {
+    replace_file(table_dir, "_schema.ssf", &format_schema(schema))?;
+
+    let path = table_dir.join("_table.txt");
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read table metadata {:?}: {}", path, e))?;
+    let version = schema_version(&contents)? + 1;
+    set_table_property(table_dir, "schema_version", Some(&version.to_string()))?;
+    Ok(version)
+}
+
+/// Value of `key` in a `_table.txt`.
+pub fn table_property<'a>(table_txt: &'a str, key: &str) -> Option<&'a str> {
+    table_txt.lines().find_map(|line| {
+        line.split_once('=')
+            .filter(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim())
+    })
+}
+
+/// Sets (or with `None` removes) `key` in `_table.txt`, creating the file
+/// if needed, and replaces it in one rename.
+pub fn set_table_property(table_dir: &Path, key: &str, value: Option<&str>) -> Result<(), String> {
+    let contents = fs::read_to_string(table_dir.join("_table.txt")).unwrap_or_default();
+    let mut out: String = contents
+        .lines()
+        .filter(|l| l.split_once('=').is_none_or(|(k, _)| k.trim() != key))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    if let Some(value) = value {
+        out.push_str(&format!("{}={}\n", key, value));
+    }
+    replace_file(table_dir, "_table.txt", &out)
+}
+
+/// `schema_version` of a `_table.txt`; tables that never changed their
+/// schema may not have one and are at version 1.
+pub fn schema_version(table_txt: &str) -> Result<u64, String> {
+    table_property(table_txt, "schema_version").map_or(Ok(1), |v| {
+        v.parse()
+            .map_err(|_| "Invalid numeric value for 'schema_version'".to_string())
+    })
+}
+
+/// File stems taken by the schema's columns or by files in the current
+/// version of any segment.
+fn file_stems(table_dir: &Path, schema: &[ColumnDef]) -> Result<HashSet<String>, String> {
+    let mut used: HashSet<String> = schema
+        .iter()
+        .map(|c| c.file.clone().unwrap_or_else(|| c.name.clone()))
+        .collect();
+    used.insert(DELETED_FILE.trim_end_matches(".bin").to_string());
+    for (number, meta) in list_segments(table_dir)? {
+        let dir = data_dir(&table_dir.join(segment_dir_name(number)), &meta);
+        let entries = fs::read_dir(&dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+        for entry in entries.flatten() {
+            if let Some(stem) = entry.file_name().to_string_lossy().strip_suffix(".bin") {
+                used.insert(stem.to_string());
+            }
+        }
+    }
+    Ok(used)
+}
+
+fn replace_file(dir: &Path, name: &str, contents: &str) -> Result<(), String> {
+    let tmp = dir.join(format!(".{}.tmp", name));
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, dir.join(name)).map_err(|e| format!("Failed to replace {}: {}", name, e))
+}
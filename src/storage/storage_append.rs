@@ -0,0 +1,276 @@
+use crate::minisql::minisql_eval::{compare_scalar, init_reader, read_value};
+use crate::rpc::ScalarValue;
+use crate::storage::storage_schema::{ColumnDef, ColumnType};
+use crate::storage::storage_segment::{
+    SegmentMeta, base_segment_count, list_segments, segment_dir_name, segment_parts,
+    write_segment_meta,
+};
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// A row in schema order.
+pub type StoredRow = Vec<Option<ScalarValue>>;
+
+/// Attempts at claiming a segment number when other writers race for it.
+const CLAIM_ATTEMPTS: usize = 16;
+
+/// Appends rows (already converted to the column types) as sorted delta
+/// segments, at most one per base segment. Rows go to the base segment
+/// whose key range holds their key, so co-located tables stay co-located.
+/// Every delta becomes visible with one directory rename. Returns the
+/// numbers of the new segments.
+pub fn append_rows(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    mut rows: Vec<StoredRow>,
+) -> Result<Vec<u32>, String> {
+    let key_idx = check_rows(schema, &rows)?;
+    rows.sort_by(|a, b| compare_keys(&a[key_idx], &b[key_idx]));
+
+    let segments = base_segment_count(table_dir)?;
+    if segments == 0 {
+        return Err(format!("Table {:?} has no segments", table_dir));
+    }
+    let bounds = lower_bounds(table_dir, &schema[key_idx], segments)?;
+    let assignment = if bounds.iter().all(Option::is_none) {
+        split_evenly(&rows, key_idx, segments)
+    } else {
+        rows.iter()
+            .map(|row| {
+                bounds
+                    .iter()
+                    .rposition(|b| {
+                        b.as_ref()
+                            .is_some_and(|b| compare_keys(&Some(b.clone()), &row[key_idx]).is_le())
+                    })
+                    .unwrap_or(0)
+            })
+            .collect()
+    };
+
+    let mut per_segment: Vec<Vec<StoredRow>> = vec![Vec::new(); segments];
+    for (row, seg) in rows.into_iter().zip(assignment) {
+        per_segment[seg].push(row);
+    }
+    let mut written = Vec::new();
+    for (base, rows) in per_segment.into_iter().enumerate() {
+        if !rows.is_empty() {
+            written.push(write_delta(table_dir, schema, base as u32, &rows)?);
+        }
+    }
+    Ok(written)
+}
+
+/// Writes the rows of a table without segments (`CREATE TABLE ... AS`) as
+/// its base segments, sorted by key and split like `load_table` splits a
+/// CSV.
+pub fn load_rows(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    segments: usize,
+    mut rows: Vec<StoredRow>,
+) -> Result<(), String> {
+    if segments == 0 {
+        return Err("segments must be > 0".into());
+    }
+    let key_idx = check_rows(schema, &rows)?;
+    rows.sort_by(|a, b| compare_keys(&a[key_idx], &b[key_idx]));
+    let assignment = split_evenly(&rows, key_idx, segments);
+    let mut per_segment: Vec<Vec<StoredRow>> = vec![Vec::new(); segments];
+    for (row, seg) in rows.into_iter().zip(assignment) {
+        per_segment[seg].push(row);
+    }
+    for (seg, rows) in per_segment.iter().enumerate() {
+        let dir = table_dir.join(segment_dir_name(seg as u32));
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create segment dir {:?}: {}", dir, e))?;
+        write_part(&dir, schema, rows)?;
+    }
+    Ok(())
+}
+
+/// Checks the row width and NOT NULL columns; returns the key position.
+fn check_rows(schema: &[ColumnDef], rows: &[StoredRow]) -> Result<usize, String> {
+    let key_idx = schema
+        .iter()
+        .position(|c| c.is_key)
+        .ok_or("Schema has no column marked as `key`")?;
+    for row in rows {
+        if row.len() != schema.len() {
+            return Err(format!(
+                "Row has {} values, schema has {} columns",
+                row.len(),
+                schema.len()
+            ));
+        }
+        for (value, col) in row.iter().zip(schema) {
+            if value.is_none() && (!col.nullable || col.is_key) {
+                return Err(format!("Column '{}' is NOT NULL", col.name));
+            }
+        }
+    }
+    Ok(key_idx)
+}
+
+/// The smallest key of every base segment (over the segment and its
+/// deltas); `None` for segments without rows.
+fn lower_bounds(
+    table_dir: &Path,
+    key: &ColumnDef,
+    segments: usize,
+) -> Result<Vec<Option<ScalarValue>>, String> {
+    let mut bounds = Vec::with_capacity(segments);
+    for seg in 0..segments as u32 {
+        let mut bound: Option<ScalarValue> = None;
+        for part in segment_parts(table_dir, seg) {
+            let path = part.join(key.file_name());
+            let mut reader = init_reader(&path, key)
+                .ok_or_else(|| format!("Failed to open key column {:?}", path))?;
+            if let Ok(Some(first)) = read_value(&mut reader, key)
+                && bound
+                    .as_ref()
+                    .is_none_or(|b| compare_keys(&Some(first.clone()), &Some(b.clone())).is_lt())
+            {
+                bound = Some(first);
+            }
+        }
+        bounds.push(bound);
+    }
+    Ok(bounds)
+}
+
+/// Spreads the rows of an empty table like `load_table` does, never
+/// splitting a run of equal keys.
+fn split_evenly(rows: &[StoredRow], key_idx: usize, segments: usize) -> Vec<usize> {
+    let rows_per_seg = rows.len().div_ceil(segments).max(1);
+    let mut assignment = Vec::with_capacity(rows.len());
+    let mut seg = 0usize;
+    for (i, row) in rows.iter().enumerate() {
+        if seg + 1 < segments
+            && i >= (seg + 1) * rows_per_seg
+            && compare_keys(&rows[i - 1][key_idx], &row[key_idx]).is_ne()
+        {
+            seg += 1;
+        }
+        assignment.push(seg);
+    }
+    assignment
+}
+
+fn compare_keys(a: &Option<ScalarValue>, b: &Option<ScalarValue>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare_scalar(a, b).unwrap_or(Ordering::Equal),
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+    }
+}
+
+/// Writes a delta segment under a hidden name and renames it to the next
+/// free segment number.
+fn write_delta(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    base: u32,
+    rows: &[StoredRow],
+) -> Result<u32, String> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let tmp = table_dir.join(format!(
+        ".delta-{}-{}-{}.tmp",
+        base,
+        std::process::id(),
+        WRITES.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+    fs::create_dir_all(&tmp).map_err(|e| format!("Failed to create {:?}: {}", tmp, e))?;
+    let written = write_part(&tmp, schema, rows)
+        .and_then(|_| {
+            write_segment_meta(
+                &tmp,
+                &SegmentMeta {
+                    base: Some(base),
+                    ..SegmentMeta::default()
+                },
+            )
+        })
+        .and_then(|_| claim_segment(table_dir, &tmp));
+    if written.is_err() {
+        let _ = fs::remove_dir_all(&tmp);
+    }
+    written
+}
+
+fn claim_segment(table_dir: &Path, tmp: &Path) -> Result<u32, String> {
+    for _ in 0..CLAIM_ATTEMPTS {
+        let next = list_segments(table_dir)?
+            .last()
+            .map(|(n, _)| n + 1)
+            .unwrap_or(0);
+        let target = table_dir.join(segment_dir_name(next));
+        // Renaming onto an existing (non-empty) segment fails, so a racing
+        // writer that took the number just makes us try the next one.
+        if !target.exists() && fs::rename(tmp, &target).is_ok() {
+            return Ok(next);
+        }
+    }
+    Err(format!(
+        "Failed to claim a segment number in {:?}",
+        table_dir
+    ))
+}
+
+/// Writes one column file per schema column, in the format `load_table`
+/// writes.
+pub fn write_part(dir: &Path, schema: &[ColumnDef], rows: &[StoredRow]) -> Result<(), String> {
+    for (idx, col) in schema.iter().enumerate() {
+        let path = dir.join(col.file_name());
+        let file =
+            File::create(&path).map_err(|e| format!("Failed to create file {:?}: {}", path, e))?;
+        let mut w = BufWriter::new(file);
+        for row in rows {
+            write_scalar(&mut w, &row[idx], col)?;
+        }
+        w.flush()
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn write_scalar(
+    w: &mut BufWriter<File>,
+    value: &Option<ScalarValue>,
+    col: &ColumnDef,
+) -> Result<(), String> {
+    let mismatch = |v: &ScalarValue| {
+        format!(
+            "Value {:?} does not fit column '{}' ({})",
+            v, col.name, col.col_type
+        )
+    };
+    let Some(v) = value else {
+        return w.write_all(&[0u8]).map_err(|e| e.to_string());
+    };
+    let mut bytes = vec![1u8];
+    match (&col.col_type, v) {
+        (ColumnType::Int32 | ColumnType::Date, ScalarValue::Int(i)) => {
+            let i = i32::try_from(*i).map_err(|_| mismatch(v))?;
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        (ColumnType::Int64 | ColumnType::TimestampMs, ScalarValue::Int(i)) => {
+            bytes.extend_from_slice(&i.to_le_bytes())
+        }
+        (ColumnType::Float64, ScalarValue::Float(f)) => bytes.extend_from_slice(&f.to_le_bytes()),
+        (ColumnType::Float64, ScalarValue::Int(i)) => {
+            bytes.extend_from_slice(&(*i as f64).to_le_bytes())
+        }
+        (ColumnType::Bool, ScalarValue::Bool(b)) => bytes.push(*b as u8),
+        (ColumnType::String, ScalarValue::String(s)) => {
+            bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        _ => return Err(mismatch(v)),
+    }
+    w.write_all(&bytes).map_err(|e| e.to_string())
+}
@@ -0,0 +1,111 @@
+use crate::storage::storage_view::VIEW_QUERY;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Registry of the tables and views in a data root, one name per line,
+/// and a `version=<n>` line. Each name is a directory directly under the
+/// root.
+pub const CATALOG: &str = "_catalog.txt";
+
+/// Registering reads, changes and replaces `_catalog.txt`; one at a time.
+static REGISTRY: Mutex<()> = Mutex::new(());
+
+/// Names in the catalog of `root`, sorted. A root without `_catalog.txt`
+/// holds the directories with a schema or a view query.
+pub fn catalog_names(root: &Path) -> Result<Vec<String>, String> {
+    read_catalog(root).map(|(names, _)| names)
+}
+
+/// Counts changes to the tables and views of `root`: creating, dropping or
+/// altering any of them moves it, so whatever was resolved against the
+/// catalog at one version may be stale at another. 0 without a catalog.
+pub fn catalog_version(root: &Path) -> u64 {
+    read_catalog(root).map_or(0, |(_, version)| version)
+}
+
+fn read_catalog(root: &Path) -> Result<(Vec<String>, u64), String> {
+    let path = root.join(CATALOG);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok((discover_names(root)?, 0));
+    };
+    let mut version = 0;
+    let mut names = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match line.strip_prefix("version=") {
+            Some(v) => {
+                version = v
+                    .parse()
+                    .map_err(|_| format!("Invalid catalog version in {:?}", path))?
+            }
+            None => names.push(line.to_string()),
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok((names, version))
+}
+
+fn discover_names(root: &Path) -> Result<Vec<String>, String> {
+    let entries =
+        fs::read_dir(root).map_err(|e| format!("Failed to read data root {:?}: {}", root, e))?;
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| {
+            let dir = e.path();
+            dir.join("_schema.ssf").is_file() || dir.join(VIEW_QUERY).is_file()
+        })
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn is_registered(root: &Path, name: &str) -> bool {
+    catalog_names(root).is_ok_and(|names| names.iter().any(|n| n == name))
+}
+
+/// Writes `_catalog.txt` for a root that has none, listing what it holds.
+pub fn init_catalog(root: &Path) -> Result<(), String> {
+    let _guard = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    if root.join(CATALOG).is_file() {
+        return Ok(());
+    }
+    write_catalog(root, &discover_names(root)?, 0)
+}
+
+pub fn register(root: &Path, name: &str) -> Result<(), String> {
+    let _guard = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut names, version) = read_catalog(root)?;
+    if !names.iter().any(|n| n == name) {
+        names.push(name.to_string());
+        names.sort();
+    }
+    write_catalog(root, &names, version + 1)
+}
+
+pub fn unregister(root: &Path, name: &str) -> Result<(), String> {
+    let _guard = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut names, version) = read_catalog(root)?;
+    names.retain(|n| n != name);
+    write_catalog(root, &names, version + 1)
+}
+
+/// Moves `catalog_version` for a change that keeps the names, e.g. an
+/// `ALTER TABLE`.
+pub fn bump_version(root: &Path) -> Result<(), String> {
+    let _guard = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let (names, version) = read_catalog(root)?;
+    write_catalog(root, &names, version + 1)
+}
+
+/// Replaces the registry in one rename.
+fn write_catalog(root: &Path, names: &[String], version: u64) -> Result<(), String> {
+    let mut contents: String = names.iter().map(|n| format!("{}\n", n)).collect();
+    contents.push_str(&format!("version={}\n", version));
+    let tmp = root.join(format!(".{}.tmp", CATALOG));
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, root.join(CATALOG))
+        .map_err(|e| format!("Failed to replace catalog in {:?}: {}", root, e))
+}
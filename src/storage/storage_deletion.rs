@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+
+/// Deletion vector of a segment, next to its column files.
+pub const DELETED_FILE: &str = "_deleted.bin";
+
+/// Row positions deleted from a segment: bit `i % 8` of byte `i / 8` is set
+/// when row `i` is deleted. Rows past the end of the file are live.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeletionVector {
+    bits: Vec<u8>,
+}
+
+impl DeletionVector {
+    /// Reads the segment's vector; a segment without one has no deletes.
+    pub fn read(seg_dir: &Path) -> Result<DeletionVector, String> {
+        match fs::read(seg_dir.join(DELETED_FILE)) {
+            Ok(bits) => Ok(DeletionVector { bits }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DeletionVector::default()),
+            Err(e) => Err(format!(
+                "Failed to read deletion vector in {:?}: {}",
+                seg_dir, e
+            )),
+        }
+    }
+
+    /// Replaces the segment's vector in one rename, so scans see either the
+    /// old or the new one.
+    pub fn write(&self, seg_dir: &Path) -> Result<(), String> {
+        let tmp = seg_dir.join(format!(".{}.tmp", DELETED_FILE));
+        fs::write(&tmp, &self.bits).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, seg_dir.join(DELETED_FILE))
+            .map_err(|e| format!("Failed to replace deletion vector in {:?}: {}", seg_dir, e))
+    }
+
+    pub fn is_deleted(&self, row: u64) -> bool {
+        self.bits
+            .get((row / 8) as usize)
+            .is_some_and(|b| b & (1 << (row % 8)) != 0)
+    }
+
+    pub fn delete(&mut self, row: u64) {
+        let byte = (row / 8) as usize;
+        if self.bits.len() <= byte {
+            self.bits.resize(byte + 1, 0);
+        }
+        self.bits[byte] |= 1 << (row % 8);
+    }
+
+    /// Number of deleted rows.
+    pub fn count(&self) -> u64 {
+        self.bits.iter().map(|b| b.count_ones() as u64).sum()
+    }
+}
@@ -1,6 +1,16 @@
+use crate::storage::storage_schema::{ColumnDef, format_schema, validate_schema};
 use std::fs;
 use std::path::Path;
 
+// XXX: Unspecified what key-values in this file represent.
+//      Copying example verbatim for now.
+const TABLE_TXT: &str = "\
+version=1
+block_rows=65536
+segment_target_rows=1000000
+endianness=little
+";
+
 pub fn init_table(dir: &Path, schema_file: &Path) -> Result<(), String> {
     fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
 
@@ -8,17 +18,21 @@ pub fn init_table(dir: &Path, schema_file: &Path) -> Result<(), String> {
     let target_schema = dir.join("_schema.ssf");
     fs::copy(schema_file, &target_schema).map_err(|e| format!("Failed to copy schema: {}", e))?;
 
-    // XXX: Unspecified what key-values in this file represent.
-    //      Copying example verbatim for now.
-    let table_txt = "\
-version=1
-block_rows=65536
-segment_target_rows=1000000
-endianness=little
-";
+    write_table_txt(dir)
+}
+
+/// Like `init_table`, for a schema given as columns (`CREATE TABLE`).
+pub fn init_table_with_schema(dir: &Path, schema: &[ColumnDef]) -> Result<(), String> {
+    validate_schema(schema)?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    fs::write(dir.join("_schema.ssf"), format_schema(schema))
+        .map_err(|e| format!("Failed to write schema: {}", e))?;
+    write_table_txt(dir)
+}
 
+fn write_table_txt(dir: &Path) -> Result<(), String> {
     let target_table = dir.join("_table.txt");
-    fs::write(&target_table, table_txt)
+    fs::write(&target_table, TABLE_TXT)
         .map_err(|e| format!("Failed to write _table.txt: {}", e))?;
 
     Ok(())
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+use crate::storage::storage_alter::schema_version;
 use crate::storage::storage_schema;
 
 pub fn inspect_schema(dir: &Path) -> Result<String, String> {
@@ -29,6 +30,7 @@ pub fn inspect_metadata(dir: &Path) -> Result<String, String> {
         metadata.segment_target_rows
     ));
     out.push_str(&format!("endianness: {}\n", metadata.endianness));
+    out.push_str(&format!("schema_version: {}\n", metadata.schema_version));
 
     Ok(out)
 }
@@ -38,6 +40,7 @@ struct TableMetadata {
     block_rows: usize,
     segment_target_rows: usize,
     endianness: String,
+    schema_version: u64,
 }
 
 fn parse_table_metadata(contents: &str) -> Result<TableMetadata, String> {
@@ -100,5 +103,6 @@ fn parse_table_metadata(contents: &str) -> Result<TableMetadata, String> {
         block_rows,
         segment_target_rows,
         endianness,
+        schema_version: schema_version(contents)?,
     })
 }
@@ -1,8 +1,18 @@
+use crate::minisql::minisql_eval::{init_reader, read_value};
+use crate::rpc::ScalarValue;
+use crate::storage::storage_alter::{set_table_property, table_property};
 use crate::storage::storage_schema::{ColumnDef, ColumnType};
+use crate::storage::storage_segment::{data_dir, read_segment_meta};
 use csv::ReaderBuilder;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `_table.txt` key naming the segment boundaries a table was loaded with.
+/// `load_table` draws new ones; `load_table_colocated` copies the
+/// reference's, so tables with equal values are co-located.
+pub const COLOCATION: &str = "colocation";
 
 pub fn load_table(
     table_dir: &Path,
@@ -14,6 +24,95 @@ pub fn load_table(
     if segments == 0 {
         return Err("segments must be > 0".into());
     }
+    let key_col = check_sort_key(sort_key, schema)?;
+    let (rows, col_index) = read_sorted_rows(csv_path, key_col, schema)?;
+
+    let total_rows = rows.len();
+    let rows_per_seg = total_rows.div_ceil(segments);
+
+    // Runs of equal keys are never split across segments, so that every key
+    // lives in exactly one segment (required by co-located merge joins).
+    let mut assignment = Vec::with_capacity(total_rows);
+    let mut seg = 0usize;
+    for (i, (key, _)) in rows.iter().enumerate() {
+        if seg + 1 < segments && i >= (seg + 1) * rows_per_seg && rows[i - 1].0 != *key {
+            seg += 1;
+        }
+        assignment.push(seg);
+    }
+
+    write_segments(table_dir, schema, segments, rows, &col_index, &assignment)?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let name = table_dir.file_name().unwrap_or_default().to_string_lossy();
+    set_table_property(
+        table_dir,
+        COLOCATION,
+        Some(&format!("{}-{:x}", name, nanos)),
+    )
+}
+
+/// Loads a CSV so that its segment boundaries line up with those of the
+/// already loaded `reference_dir` table: segment `i` of both tables covers
+/// the same key range. Both tables must be keyed on columns of the same type.
+pub fn load_table_colocated(
+    table_dir: &Path,
+    csv_path: &Path,
+    sort_key: &String,
+    segments: usize,
+    schema: &[ColumnDef],
+    reference_dir: &Path,
+) -> Result<(), String> {
+    let key_col = check_sort_key(sort_key, schema)?;
+    let reference = colocation(reference_dir).ok_or_else(|| {
+        format!(
+            "Reference table {:?} was not loaded from a CSV",
+            reference_dir
+        )
+    })?;
+    let bounds = segment_lower_bounds(reference_dir, key_col)?;
+    if bounds.len() + 1 != segments {
+        return Err(format!(
+            "Reference table has {} segments, but {} were requested",
+            bounds.len() + 1,
+            segments
+        ));
+    }
+    let (rows, col_index) = read_sorted_rows(csv_path, key_col, schema)?;
+
+    let mut assignment = Vec::with_capacity(rows.len());
+    for (key, _) in &rows {
+        let seg = bounds
+            .iter()
+            .rposition(|b| b.as_ref().is_some_and(|b| b <= key))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        assignment.push(seg);
+    }
+
+    write_segments(table_dir, schema, segments, rows, &col_index, &assignment)?;
+    set_table_property(table_dir, COLOCATION, Some(&reference))
+}
+
+/// The `colocation` of a table, if it was loaded from a CSV.
+pub fn colocation(table_dir: &Path) -> Option<String> {
+    let contents = fs::read_to_string(table_dir.join("_table.txt")).ok()?;
+    table_property(&contents, COLOCATION).map(str::to_string)
+}
+
+/// Creates `segments` empty segments (empty column files), so that every
+/// worker of a new table has a segment to scan.
+pub fn create_empty_segments(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    segments: usize,
+) -> Result<(), String> {
+    write_segments(table_dir, schema, segments, Vec::new(), &[], &[])
+}
+
+fn check_sort_key<'a>(sort_key: &String, schema: &'a [ColumnDef]) -> Result<&'a ColumnDef, String> {
     let key_col = schema
         .iter()
         .find(|c| c.is_key)
@@ -25,7 +124,18 @@ pub fn load_table(
             sort_key, key_col.name
         ));
     }
+    Ok(key_col)
+}
 
+type KeyedRecord = (SortKeyValue, csv::StringRecord);
+
+/// Reads the CSV, validates its headers and returns the records sorted by key,
+/// together with the CSV field index of every schema column.
+fn read_sorted_rows(
+    csv_path: &Path,
+    key_col: &ColumnDef,
+    schema: &[ColumnDef],
+) -> Result<(Vec<KeyedRecord>, Vec<usize>), String> {
     let mut reader = ReaderBuilder::new()
         .has_headers(true)
         .from_path(csv_path)
@@ -42,14 +152,14 @@ pub fn load_table(
     for col in schema {
         let idx = headers
             .iter()
-            .position(|h| h == &col.name)
+            .position(|h| h == col.name)
             .ok_or_else(|| format!("CSV missing required column: '{}'", col.name))?;
         col_index.push(idx);
     }
 
     let key_idx = headers
         .iter()
-        .position(|h| h == &key_col.name)
+        .position(|h| h == key_col.name)
         .ok_or_else(|| format!("CSV missing key column '{}'", key_col.name))?;
 
     let mut rows = Vec::new();
@@ -64,7 +174,17 @@ pub fn load_table(
     }
 
     rows.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((rows, col_index))
+}
 
+fn write_segments(
+    table_dir: &Path,
+    schema: &[ColumnDef],
+    segments: usize,
+    rows: Vec<KeyedRecord>,
+    col_index: &[usize],
+    assignment: &[usize],
+) -> Result<(), String> {
     for seg in 0..segments {
         let seg_dir = table_dir.join(format!("seg-{:06}", seg));
         fs::create_dir_all(&seg_dir)
@@ -81,7 +201,7 @@ pub fn load_table(
         let mut writers = Vec::new();
         for seg in 0..segments {
             let seg_dir = table_dir.join(format!("seg-{:06}", seg));
-            let path = seg_dir.join(format!("{}.bin", col.name));
+            let path = seg_dir.join(col.file_name());
 
             let f = File::create(&path)
                 .map_err(|e| format!("Failed to create file {:?}: {}", path, e))?;
@@ -92,12 +212,7 @@ pub fn load_table(
         col_writers.push(ColWriter { writers });
     }
 
-    let total_rows = rows.len();
-    let rows_per_seg = (total_rows + segments - 1) / segments;
-
-    for (i, (_, record)) in rows.into_iter().enumerate() {
-        let seg = (i / rows_per_seg).min(segments - 1);
-
+    for ((_, record), &seg) in rows.into_iter().zip(assignment) {
         for (col_idx, col) in schema.iter().enumerate() {
             let csv_field = record[col_index[col_idx]].trim();
             let writer = &mut col_writers[col_idx].writers[seg];
@@ -108,6 +223,63 @@ pub fn load_table(
     Ok(())
 }
 
+/// Returns the first key of every segment of `reference_dir` except the
+/// first one, i.e. the lower bound of segments `1..N`. Empty segments have no
+/// bound and never receive rows.
+fn segment_lower_bounds(
+    reference_dir: &Path,
+    key_col: &ColumnDef,
+) -> Result<Vec<Option<SortKeyValue>>, String> {
+    let schema_path = reference_dir.join("_schema.ssf");
+    let contents = fs::read_to_string(&schema_path)
+        .map_err(|e| format!("Failed to read schema file {:?}: {}", schema_path, e))?;
+    let ref_schema = crate::storage::storage_schema::parse_schema_file(&contents)?;
+    let ref_key = ref_schema
+        .iter()
+        .find(|c| c.is_key)
+        .ok_or("Reference schema has no column marked as `key`")?;
+    if ref_key.col_type != key_col.col_type {
+        return Err(format!(
+            "Key '{}' ({:?}) is not compatible with reference key '{}' ({:?})",
+            key_col.name, key_col.col_type, ref_key.name, ref_key.col_type
+        ));
+    }
+
+    let mut segments = 0usize;
+    while reference_dir.join(format!("seg-{:06}", segments)).is_dir() {
+        segments += 1;
+    }
+    if segments == 0 {
+        return Err(format!(
+            "Reference table {:?} has no segments",
+            reference_dir
+        ));
+    }
+
+    let mut bounds = Vec::with_capacity(segments - 1);
+    for seg in 1..segments {
+        let seg_dir = reference_dir.join(format!("seg-{:06}", seg));
+        let path = data_dir(&seg_dir, &read_segment_meta(&seg_dir)?).join(ref_key.file_name());
+        let mut reader = init_reader(&path, ref_key)
+            .ok_or_else(|| format!("Failed to open key column {:?}", path))?;
+        let bound = match read_value(&mut reader, ref_key) {
+            Ok(Some(v)) => Some(scalar_sort_key(v)),
+            _ => None,
+        };
+        bounds.push(bound);
+    }
+    Ok(bounds)
+}
+
+fn scalar_sort_key(v: ScalarValue) -> SortKeyValue {
+    match v {
+        ScalarValue::Int(i) => SortKeyValue::Primitive(SortKey::Int(i)),
+        ScalarValue::Float(f) => SortKeyValue::Float(SortKeyFloat::Float(f)),
+        ScalarValue::Bool(b) => SortKeyValue::Primitive(SortKey::Bool(b)),
+        ScalarValue::String(s) => SortKeyValue::Primitive(SortKey::String(s)),
+    }
+}
+
 fn write_value(w: &mut BufWriter<File>, field: &str, col: &ColumnDef) -> Result<(), String> {
     if field.is_empty() {
         if col.nullable {
@@ -244,7 +416,7 @@ fn parse_sort_key(
             let date = chrono::NaiveDate::parse_from_str(field, "%Y-%m-%d")
                 .map_err(|e| format!("{}", e))?;
             let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
-            let days = date.signed_duration_since(epoch).num_days() as i64;
+            let days = date.signed_duration_since(epoch).num_days();
             Ok(SortKeyValue::Primitive(SortKey::Int(days)))
         }
         ColumnType::TimestampMs => {
@@ -0,0 +1,75 @@
+use crate::storage::storage_append::{StoredRow, write_part};
+use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_segment::{
+    data_dir, generation_dir_name, read_segment_meta, write_segment_meta,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Replaces the rows of a segment (base or delta) by writing them as the
+/// segment's next generation and switching `_segment.txt` to it in one
+/// rename. `part_dir` is the data directory the rows were read from, as
+/// `segment_parts` lists it.
+pub fn rewrite_part(
+    part_dir: &Path,
+    schema: &[ColumnDef],
+    rows: &[StoredRow],
+) -> Result<(), String> {
+    let seg_dir = segment_dir_of(part_dir);
+    let mut meta = read_segment_meta(&seg_dir)?;
+    if data_dir(&seg_dir, &meta) != part_dir {
+        return Err(format!("Segment {:?} was rewritten concurrently", seg_dir));
+    }
+    let previous = meta.generation;
+    meta.generation += 1;
+    let new_dir = data_dir(&seg_dir, &meta);
+    // Left over by a rewrite that failed before switching.
+    if new_dir.exists() {
+        fs::remove_dir_all(&new_dir)
+            .map_err(|e| format!("Failed to remove {:?}: {}", new_dir, e))?;
+    }
+    fs::create_dir(&new_dir).map_err(|e| format!("Failed to create {:?}: {}", new_dir, e))?;
+    let written =
+        write_part(&new_dir, schema, rows).and_then(|_| write_segment_meta(&seg_dir, &meta));
+    if written.is_err() {
+        let _ = fs::remove_dir_all(&new_dir);
+        return written;
+    }
+    // Scans that listed the segment before the switch may still open the
+    // previous generation, so only the one before it is removed.
+    if previous > 0 {
+        remove_generation(&seg_dir, previous - 1);
+    }
+    Ok(())
+}
+
+/// The segment directory a data directory belongs to.
+fn segment_dir_of(part_dir: &Path) -> PathBuf {
+    match part_dir.file_name().and_then(|n| n.to_str()) {
+        Some(name) if name.starts_with("gen-") => part_dir
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| part_dir.to_path_buf()),
+        _ => part_dir.to_path_buf(),
+    }
+}
+
+/// Best effort: a leftover generation only wastes space.
+fn remove_generation(seg_dir: &Path, generation: u32) {
+    if generation > 0 {
+        let _ = fs::remove_dir_all(seg_dir.join(generation_dir_name(generation)));
+        return;
+    }
+    // Generation 0 is the `.bin` files (columns and deletion vector)
+    // directly in the segment directory.
+    let Ok(entries) = fs::read_dir(seg_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.path().is_file() && name.ends_with(".bin") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
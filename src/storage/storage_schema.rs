@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+use crate::rpc::ScalarValue;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ColumnType {
     Int32,
     Int64,
@@ -26,12 +28,39 @@ impl std::str::FromStr for ColumnType {
     }
 }
 
+impl std::fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ColumnType::Int32 => "int32",
+            ColumnType::Int64 => "int64",
+            ColumnType::Float64 => "float64",
+            ColumnType::Bool => "bool",
+            ColumnType::String => "string",
+            ColumnType::Date => "date",
+            ColumnType::TimestampMs => "timestamp(ms)",
+        })
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ColumnDef {
     pub name: String,
     pub col_type: ColumnType,
     pub nullable: bool,
     pub is_key: bool,
+    /// Value of the column in segments written before it was added (and of
+    /// rows inserted without it); NULL when unset.
+    pub default: Option<ScalarValue>,
+    /// Stem of the column files when it differs from the name, e.g. after
+    /// `RENAME COLUMN`.
+    pub file: Option<String>,
+}
+
+impl ColumnDef {
+    /// Name of the column's file in a segment directory.
+    pub fn file_name(&self) -> String {
+        format!("{}.bin", self.file.as_deref().unwrap_or(&self.name))
+    }
 }
 
 pub fn parse_schema_line(line: &str) -> Result<ColumnDef, String> {
@@ -51,15 +80,26 @@ pub fn parse_schema_line(line: &str) -> Result<ColumnDef, String> {
         return Err("Column name is empty".into());
     }
 
-    let mut parts = rest.split_whitespace();
+    let tokens = split_tokens(rest)?;
+    let mut parts = tokens.iter().map(String::as_str);
 
     let type_token = parts.next().ok_or("Missing column type")?;
     let col_type: ColumnType = type_token.parse()?;
 
     let mut nullable = false;
     let mut is_key = false;
+    let mut default = None;
+    let mut file = None;
 
     for p in parts {
+        if let Some(value) = p.strip_prefix("default=") {
+            default = Some(parse_default(value, &col_type)?);
+            continue;
+        }
+        if let Some(value) = p.strip_prefix("file=") {
+            file = Some(unquote(value)?);
+            continue;
+        }
         match p {
             "nullable" => nullable = true,
             "key" => {
@@ -77,6 +117,8 @@ pub fn parse_schema_line(line: &str) -> Result<ColumnDef, String> {
         col_type,
         nullable,
         is_key,
+        default,
+        file,
     })
 }
 
@@ -108,3 +150,156 @@ pub fn parse_schema_file(contents: &str) -> Result<Vec<ColumnDef>, String> {
 
     Ok(columns)
 }
+
+/// Checks a schema built outside an `.ssf` file: one key column, and names
+/// that are unique, can be written back to `_schema.ssf` and stay inside
+/// the segment directory as file names.
+pub fn validate_schema(columns: &[ColumnDef]) -> Result<(), String> {
+    for (i, col) in columns.iter().enumerate() {
+        if col.name.trim().is_empty() || col.name.trim() != col.name {
+            return Err(format!("Invalid column name '{}'", col.name));
+        }
+        if col.name.contains(':') {
+            return Err(format!("Column name '{}' may not contain ':'", col.name));
+        }
+        // Names become file names in the segment directories.
+        for stem in [Some(&col.name), col.file.as_ref()].into_iter().flatten() {
+            if stem.is_empty()
+                || stem.starts_with('.')
+                || stem.contains(['/', '\\'])
+                || stem.chars().any(char::is_control)
+            {
+                return Err(format!("Invalid column name '{}'", stem));
+            }
+        }
+        if columns[..i].iter().any(|c| c.name == col.name) {
+            return Err(format!("Duplicate column '{}'", col.name));
+        }
+        if columns[..i]
+            .iter()
+            .any(|c| c.file_name() == col.file_name())
+        {
+            return Err(format!("Column '{}' shares its file", col.name));
+        }
+    }
+    match columns.iter().filter(|c| c.is_key).count() {
+        0 => Err("Schema must contain exactly one 'key' column".into()),
+        1 => Ok(()),
+        _ => Err("Schema contains more than one 'key' column".into()),
+    }
+}
+
+/// `_schema.ssf` text of a schema; `parse_schema_file` reads it back.
+pub fn format_schema(columns: &[ColumnDef]) -> String {
+    let mut out = String::new();
+    for col in columns {
+        out.push_str(&format!("{}: {}", col.name, col.col_type));
+        if col.nullable {
+            out.push_str(" nullable");
+        }
+        if col.is_key {
+            out.push_str(" key");
+        }
+        if let Some(default) = &col.default {
+            out.push_str(&format!(" default={}", format_default(default)));
+        }
+        if let Some(file) = &col.file {
+            out.push_str(&format!(" file={}", quote(file)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Defaults are stored as the column's values are: dates and timestamps as
+/// integers, strings quoted.
+fn format_default(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Int(i) => i.to_string(),
+        ScalarValue::Float(f) => format!("{:?}", f),
+        ScalarValue::Bool(b) => b.to_string(),
+        ScalarValue::String(s) => quote(s),
+    }
+}
+
+fn parse_default(token: &str, col_type: &ColumnType) -> Result<ScalarValue, String> {
+    let invalid = || format!("Invalid default for {}: {}", col_type, token);
+    Ok(match col_type {
+        ColumnType::Int32 | ColumnType::Int64 | ColumnType::Date | ColumnType::TimestampMs => {
+            ScalarValue::Int(token.parse().map_err(|_| invalid())?)
+        }
+        ColumnType::Float64 => ScalarValue::Float(token.parse().map_err(|_| invalid())?),
+        ColumnType::Bool => ScalarValue::Bool(token.parse().map_err(|_| invalid())?),
+        ColumnType::String => ScalarValue::String(unquote(token)?),
+    })
+}
+
+/// `"text"` with `\"`, `\\`, `\n` and `\r` escaped, so it stays one token.
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn unquote(token: &str) -> Result<String, String> {
+    let inner = token
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| format!("Expected a quoted string: {}", token))?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c @ ('"' | '\\')) => out.push(c),
+            _ => return Err(format!("Invalid escape in {}", token)),
+        }
+    }
+    Ok(out)
+}
+
+/// Splits at whitespace, except inside quotes.
+fn split_tokens(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let (mut quoted, mut escaped) = (false, false);
+    for c in s.chars() {
+        if quoted {
+            current.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+        } else if c.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            quoted = c == '"';
+            current.push(c);
+        }
+    }
+    if quoted {
+        return Err(format!("Unterminated quote: {}", current));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Per-segment metadata; segments written by `minidist load` have none.
+pub const SEGMENT_META: &str = "_segment.txt";
+
+/// What `_segment.txt` records about a segment directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentMeta {
+    /// Set for delta segments (appended by `INSERT`): the base segment whose
+    /// key range they belong to and whose worker scans them.
+    pub base: Option<u32>,
+    /// Set once `UPDATE` rewrote the segment: its column files are in
+    /// `gen-NNNNNN` under the segment directory. Generation 0 keeps them in
+    /// the segment directory itself.
+    pub generation: u32,
+}
+
+pub fn segment_dir_name(segment: u32) -> String {
+    format!("seg-{:06}", segment)
+}
+
+pub fn generation_dir_name(generation: u32) -> String {
+    format!("gen-{:06}", generation)
+}
+
+/// Directory holding the column files (and deletion vector) of a segment.
+pub fn data_dir(seg_dir: &Path, meta: &SegmentMeta) -> PathBuf {
+    match meta.generation {
+        0 => seg_dir.to_path_buf(),
+        g => seg_dir.join(generation_dir_name(g)),
+    }
+}
+
+/// Reads `_segment.txt` as `key=value` lines; a missing file is a base
+/// segment.
+pub fn read_segment_meta(seg_dir: &Path) -> Result<SegmentMeta, String> {
+    let path = seg_dir.join(SEGMENT_META);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok(SegmentMeta::default());
+    };
+    let mut meta = SegmentMeta::default();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid line in {:?}: {}", path, line))?;
+        let number = || {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid {} in {:?}: {}", key.trim(), path, e))
+        };
+        // Unknown keys are left for newer versions.
+        match key.trim() {
+            "base" => meta.base = Some(number()?),
+            "generation" => meta.generation = number()?,
+            _ => {}
+        }
+    }
+    Ok(meta)
+}
+
+pub fn write_segment_meta(seg_dir: &Path, meta: &SegmentMeta) -> Result<(), String> {
+    let mut contents = String::new();
+    if let Some(base) = meta.base {
+        contents.push_str(&format!("base={}\n", base));
+    }
+    if meta.generation > 0 {
+        contents.push_str(&format!("generation={}\n", meta.generation));
+    }
+    // Written aside and renamed, so readers never see half a file.
+    let tmp = seg_dir.join(format!(".{}.tmp", SEGMENT_META));
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, seg_dir.join(SEGMENT_META))
+        .map_err(|e| format!("Failed to replace segment metadata in {:?}: {}", seg_dir, e))
+}
+
+/// Every `seg-NNNNNN` directory of a table by number, with its metadata.
+pub fn list_segments(table_dir: &Path) -> Result<Vec<(u32, SegmentMeta)>, String> {
+    let entries = fs::read_dir(table_dir)
+        .map_err(|e| format!("Failed to read table dir {:?}: {}", table_dir, e))?;
+    let mut segments = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+        let name = entry.file_name();
+        let Some(number) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("seg-"))
+            .and_then(|n| n.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if entry.path().is_dir() {
+            segments.push((number, read_segment_meta(&entry.path())?));
+        }
+    }
+    segments.sort_by_key(|(n, _)| *n);
+    Ok(segments)
+}
+
+/// Number of base segments, i.e. of workers the table needs.
+pub fn base_segment_count(table_dir: &Path) -> Result<usize, String> {
+    Ok(list_segments(table_dir)?
+        .iter()
+        .filter(|(_, meta)| meta.base.is_none())
+        .count())
+}
+
+/// Data directories (see `data_dir`) holding the rows of base segment
+/// `segment`: the segment itself, then its deltas in the order they were
+/// written. Each is sorted by the table key.
+pub fn segment_parts(table_dir: &Path, segment: u32) -> Vec<PathBuf> {
+    numbered_parts(table_dir, segment)
+        .into_iter()
+        .map(|(_, dir)| dir)
+        .collect()
+}
+
+/// Like `segment_parts`, with the number of the segment directory each
+/// data directory belongs to.
+pub fn numbered_parts(table_dir: &Path, segment: u32) -> Vec<(u32, PathBuf)> {
+    let base = table_dir.join(segment_dir_name(segment));
+    if !base.is_dir() {
+        return Vec::new();
+    }
+    let mut parts = vec![(
+        segment,
+        data_dir(&base, &read_segment_meta(&base).unwrap_or_default()),
+    )];
+    for (number, meta) in list_segments(table_dir).unwrap_or_default() {
+        if meta.base == Some(segment) {
+            parts.push((
+                number,
+                data_dir(&table_dir.join(segment_dir_name(number)), &meta),
+            ));
+        }
+    }
+    parts
+}
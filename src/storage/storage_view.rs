@@ -0,0 +1,116 @@
+use crate::rpc::GroupMap;
+use crate::storage::storage_alter::schema_version;
+use crate::storage::storage_deletion::DeletionVector;
+use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_segment::{data_dir, list_segments, segment_dir_name};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// The query of a view, as written in `CREATE VIEW`.
+pub const VIEW_QUERY: &str = "_view.sql";
+/// Stored partials of a materialized view (MessagePack `ViewPartials`).
+pub const VIEW_PARTIALS: &str = "_partials.bin";
+
+/// Identifies what a segment holds: `UPDATE` moves it to a new
+/// generation, `DELETE` grows its deletion vector, and a table created
+/// again under the same name writes new key files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartFingerprint {
+    pub generation: u32,
+    pub deleted: u64,
+    pub key_bytes: u64,
+    pub key_modified_ns: u128,
+}
+
+/// A segment (base or delta) of the table a materialized view reads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TablePart {
+    pub segment: u32,
+    pub base: u32, // the worker that scans it
+    pub fingerprint: PartFingerprint,
+}
+
+/// The view query's groups over one segment, as of `fingerprint`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredPart {
+    pub segment: u32,
+    pub fingerprint: PartFingerprint,
+    pub groups: GroupMap,
+}
+
+/// Everything a materialized view stores. Partials taken under another
+/// `schema_version` of the table are stale.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ViewPartials {
+    pub schema_version: u64,
+    pub parts: Vec<StoredPart>,
+}
+
+pub fn is_view(dir: &Path) -> bool {
+    dir.join(VIEW_QUERY).is_file()
+}
+
+pub fn is_materialized(dir: &Path) -> bool {
+    dir.join(VIEW_PARTIALS).is_file()
+}
+
+pub fn read_view_query(dir: &Path) -> Result<String, String> {
+    let path = dir.join(VIEW_QUERY);
+    fs::read_to_string(&path).map_err(|e| format!("Failed to read view {:?}: {}", path, e))
+}
+
+pub fn write_view_query(dir: &Path, sql: &str) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create view dir {:?}: {}", dir, e))?;
+    let path = dir.join(VIEW_QUERY);
+    fs::write(&path, format!("{}\n", sql.trim()))
+        .map_err(|e| format!("Failed to write view {:?}: {}", path, e))
+}
+
+pub fn read_partials(dir: &Path) -> Result<ViewPartials, String> {
+    let path = dir.join(VIEW_PARTIALS);
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    rmp_serde::from_slice(&bytes).map_err(|e| format!("Invalid partials in {:?}: {}", path, e))
+}
+
+/// Replaces the stored partials in one rename.
+pub fn write_partials(dir: &Path, partials: &ViewPartials) -> Result<(), String> {
+    let bytes = rmp_serde::to_vec_named(partials)
+        .map_err(|e| format!("Failed to encode partials: {}", e))?;
+    let tmp = dir.join(format!(".{}.tmp", VIEW_PARTIALS));
+    fs::write(&tmp, bytes).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, dir.join(VIEW_PARTIALS))
+        .map_err(|e| format!("Failed to replace partials in {:?}: {}", dir, e))
+}
+
+/// The table's `schema_version` and its segments with their fingerprints.
+pub fn table_parts(table_dir: &Path, key: &ColumnDef) -> Result<(u64, Vec<TablePart>), String> {
+    let path = table_dir.join("_table.txt");
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read table metadata {:?}: {}", path, e))?;
+    let mut parts = Vec::new();
+    for (segment, meta) in list_segments(table_dir)? {
+        let data = data_dir(&table_dir.join(segment_dir_name(segment)), &meta);
+        let key_path = data.join(key.file_name());
+        let key_file =
+            fs::metadata(&key_path).map_err(|e| format!("Failed to read {:?}: {}", key_path, e))?;
+        let modified = key_file
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        parts.push(TablePart {
+            segment,
+            base: meta.base.unwrap_or(segment),
+            fingerprint: PartFingerprint {
+                generation: meta.generation,
+                deleted: DeletionVector::read(&data)?.count(),
+                key_bytes: key_file.len(),
+                key_modified_ns: modified,
+            },
+        });
+    }
+    Ok((schema_version(&contents)?, parts))
+}
@@ -1,2 +1,5 @@
 pub mod worker_exec;
+pub mod worker_join;
+pub mod worker_mutate;
+pub mod worker_scan;
 pub mod worker_server;
@@ -1,16 +1,26 @@
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::minisql::minisql_eval::{
-    ReadError, ReaderState, apply_agg, format_scalar, init_reader, read_value, row_matches,
+    ReadError, ReaderState, apply_agg, compare_scalar, in_list_values, init_reader, read_value,
+    row_matches,
 };
-use crate::rpc::{AggregateState, GroupMap, PartialAggregate, QueryRequest, ScalarValue};
+use crate::minisql::minisql_plan::{derive_group_by, scan_columns};
+use crate::minisql::minisql_print::format_filter;
+use crate::minisql::minisql_window::{eval_windows, window_input_columns};
+use crate::rpc::{GroupKey, GroupMap, PartialAggregate, QueryRequest, Relation, ScalarValue};
 use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_segment::segment_dir_name;
+
+use super::worker_scan::SegmentScan;
+
+/// Per-column (min, max) over a segment, used for zone-map pruning.
+type ZoneMap = (Option<ScalarValue>, Option<ScalarValue>);
 
 #[derive(Debug, Clone)]
 pub struct WorkerContext {
     pub port: u16,
-    pub table: String,
+    pub table: String, // a table or data root; requests name the table they scan
     pub segment: u32,
 }
 
@@ -21,122 +31,102 @@ pub fn execute_query(
 ) -> PartialAggregate {
     // Guard against missing/invalid schema to avoid spinning forever.
     let mut rows_scanned = 0u64;
+    let mut rows_matched = 0u64;
     let mut groups: GroupMap = HashMap::new();
 
-    let schema = load_schema(&ctx.table);
+    let schema = load_schema(&req.table);
     let def_map: HashMap<String, ColumnDef> =
         schema.iter().map(|c| (c.name.clone(), c.clone())).collect();
     if schema.is_empty() {
-        return PartialAggregate {
-            worker_port: ctx.port,
-            segment: ctx.segment,
-            rows_scanned: 0,
-            segments_skipped: 1,
-            exec_ms: started.elapsed().as_millis() as u64,
-            groups,
-        };
+        return skipped_partial(ctx, started, "missing schema");
+    }
+    if let Some(mutation) = &req.mutation {
+        return super::worker_mutate::execute_mutation(ctx, &req, mutation, &def_map, started);
+    }
+    if let Some(join) = &req.join {
+        return super::worker_join::execute_join(ctx, &req, join, &def_map, started);
     }
-    let segment_dir = segment_path(&ctx.table, ctx.segment);
 
     let effective_group_by = derive_group_by(&req);
-    let mut needed_cols: HashSet<String> = needed_columns(&req, &effective_group_by);
+    let mut needed_cols: HashSet<String> = scan_columns(&req).into_iter().collect();
     if needed_cols.is_empty() {
         if let Some(first) = schema.first() {
             needed_cols.insert(first.name.clone());
         }
     }
-    let mut readers = match open_readers(&segment_dir, &def_map, &needed_cols) {
-        Ok(r) => r,
-        Err(_) => {
-            return PartialAggregate {
-                worker_port: ctx.port,
-                segment: ctx.segment,
-                rows_scanned: 0,
-                segments_skipped: 1,
-                exec_ms: started.elapsed().as_millis() as u64,
-                groups,
-            };
+    let scan = SegmentScan::open_parts(
+        &req.table,
+        ctx.segment,
+        req.parts.as_deref(),
+        &def_map,
+        &needed_cols,
+    );
+    let mut scan = match scan {
+        Some(s) => s,
+        None => {
+            return skipped_partial(ctx, started, "missing column files");
         }
     };
     // Zone map pruning: if filters cannot match based on min/max, skip segment
-    if let Some(min_max) = compute_min_max(&segment_dir, &def_map, &req.filters) {
-        if should_skip(&req.filters, &min_max) {
-            return PartialAggregate {
-                worker_port: ctx.port,
-                segment: ctx.segment,
-                rows_scanned: 0,
-                segments_skipped: 1,
-                exec_ms: started.elapsed().as_millis() as u64,
-                groups,
-            };
-        }
+    if let Some(min_max) = compute_min_max(&scan.dirs, &def_map, &req.filters)
+        && let Some(f) = pruning_filter(&req.filters, &min_max)
+    {
+        return skipped_partial(ctx, started, &format!("zone map ({})", format_filter(f)));
+    }
+    if scan.is_empty() {
+        return skipped_partial(ctx, started, "no columns to read");
     }
-    if readers.is_empty() {
+
+    if !req.windows.is_empty() {
+        // Window queries return rows. Partitions on the table key never span
+        // segments, so those windows are evaluated here, in key order.
+        let columns = window_input_columns(&req.projections, &req.windows);
+        let mut rel = Relation {
+            columns,
+            rows: Vec::new(),
+        };
+        while let Some(row_values) = scan.next_row() {
+            rows_scanned += 1;
+            if row_matches(&req.filters, &row_values) {
+                rows_matched += 1;
+                rel.rows.push(
+                    rel.columns
+                        .iter()
+                        .map(|c| row_values.get(c).cloned().flatten())
+                        .collect(),
+                );
+            }
+        }
+        if req.window_local {
+            rel = eval_windows(&rel, &req);
+        }
         return PartialAggregate {
             worker_port: ctx.port,
             segment: ctx.segment,
-            rows_scanned: 0,
-            segments_skipped: 1,
-            exec_ms: started.elapsed().as_millis() as u64,
+            rows_scanned,
+            rows_matched,
+            bytes_read: scan.bytes_read,
+            segments_skipped: 0,
+            skip_reason: None,
+            exec_us: started.elapsed().as_micros() as u64,
             groups,
+            grouping_groups: Vec::new(),
+            rows: Some(rel),
+            error: None,
         };
     }
 
-    loop {
-        let mut row_values: HashMap<String, Option<ScalarValue>> = HashMap::new();
-
-        let mut eof = false;
-        for (name, reader_state) in readers.iter_mut() {
-            if !needed_cols.contains(name) {
-                continue;
-            }
-            let def = def_map.get(name).unwrap();
-            match read_value(reader_state, def) {
-                Ok(v) => {
-                    row_values.insert(name.clone(), v);
-                }
-                Err(ReadError::Eof) | Err(ReadError::Io) => {
-                    eof = true;
-                    break;
-                }
-            }
-        }
-
-        if eof {
-            break;
-        }
-
+    let mut grouping_groups = vec![GroupMap::new(); req.grouping_sets.len()];
+    while let Some(row_values) = scan.next_row() {
         rows_scanned += 1;
-
-        if !row_matches(&req.filters, &row_values) {
-            continue;
-        }
-
-        let gkey = if effective_group_by.is_empty() {
-            "all".to_string()
-        } else {
-            let mut parts = Vec::new();
-            for gcol in &effective_group_by {
-                let val = row_values.get(gcol).and_then(|v| v.clone());
-                parts.push(format_scalar(&val));
-            }
-            parts.join("|")
-        };
-
-        let agg_map = groups.entry(gkey).or_default();
-        if req.aggregates.is_empty() {
-            // Implicit COUNT(*) for projection-only queries so users get a visible result.
-            let state = agg_map
-                .entry("COUNT(*)".to_string())
-                .or_insert_with(AggregateState::default);
-            state.count += 1;
-        } else {
-            for agg in &req.aggregates {
-                let state = agg_map
-                    .entry(agg.output_name.clone())
-                    .or_insert_with(AggregateState::default);
-                apply_agg(state, agg, &row_values);
-            }
+        if accumulate(
+            &mut groups,
+            &mut grouping_groups,
+            &req,
+            &effective_group_by,
+            &row_values,
+        ) {
+            rows_matched += 1;
         }
     }
 
@@ -144,109 +134,230 @@ pub fn execute_query(
         worker_port: ctx.port,
         segment: ctx.segment,
         rows_scanned,
+        rows_matched,
+        bytes_read: scan.bytes_read,
         segments_skipped: 0,
-        exec_ms: started.elapsed().as_millis() as u64,
+        skip_reason: None,
+        exec_us: started.elapsed().as_micros() as u64,
         groups,
+        grouping_groups,
+        rows: None,
+        error: None,
     }
 }
 
-fn load_schema(table_dir: &str) -> Vec<ColumnDef> {
-    let path = PathBuf::from(table_dir).join("_schema.ssf");
-    let contents = std::fs::read_to_string(&path).unwrap_or_default();
-    crate::storage::storage_schema::parse_schema_file(&contents).unwrap_or_default()
+/// Result of a segment that was not scanned, and why.
+pub fn skipped_partial(
+    ctx: &WorkerContext,
+    started: std::time::Instant,
+    reason: &str,
+) -> PartialAggregate {
+    PartialAggregate {
+        worker_port: ctx.port,
+        segment: ctx.segment,
+        rows_scanned: 0,
+        rows_matched: 0,
+        bytes_read: 0,
+        segments_skipped: 1,
+        skip_reason: Some(reason.to_string()),
+        exec_us: started.elapsed().as_micros() as u64,
+        groups: HashMap::new(),
+        grouping_groups: Vec::new(),
+        rows: None,
+        error: None,
+    }
 }
 
-fn segment_path(table_dir: &str, segment: u32) -> PathBuf {
-    PathBuf::from(table_dir).join(format!("seg-{:06}", segment))
+/// Size of the column files a scan reads in full.
+pub fn column_bytes<'a>(
+    segment_dir: &Path,
+    defs: &HashMap<String, ColumnDef>,
+    columns: impl Iterator<Item = &'a String>,
+) -> u64 {
+    columns
+        .filter_map(|c| defs.get(c))
+        .filter_map(|c| std::fs::metadata(segment_dir.join(c.file_name())).ok())
+        .map(|m| m.len())
+        .sum()
 }
 
-fn needed_columns(req: &QueryRequest, group_by: &[String]) -> HashSet<String> {
-    let mut set = HashSet::new();
-    for g in group_by {
-        set.insert(g.clone());
-    }
-    for agg in &req.aggregates {
-        if let Some(c) = &agg.column {
-            set.insert(c.clone());
+/// Reads the next row from every open column reader; `None` once any column
+/// is exhausted.
+pub fn read_row(
+    readers: &mut HashMap<String, ReaderState>,
+    defs: &HashMap<String, ColumnDef>,
+) -> Option<HashMap<String, Option<ScalarValue>>> {
+    let mut row_values: HashMap<String, Option<ScalarValue>> = HashMap::new();
+    for (name, reader_state) in readers.iter_mut() {
+        let def = defs.get(name)?;
+        match read_value(reader_state, def) {
+            Ok(v) => {
+                row_values.insert(name.clone(), v);
+            }
+            Err(ReadError::Eof) | Err(ReadError::Io) => return None,
         }
     }
-    for f in &req.filters {
-        set.insert(f.column.clone());
+    Some(row_values)
+}
+
+/// Filters a row and folds it into `groups`, or with GROUPING SETS into one
+/// map per set (kept separate so the coordinator can merge each set).
+/// Returns whether the row matched the filters.
+pub fn accumulate(
+    groups: &mut GroupMap,
+    grouping_groups: &mut [GroupMap],
+    req: &QueryRequest,
+    group_by: &[String],
+    row_values: &HashMap<String, Option<ScalarValue>>,
+) -> bool {
+    if req.grouping_sets.is_empty() {
+        return accumulate_row(groups, req, group_by, row_values);
+    }
+    if !row_matches(&req.filters, row_values) {
+        return false;
+    }
+    for (set, set_groups) in req.grouping_sets.iter().zip(grouping_groups.iter_mut()) {
+        add_to_group(set_groups, req, set, row_values);
     }
-    // projections unused in aggregation path
-    set
+    true
 }
 
-fn derive_group_by(req: &QueryRequest) -> Vec<String> {
-    if req.aggregates.is_empty() && req.group_by.is_empty() {
-        if req.projections.len() == 1 && req.projections[0] == "*" {
-            Vec::new()
-        } else {
-            req.projections.clone()
-        }
+/// Filters a row and folds it into the group it belongs to.
+pub fn accumulate_row(
+    groups: &mut GroupMap,
+    req: &QueryRequest,
+    group_by: &[String],
+    row_values: &HashMap<String, Option<ScalarValue>>,
+) -> bool {
+    let matched = row_matches(&req.filters, row_values);
+    if matched {
+        add_to_group(groups, req, group_by, row_values);
+    }
+    matched
+}
+
+fn add_to_group(
+    groups: &mut GroupMap,
+    req: &QueryRequest,
+    group_by: &[String],
+    row_values: &HashMap<String, Option<ScalarValue>>,
+) {
+    let gkey: GroupKey = group_by
+        .iter()
+        .map(|gcol| row_values.get(gcol).and_then(|v| v.clone()))
+        .collect();
+
+    let agg_map = groups.entry(gkey).or_default();
+    if req.aggregates.is_empty() {
+        // Implicit COUNT(*) for projection-only queries so users get a visible result.
+        let state = agg_map.entry("COUNT(*)".to_string()).or_default();
+        state.count += 1;
     } else {
-        req.group_by.clone()
+        for agg in &req.aggregates {
+            let state = agg_map.entry(agg.output_name.clone()).or_default();
+            apply_agg(state, agg, row_values);
+        }
     }
 }
 
-fn open_readers(
-    segment_dir: &PathBuf,
+pub fn load_schema(table_dir: &str) -> Vec<ColumnDef> {
+    let path = PathBuf::from(table_dir).join("_schema.ssf");
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    crate::storage::storage_schema::parse_schema_file(&contents).unwrap_or_default()
+}
+
+/// Directory of base segment `segment`; see `SegmentScan` for its deltas.
+pub fn segment_path(table_dir: &str, segment: u32) -> PathBuf {
+    PathBuf::from(table_dir).join(segment_dir_name(segment))
+}
+
+pub fn open_readers(
+    segment_dir: &Path,
     defs: &HashMap<String, ColumnDef>,
     needed: &HashSet<String>,
-) -> Result<HashMap<String, ReaderState>, ()> {
+) -> Option<HashMap<String, ReaderState>> {
     let mut map = HashMap::new();
+    let mut added_later = false;
     for name in needed {
         if let Some(col) = defs.get(name) {
-            let path = segment_dir.join(format!("{}.bin", col.name));
-            let reader = init_reader(&path, col).ok_or(())?;
+            let path = segment_dir.join(col.file_name());
+            // Columns added by ALTER TABLE after the segment was written
+            // have no file; they read their default.
+            let reader = if !col.is_key && !path.exists() {
+                added_later = true;
+                ReaderState::Constant(col.default.clone())
+            } else {
+                init_reader(&path, col)?
+            };
             map.insert(col.name.clone(), reader);
         }
     }
-    Ok(map)
+    // Such a column never ends, so the key column decides where rows end.
+    if added_later
+        && let Some(key) = defs.values().find(|c| c.is_key)
+        && !map.contains_key(&key.name)
+    {
+        map.insert(
+            key.name.clone(),
+            init_reader(&segment_dir.join(key.file_name()), key)?,
+        );
+    }
+    Some(map)
 }
 
-fn compute_min_max(
-    segment_dir: &PathBuf,
+/// Zone maps of the filtered columns over all parts of a segment.
+pub fn compute_min_max(
+    segment_dirs: &[PathBuf],
     defs: &HashMap<String, ColumnDef>,
     filters: &[crate::rpc::FilterExpr],
-) -> Option<HashMap<String, (Option<ScalarValue>, Option<ScalarValue>)>> {
+) -> Option<HashMap<String, ZoneMap>> {
     let mut stats = HashMap::new();
     for f in filters {
         if stats.contains_key(&f.column) {
             continue;
         }
         let def = defs.get(&f.column)?;
-        let path = segment_dir.join(format!("{}.bin", def.name));
-        let mut reader = init_reader(&path, def)?;
         let mut min_val: Option<ScalarValue> = None;
         let mut max_val: Option<ScalarValue> = None;
-        loop {
-            match read_value(&mut reader, def) {
-                Ok(Some(v)) => {
-                    min_val = match min_val {
-                        None => Some(v.clone()),
-                        Some(ref cur) => Some(
-                            if compare_scalar(&v, cur).map(|o| o.is_lt()).unwrap_or(false) {
-                                v.clone()
-                            } else {
-                                cur.clone()
-                            },
-                        ),
-                    };
-                    max_val = match max_val {
-                        None => Some(v.clone()),
-                        Some(ref cur) => Some(
-                            if compare_scalar(&v, cur).map(|o| o.is_gt()).unwrap_or(false) {
-                                v
-                            } else {
-                                cur.clone()
-                            },
-                        ),
-                    };
+        let mut fold = |v: ScalarValue| {
+            min_val = match min_val.take() {
+                None => Some(v.clone()),
+                Some(cur) => Some(
+                    if compare_scalar(&v, &cur).map(|o| o.is_lt()).unwrap_or(false) {
+                        v.clone()
+                    } else {
+                        cur
+                    },
+                ),
+            };
+            max_val = match max_val.take() {
+                None => Some(v),
+                Some(cur) => Some(
+                    if compare_scalar(&v, &cur).map(|o| o.is_gt()).unwrap_or(false) {
+                        v
+                    } else {
+                        cur
+                    },
+                ),
+            };
+        };
+        for segment_dir in segment_dirs {
+            let path = segment_dir.join(def.file_name());
+            if !def.is_key && !path.exists() {
+                // Added after the segment was written: rows read the default.
+                if let Some(v) = &def.default {
+                    fold(v.clone());
+                }
+                continue;
+            }
+            let mut reader = init_reader(&path, def)?;
+            loop {
+                match read_value(&mut reader, def) {
+                    Ok(Some(v)) => fold(v),
+                    Ok(None) => {}
+                    Err(ReadError::Eof) => break,
+                    Err(ReadError::Io) => break,
                 }
-                Ok(None) => {}
-                Err(ReadError::Eof) => break,
-                Err(ReadError::Io) => break,
             }
         }
         stats.insert(f.column.clone(), (min_val, max_val));
@@ -254,10 +365,16 @@ fn compute_min_max(
     Some(stats)
 }
 
-fn should_skip(
-    filters: &[crate::rpc::FilterExpr],
-    stats: &HashMap<String, (Option<ScalarValue>, Option<ScalarValue>)>,
-) -> bool {
+pub fn should_skip(filters: &[crate::rpc::FilterExpr], stats: &HashMap<String, ZoneMap>) -> bool {
+    pruning_filter(filters, stats).is_some()
+}
+
+/// The first filter that no value in the segment's (min, max) range can
+/// satisfy, if any.
+pub fn pruning_filter<'a>(
+    filters: &'a [crate::rpc::FilterExpr],
+    stats: &HashMap<String, ZoneMap>,
+) -> Option<&'a crate::rpc::FilterExpr> {
     for f in filters {
         let Some((min_v, max_v)) = stats.get(&f.column) else {
             continue;
@@ -265,84 +382,76 @@ fn should_skip(
         let target = &f.value;
         match f.pred {
             crate::rpc::Predicate::Eq => {
-                if let (Some(minv), Some(maxv)) = (min_v, max_v) {
-                    if compare_scalar(target, minv)
+                if let (Some(minv), Some(maxv)) = (min_v, max_v)
+                    && (compare_scalar(target, minv)
                         .map(|o| o.is_lt())
                         .unwrap_or(false)
                         || compare_scalar(target, maxv)
                             .map(|o| o.is_gt())
-                            .unwrap_or(false)
-                    {
-                        return true;
-                    }
+                            .unwrap_or(false))
+                {
+                    return Some(f);
                 }
             }
             crate::rpc::Predicate::Lt => {
-                if let Some(minv) = min_v {
-                    if compare_scalar(minv, target)
+                if let Some(minv) = min_v
+                    && compare_scalar(minv, target)
                         .map(|o| o.is_ge())
                         .unwrap_or(false)
-                    {
-                        return true;
-                    }
+                {
+                    return Some(f);
                 }
             }
             crate::rpc::Predicate::Le => {
-                if let Some(minv) = min_v {
-                    if compare_scalar(minv, target)
+                if let Some(minv) = min_v
+                    && compare_scalar(minv, target)
                         .map(|o| o.is_gt())
                         .unwrap_or(false)
-                    {
-                        return true;
-                    }
+                {
+                    return Some(f);
                 }
             }
             crate::rpc::Predicate::Gt => {
-                if let Some(maxv) = max_v {
-                    if compare_scalar(maxv, target)
+                if let Some(maxv) = max_v
+                    && compare_scalar(maxv, target)
                         .map(|o| o.is_le())
                         .unwrap_or(false)
-                    {
-                        return true;
-                    }
+                {
+                    return Some(f);
                 }
             }
             crate::rpc::Predicate::Ge => {
-                if let Some(maxv) = max_v {
-                    if compare_scalar(maxv, target)
+                if let Some(maxv) = max_v
+                    && compare_scalar(maxv, target)
                         .map(|o| o.is_lt())
                         .unwrap_or(false)
-                    {
-                        return true;
-                    }
+                {
+                    return Some(f);
                 }
             }
             crate::rpc::Predicate::Between => {
-                if let Some(hi) = &f.value_hi {
-                    if let (Some(minv), Some(maxv)) = (min_v, max_v) {
-                        if compare_scalar(maxv, target)
-                            .map(|o| o.is_lt())
-                            .unwrap_or(false)
-                            || compare_scalar(minv, hi).map(|o| o.is_gt()).unwrap_or(false)
-                        {
-                            return true;
-                        }
-                    }
+                if let Some(hi) = &f.value_hi
+                    && let (Some(minv), Some(maxv)) = (min_v, max_v)
+                    && (compare_scalar(maxv, target)
+                        .map(|o| o.is_lt())
+                        .unwrap_or(false)
+                        || compare_scalar(minv, hi).map(|o| o.is_gt()).unwrap_or(false))
+                {
+                    return Some(f);
+                }
+            }
+            crate::rpc::Predicate::In => {
+                if let Some(list) = &f.list
+                    && let (Some(minv), Some(maxv)) = (min_v, max_v)
+                    && in_list_values(list).into_iter().all(|v| {
+                        compare_scalar(v, minv).is_some_and(|o| o.is_lt())
+                            || compare_scalar(v, maxv).is_some_and(|o| o.is_gt())
+                    })
+                {
+                    return Some(f);
                 }
             }
         }
     }
-    false
-}
-
-fn compare_scalar(a: &ScalarValue, b: &ScalarValue) -> Option<std::cmp::Ordering> {
-    match (a, b) {
-        (ScalarValue::Int(x), ScalarValue::Int(y)) => Some(x.cmp(y)),
-        (ScalarValue::Float(x), ScalarValue::Float(y)) => x.partial_cmp(y),
-        (ScalarValue::Int(x), ScalarValue::Float(y)) => (*x as f64).partial_cmp(y),
-        (ScalarValue::Float(x), ScalarValue::Int(y)) => x.partial_cmp(&(*y as f64)),
-        (ScalarValue::String(x), ScalarValue::String(y)) => Some(x.cmp(y)),
-        (ScalarValue::Bool(x), ScalarValue::Bool(y)) => Some(x.cmp(y)),
-        _ => None,
-    }
+    None
 }
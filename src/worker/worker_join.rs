@@ -0,0 +1,189 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use super::worker_exec::{
+    WorkerContext, accumulate, compute_min_max, load_schema, pruning_filter, skipped_partial,
+};
+use super::worker_scan::{Row, SegmentScan};
+use crate::minisql::minisql_eval::compare_scalar;
+use crate::minisql::minisql_plan::{derive_group_by, scan_columns};
+use crate::minisql::minisql_print::format_filter;
+use crate::rpc::{GroupMap, JoinSpec, PartialAggregate, QueryRequest, ScalarValue};
+use crate::storage::storage_schema::ColumnDef;
+
+/// Executes an inner equi-join between segment `ctx.segment` of the left table
+/// and the same segment of the joined table. Both segments are sorted by their
+/// key columns and cover the same key range (see `load_table_colocated`), so
+/// the join is a single streaming merge pass with no data movement.
+pub fn execute_join(
+    ctx: &WorkerContext,
+    req: &QueryRequest,
+    join: &JoinSpec,
+    left_defs: &HashMap<String, ColumnDef>,
+    started: std::time::Instant,
+) -> PartialAggregate {
+    let mut groups: GroupMap = HashMap::new();
+    let right_schema = load_schema(&join.right_table);
+    if right_schema.is_empty() {
+        return skipped_partial(ctx, started, "missing schema");
+    }
+    let right_defs: HashMap<String, ColumnDef> = right_schema
+        .iter()
+        .map(|c| (c.name.clone(), c.clone()))
+        .collect();
+
+    let group_by = derive_group_by(req);
+    let (mut left_needed, mut right_needed) = split_columns(
+        &scan_columns(req).into_iter().collect(),
+        join,
+        left_defs,
+        &right_defs,
+    );
+    left_needed.insert(join.left_key.clone());
+    right_needed.insert(join.right_key.clone());
+
+    let (Some(mut left), Some(mut right)) = (
+        SegmentScan::open(&req.table, ctx.segment, left_defs, &left_needed),
+        SegmentScan::open(&join.right_table, ctx.segment, &right_defs, &right_needed),
+    ) else {
+        return skipped_partial(ctx, started, "missing column files");
+    };
+    // Zone map pruning only considers filters on left-hand columns.
+    if let Some(min_max) = compute_min_max(&left.dirs, left_defs, &req.filters)
+        && let Some(f) = pruning_filter(&req.filters, &min_max)
+    {
+        return skipped_partial(ctx, started, &format!("zone map ({})", format_filter(f)));
+    }
+    let bytes_read = left.bytes_read + right.bytes_read;
+
+    let mut grouping_groups = vec![GroupMap::new(); req.grouping_sets.len()];
+    let mut rows_scanned = 0u64;
+    let mut rows_matched = 0u64;
+    let mut l = next_row(&mut left, &mut rows_scanned);
+    let mut r = next_row(&mut right, &mut rows_scanned);
+
+    while let (Some(lrow), Some(rrow)) = (&l, &r) {
+        let ord = match (key_of(lrow, &join.left_key), key_of(rrow, &join.right_key)) {
+            (Some(a), Some(b)) => compare_scalar(a, b).unwrap_or(Ordering::Less),
+            // NULL keys never match.
+            (None, _) => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        };
+
+        match ord {
+            Ordering::Less => l = next_row(&mut left, &mut rows_scanned),
+            Ordering::Greater => r = next_row(&mut right, &mut rows_scanned),
+            Ordering::Equal => {
+                let key = r
+                    .as_ref()
+                    .and_then(|row| key_of(row, &join.right_key))
+                    .cloned()
+                    .unwrap();
+
+                // Buffer the run of right rows sharing this key...
+                let mut run = Vec::new();
+                while let Some(rrow) = r.take() {
+                    if !key_equals(&rrow, &join.right_key, &key) {
+                        r = Some(rrow);
+                        break;
+                    }
+                    run.push(rrow);
+                    r = next_row(&mut right, &mut rows_scanned);
+                }
+
+                // ...and pair it with every left row of the same key.
+                while let Some(lrow) = l.take() {
+                    if !key_equals(&lrow, &join.left_key, &key) {
+                        l = Some(lrow);
+                        break;
+                    }
+                    for rrow in &run {
+                        let joined = combine(&lrow, rrow, join);
+                        if accumulate(&mut groups, &mut grouping_groups, req, &group_by, &joined) {
+                            rows_matched += 1;
+                        }
+                    }
+                    l = next_row(&mut left, &mut rows_scanned);
+                }
+            }
+        }
+    }
+
+    PartialAggregate {
+        worker_port: ctx.port,
+        segment: ctx.segment,
+        rows_scanned,
+        rows_matched,
+        bytes_read,
+        segments_skipped: 0,
+        skip_reason: None,
+        exec_us: started.elapsed().as_micros() as u64,
+        groups,
+        grouping_groups,
+        rows: None,
+        error: None,
+    }
+}
+
+/// Assigns each referenced column to the side of the join it belongs to.
+/// Qualified names (`table.column`) go to the named table; bare names prefer
+/// the left table.
+fn split_columns(
+    needed: &HashSet<String>,
+    join: &JoinSpec,
+    left_defs: &HashMap<String, ColumnDef>,
+    right_defs: &HashMap<String, ColumnDef>,
+) -> (HashSet<String>, HashSet<String>) {
+    let mut left = HashSet::new();
+    let mut right = HashSet::new();
+    for col in needed {
+        match col.split_once('.') {
+            Some((q, name)) if q == join.left_name => {
+                left.insert(name.to_string());
+            }
+            Some((q, name)) if q == join.right_name => {
+                right.insert(name.to_string());
+            }
+            Some(_) => {}
+            None => {
+                if left_defs.contains_key(col) {
+                    left.insert(col.clone());
+                } else if right_defs.contains_key(col) {
+                    right.insert(col.clone());
+                }
+            }
+        }
+    }
+    (left, right)
+}
+
+fn next_row(scan: &mut SegmentScan, rows_scanned: &mut u64) -> Option<Row> {
+    let row = scan.next_row()?;
+    *rows_scanned += 1;
+    Some(row)
+}
+
+fn key_of<'a>(row: &'a Row, key: &str) -> Option<&'a ScalarValue> {
+    row.get(key).and_then(|v| v.as_ref())
+}
+
+fn key_equals(row: &Row, key: &str, target: &ScalarValue) -> bool {
+    key_of(row, key)
+        .and_then(|v| compare_scalar(v, target))
+        .is_some_and(|o| o.is_eq())
+}
+
+/// Builds the joined row; every column is reachable as `table.column` and,
+/// unless shadowed by a left-hand column, by its bare name.
+fn combine(left: &Row, right: &Row, join: &JoinSpec) -> Row {
+    let mut row = HashMap::with_capacity((left.len() + right.len()) * 2);
+    for (name, v) in left {
+        row.insert(format!("{}.{}", join.left_name, name), v.clone());
+        row.insert(name.clone(), v.clone());
+    }
+    for (name, v) in right {
+        row.insert(format!("{}.{}", join.right_name, name), v.clone());
+        row.entry(name.clone()).or_insert_with(|| v.clone());
+    }
+    row
+}
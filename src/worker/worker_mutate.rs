@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::worker_exec::{
+    WorkerContext, accumulate_row, compute_min_max, load_schema, open_readers, pruning_filter,
+    skipped_partial,
+};
+use super::worker_scan::SegmentScan;
+use crate::minisql::minisql_eval::{ReadError, read_value};
+use crate::minisql::minisql_plan::scan_columns;
+use crate::minisql::minisql_print::format_filter;
+use crate::rpc::{
+    ArithOp, AssignedValue, Assignment, GroupMap, Mutation, PartialAggregate, QueryRequest,
+    ScalarValue,
+};
+use crate::storage::storage_append::StoredRow;
+use crate::storage::storage_deletion::DeletionVector;
+use crate::storage::storage_rewrite::rewrite_part;
+use crate::storage::storage_schema::{ColumnDef, ColumnType};
+
+/// Mutations of a segment run one at a time, so two of them never read and
+/// replace the same deletion vector or generation concurrently.
+static MUTATIONS: Mutex<()> = Mutex::new(());
+
+/// Applies `req.mutation` to the rows of the segment that match the
+/// filters. The partial counts them like `COUNT(*)`.
+pub fn execute_mutation(
+    ctx: &WorkerContext,
+    req: &QueryRequest,
+    mutation: &Mutation,
+    defs: &HashMap<String, ColumnDef>,
+    started: std::time::Instant,
+) -> PartialAggregate {
+    let _guard = MUTATIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut needed: HashSet<String> = scan_columns(req).into_iter().collect();
+    // Without filters nothing would be read at all.
+    if let Some(key) = defs.values().find(|c| c.is_key) {
+        needed.insert(key.name.clone());
+    }
+    let Some(mut scan) = SegmentScan::open(&req.table, ctx.segment, defs, &needed) else {
+        return skipped_partial(ctx, started, "missing column files");
+    };
+    if let Some(min_max) = compute_min_max(&scan.dirs, defs, &req.filters)
+        && let Some(f) = pruning_filter(&req.filters, &min_max)
+    {
+        return skipped_partial(ctx, started, &format!("zone map ({})", format_filter(f)));
+    }
+
+    let mut groups = GroupMap::new();
+    let mut rows_scanned = 0u64;
+    let mut matched: Vec<Vec<u64>> = vec![Vec::new(); scan.dirs.len()];
+    while let Some((part, position, row)) = scan.next_located() {
+        rows_scanned += 1;
+        if accumulate_row(&mut groups, req, &[], &row) {
+            matched[part].push(position);
+        }
+    }
+
+    let applied = match mutation {
+        Mutation::Delete => delete_rows(&scan, &matched),
+        Mutation::Update(assignments) => update_rows(&req.table, &scan, &matched, assignments),
+    };
+    PartialAggregate {
+        worker_port: ctx.port,
+        segment: ctx.segment,
+        rows_scanned,
+        rows_matched: matched.iter().map(|m| m.len() as u64).sum(),
+        bytes_read: scan.bytes_read,
+        segments_skipped: 0,
+        skip_reason: None,
+        exec_us: started.elapsed().as_micros() as u64,
+        groups,
+        grouping_groups: Vec::new(),
+        rows: None,
+        error: applied.err(),
+    }
+}
+
+/// Adds the matched positions to the deletion vector of every part that
+/// has any.
+fn delete_rows(scan: &SegmentScan, matched: &[Vec<u64>]) -> Result<(), String> {
+    for (part, positions) in matched.iter().enumerate() {
+        if positions.is_empty() {
+            continue;
+        }
+        let mut deleted = scan.deletion_vector(part).clone();
+        for &position in positions {
+            deleted.delete(position);
+        }
+        deleted.write(&scan.dirs[part])?;
+    }
+    Ok(())
+}
+
+/// Rewrites every part that has matched rows as a new generation
+/// (`rewrite_part`): the matched rows get the assigned values and deleted
+/// rows are left out. Parts without matches are not touched. Every part is
+/// read before any is replaced, but each switches on its own; a failure
+/// names the parts already rewritten.
+fn update_rows(
+    table: &str,
+    scan: &SegmentScan,
+    matched: &[Vec<u64>],
+    assignments: &[Assignment],
+) -> Result<(), String> {
+    let schema = load_schema(table);
+    let index = |name: &str| {
+        schema
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| format!("Unknown column '{}'", name))
+    };
+    let mut resolved = Vec::with_capacity(assignments.len());
+    for a in assignments {
+        let source = match &a.value {
+            AssignedValue::Value(v) => Source::Value(v),
+            AssignedValue::Column(name) => Source::Column(index(name)?),
+            AssignedValue::Arithmetic { column, op, value } => {
+                Source::Arithmetic(index(column)?, *op, value)
+            }
+        };
+        resolved.push((index(&a.column)?, source));
+    }
+
+    let mut updated = Vec::new();
+    for (part, positions) in matched.iter().enumerate() {
+        if positions.is_empty() {
+            continue;
+        }
+        let positions: HashSet<u64> = positions.iter().copied().collect();
+        let dir = &scan.dirs[part];
+        let mut rows = read_part(dir, &schema, scan.deletion_vector(part))?;
+        for (position, row) in rows.iter_mut() {
+            if !positions.contains(position) {
+                continue;
+            }
+            let before = row.clone();
+            for (target, source) in &resolved {
+                let col = &schema[*target];
+                row[*target] = match source {
+                    Source::Value(v) => (*v).clone(),
+                    Source::Column(src) => widen(before[*src].clone(), &col.col_type),
+                    Source::Arithmetic(src, op, value) => {
+                        let result = arithmetic(&before[*src], *op, value).ok_or_else(|| {
+                            format!(
+                                "Integer overflow computing column '{}' of row {}",
+                                col.name, position
+                            )
+                        })?;
+                        fit(widen(result, &col.col_type), col)?
+                    }
+                };
+            }
+        }
+        let rows: Vec<StoredRow> = rows.into_iter().map(|(_, row)| row).collect();
+        updated.push((dir, rows));
+    }
+
+    let mut rewritten: Vec<String> = Vec::new();
+    for (dir, rows) in updated {
+        if let Err(e) = rewrite_part(dir, &schema, &rows) {
+            return Err(if rewritten.is_empty() {
+                e
+            } else {
+                format!("{}; parts already rewritten: {}", e, rewritten.join(", "))
+            });
+        }
+        rewritten.push(dir.strip_prefix(table).unwrap_or(dir).display().to_string());
+    }
+    Ok(())
+}
+
+/// The live rows of one part with their positions, in schema order. Unlike
+/// a scan, a column that cannot be read is an error: the part is about to
+/// be replaced by what is read here.
+fn read_part(
+    dir: &Path,
+    schema: &[ColumnDef],
+    deleted: &DeletionVector,
+) -> Result<Vec<(u64, StoredRow)>, String> {
+    let defs: HashMap<String, ColumnDef> =
+        schema.iter().map(|c| (c.name.clone(), c.clone())).collect();
+    let mut readers = open_readers(dir, &defs, &defs.keys().cloned().collect())
+        .ok_or_else(|| format!("Failed to open the column files in {:?}", dir))?;
+    // The key is read first: it always has a file, so it ends with the rows.
+    let key = schema
+        .iter()
+        .position(|c| c.is_key)
+        .ok_or("Schema has no column marked as `key`")?;
+    let order: Vec<usize> = std::iter::once(key)
+        .chain((0..schema.len()).filter(|&i| i != key))
+        .collect();
+    let mut rows = Vec::new();
+    let mut position = 0u64;
+    loop {
+        let mut row = vec![None; schema.len()];
+        for &idx in &order {
+            let col = &schema[idx];
+            let reader = readers.get_mut(&col.name).ok_or("Missing column reader")?;
+            match read_value(reader, col) {
+                Ok(v) => row[idx] = v,
+                Err(ReadError::Eof) if idx == key => return Ok(rows),
+                Err(_) => {
+                    return Err(format!(
+                        "Failed to read row {} of column '{}' in {:?}",
+                        position, col.name, dir
+                    ));
+                }
+            }
+        }
+        if !deleted.is_deleted(position) {
+            rows.push((position, row));
+        }
+        position += 1;
+    }
+}
+
+/// Where an assigned value comes from, with columns as schema positions.
+enum Source<'a> {
+    Value(&'a Option<ScalarValue>),
+    Column(usize),
+    Arithmetic(usize, ArithOp, &'a ScalarValue),
+}
+
+/// `left op right`; `None` when integers overflow. Integers stay integers
+/// (division truncates); a float on either side makes a float.
+fn arithmetic(
+    left: &Option<ScalarValue>,
+    op: ArithOp,
+    right: &ScalarValue,
+) -> Option<Option<ScalarValue>> {
+    let Some(left) = left else {
+        return Some(None);
+    };
+    let value = match (left, right) {
+        (ScalarValue::Int(a), ScalarValue::Int(b)) => ScalarValue::Int(match op {
+            ArithOp::Add => a.checked_add(*b)?,
+            ArithOp::Sub => a.checked_sub(*b)?,
+            ArithOp::Mul => a.checked_mul(*b)?,
+            ArithOp::Div => a.checked_div(*b)?,
+        }),
+        _ => {
+            let as_float = |v: &ScalarValue| match v {
+                ScalarValue::Int(i) => *i as f64,
+                ScalarValue::Float(f) => *f,
+                _ => f64::NAN,
+            };
+            let (a, b) = (as_float(left), as_float(right));
+            ScalarValue::Float(match op {
+                ArithOp::Add => a + b,
+                ArithOp::Sub => a - b,
+                ArithOp::Mul => a * b,
+                ArithOp::Div => a / b,
+            })
+        }
+    };
+    Some(Some(value))
+}
+
+/// A computed value checked against the range of an `int32` column.
+fn fit(value: Option<ScalarValue>, col: &ColumnDef) -> Result<Option<ScalarValue>, String> {
+    match (&value, &col.col_type) {
+        (Some(ScalarValue::Int(i)), ColumnType::Int32) if i32::try_from(*i).is_err() => {
+            Err(format!(
+                "Value {} is out of range for column '{}' (int32)",
+                i, col.name
+            ))
+        }
+        _ => Ok(value),
+    }
+}
+
+/// Integers copied into a `float64` column become floats.
+fn widen(value: Option<ScalarValue>, target: &ColumnType) -> Option<ScalarValue> {
+    match (value, target) {
+        (Some(ScalarValue::Int(i)), ColumnType::Float64) => Some(ScalarValue::Float(i as f64)),
+        (value, _) => value,
+    }
+}
@@ -0,0 +1,160 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::worker_exec::{column_bytes, open_readers, read_row};
+use crate::minisql::minisql_eval::{ReaderState, compare_scalar};
+use crate::rpc::ScalarValue;
+use crate::storage::storage_deletion::DeletionVector;
+use crate::storage::storage_schema::ColumnDef;
+use crate::storage::storage_segment::numbered_parts;
+
+pub type Row = HashMap<String, Option<ScalarValue>>;
+
+/// Rows of one base segment and its delta segments, merged by the table key
+/// so they come out in key order like a single segment would. Rows in a
+/// segment's deletion vector are skipped.
+pub struct SegmentScan {
+    parts: Vec<PartCursor>,
+    defs: HashMap<String, ColumnDef>,
+    key: Option<String>,
+    pub dirs: Vec<PathBuf>,
+    pub bytes_read: u64,
+}
+
+struct PartCursor {
+    readers: HashMap<String, ReaderState>,
+    deleted: DeletionVector,
+    head: Option<Row>,
+    head_position: u64,
+    next_position: u64,
+}
+
+impl PartCursor {
+    /// Moves `head` to the next live row.
+    fn advance(&mut self, defs: &HashMap<String, ColumnDef>) {
+        loop {
+            let position = self.next_position;
+            self.head = if self.readers.is_empty() {
+                None
+            } else {
+                read_row(&mut self.readers, defs)
+            };
+            self.head_position = position;
+            self.next_position += 1;
+            if self.head.is_none() || !self.deleted.is_deleted(position) {
+                return;
+            }
+        }
+    }
+}
+
+impl SegmentScan {
+    /// Opens `needed` columns in every part of `segment`; `None` if the
+    /// segment, one of its column files or its deletion vector cannot be
+    /// read.
+    pub fn open(
+        table_dir: &str,
+        segment: u32,
+        defs: &HashMap<String, ColumnDef>,
+        needed: &HashSet<String>,
+    ) -> Option<SegmentScan> {
+        Self::open_parts(table_dir, segment, None, defs, needed)
+    }
+
+    /// Like `open`, reading only the parts whose segment directory is in
+    /// `only` (e.g. those a materialized view has not aggregated yet).
+    pub fn open_parts(
+        table_dir: &str,
+        segment: u32,
+        only: Option<&[u32]>,
+        defs: &HashMap<String, ColumnDef>,
+        needed: &HashSet<String>,
+    ) -> Option<SegmentScan> {
+        let dirs: Vec<PathBuf> = numbered_parts(Path::new(table_dir), segment)
+            .into_iter()
+            .filter(|(number, _)| only.is_none_or(|only| only.contains(number)))
+            .map(|(_, dir)| dir)
+            .collect();
+        if dirs.is_empty() {
+            return None;
+        }
+        let key = defs.values().find(|c| c.is_key).map(|c| c.name.clone());
+        let mut needed = needed.clone();
+        // Merging parts needs their keys.
+        if dirs.len() > 1
+            && let Some(key) = &key
+        {
+            needed.insert(key.clone());
+        }
+
+        let mut parts = Vec::with_capacity(dirs.len());
+        let mut bytes_read = 0;
+        for dir in &dirs {
+            let readers = open_readers(dir, defs, &needed)?;
+            bytes_read += column_bytes(dir, defs, readers.keys());
+            let mut part = PartCursor {
+                readers,
+                deleted: DeletionVector::read(dir).ok()?,
+                head: None,
+                head_position: 0,
+                next_position: 0,
+            };
+            part.advance(defs);
+            parts.push(part);
+        }
+        Some(SegmentScan {
+            parts,
+            defs: defs.clone(),
+            key: key.filter(|_| dirs.len() > 1),
+            dirs,
+            bytes_read,
+        })
+    }
+
+    /// Whether any column is read at all.
+    pub fn is_empty(&self) -> bool {
+        self.parts.iter().all(|p| p.readers.is_empty())
+    }
+
+    pub fn next_row(&mut self) -> Option<Row> {
+        self.next_located().map(|(_, _, row)| row)
+    }
+
+    /// The next row with the index of its part (in `dirs`) and its position
+    /// in that part.
+    pub fn next_located(&mut self) -> Option<(usize, u64, Row)> {
+        let mut next: Option<usize> = None;
+        for (i, part) in self.parts.iter().enumerate() {
+            let Some(row) = &part.head else { continue };
+            let Some(best) = next else {
+                next = Some(i);
+                continue;
+            };
+            // Ties go to the earlier part, so the base segment comes first.
+            if let Some(key) = &self.key
+                && compare_keys(row.get(key), self.parts[best].head.as_ref()?.get(key)).is_lt()
+            {
+                next = Some(i);
+            }
+        }
+        let idx = next?;
+        let part = &mut self.parts[idx];
+        let row = part.head.take()?;
+        let position = part.head_position;
+        part.advance(&self.defs);
+        Some((idx, position, row))
+    }
+
+    /// Deletion vector of part `idx` as read when the scan was opened.
+    pub fn deletion_vector(&self, idx: usize) -> &DeletionVector {
+        &self.parts[idx].deleted
+    }
+}
+
+fn compare_keys(a: Option<&Option<ScalarValue>>, b: Option<&Option<ScalarValue>>) -> Ordering {
+    match (a.and_then(Option::as_ref), b.and_then(Option::as_ref)) {
+        (Some(a), Some(b)) => compare_scalar(a, b).unwrap_or(Ordering::Equal),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
@@ -0,0 +1,1526 @@
+use minidist::coordinator::coordinator_cluster::count_segments;
+use minidist::coordinator::coordinator_prepare::{StatementCache, execute_prepared};
+use minidist::coordinator::coordinator_route::{
+    QueryResult, resolve_tables, run_query, run_query_result,
+};
+use minidist::coordinator::coordinator_session::{
+    OutputFormat, Settings, run_script, run_statements,
+};
+use minidist::minisql::minisql_error::QueryError;
+use minidist::minisql::minisql_params::bind_params;
+use minidist::minisql::minisql_parse::{parse_script, parse_sql};
+use minidist::rpc::ScalarValue;
+use minidist::storage::storage_init::init_table;
+use minidist::storage::storage_load::{load_table, load_table_colocated};
+use minidist::storage::storage_schema::{ColumnType, parse_schema_file};
+use minidist::worker::worker_exec::WorkerContext;
+use minidist::worker::worker_server;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn tmp_dir(prefix: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut path = std::env::temp_dir();
+    path.push(format!("minidist-coord-{}-{}", prefix, n));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn build_table(root: &Path, name: &str, ssf: &str, csv: &str, segments: usize) -> PathBuf {
+    let dir = root.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    let schema_path = root.join(format!("{}.ssf", name));
+    let csv_path = root.join(format!("{}.csv", name));
+    fs::write(&schema_path, ssf).unwrap();
+    fs::write(&csv_path, csv).unwrap();
+    init_table(&dir, &schema_path).unwrap();
+    let schema = parse_schema_file(ssf).unwrap();
+    let key = schema.iter().find(|c| c.is_key).unwrap().name.clone();
+    load_table(&dir, &csv_path, &key, segments, &schema).unwrap();
+    dir
+}
+
+/// Like `build_table`, split at the segment boundaries of `reference`.
+fn build_colocated(root: &Path, name: &str, ssf: &str, csv: &str, reference: &Path) -> PathBuf {
+    let dir = root.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    let schema_path = root.join(format!("{}.ssf", name));
+    let csv_path = root.join(format!("{}.csv", name));
+    fs::write(&schema_path, ssf).unwrap();
+    fs::write(&csv_path, csv).unwrap();
+    init_table(&dir, &schema_path).unwrap();
+    let schema = parse_schema_file(ssf).unwrap();
+    let key = schema.iter().find(|c| c.is_key).unwrap().name.clone();
+    let segments = count_segments(reference).unwrap();
+    load_table_colocated(&dir, &csv_path, &key, segments, &schema, reference).unwrap();
+    dir
+}
+
+async fn spawn_workers(table_dir: &Path, segments: u32) -> Vec<u16> {
+    let mut ports = Vec::new();
+    for segment in 0..segments {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let ctx = WorkerContext {
+            port,
+            table: table_dir.to_string_lossy().to_string(),
+            segment,
+        };
+        tokio::spawn(worker_server::serve(ctx));
+        ports.push(port);
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    ports
+}
+
+async fn query(ports: &[u16], table_dir: &Path, sql: &str) -> String {
+    let mut req = parse_sql(sql).expect("parse");
+    let data = table_dir.parent().unwrap();
+    resolve_tables(&mut req, &data.to_string_lossy()).unwrap();
+    run_query(ports, req).await.expect("query")
+}
+
+fn result_lines(out: &str) -> Vec<String> {
+    out.lines()
+        .take_while(|l| !l.is_empty())
+        .map(|l| l.split('|').map(|c| c.trim()).collect::<Vec<_>>().join("|"))
+        .collect()
+}
+
+#[tokio::test]
+async fn scalar_subquery_is_inlined() {
+    let root = tmp_dir("scalar");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT COUNT(*) FROM sales WHERE amount > (SELECT AVG(amount) FROM sales);",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "count_star");
+    assert_eq!(lines[2], "2");
+    // Rows scanned by the subquery are part of the totals.
+    assert!(out.contains("Rows scanned:       8"), "{}", out);
+}
+
+#[tokio::test]
+async fn in_subquery_reads_another_table() {
+    let root = tmp_dir("in");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_table(&root, "vip", VIP_SSF, VIP_CSV, 1);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, SUM(amount) FROM sales \
+         WHERE region IN (SELECT region FROM vip WHERE tier >= 2) GROUP BY region;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines.len(), 4, "{}", out);
+    assert_eq!(lines[2], "APAC|300.000");
+    assert_eq!(lines[3], "EU|150.000");
+}
+
+#[tokio::test]
+async fn large_in_list_is_hashed() {
+    let root = tmp_dir("hashed");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let evens: Vec<String> = (1..=100).map(|i| (i * 2).to_string()).collect();
+    let sql = format!(
+        "SELECT COUNT(*) FROM sales WHERE id IN ({});",
+        evens.join(", ")
+    );
+    let lines = result_lines(&query(&ports, &sales, &sql).await);
+    assert_eq!(lines[2], "2");
+}
+
+#[tokio::test]
+async fn cte_feeds_outer_aggregate() {
+    let root = tmp_dir("cte");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "WITH per_region AS (SELECT region, SUM(amount) AS total FROM sales GROUP BY region) \
+         SELECT AVG(total), MAX(total) FROM per_region WHERE total > 150;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "avg_total|max_total");
+    assert_eq!(lines[2], "250.000|300.000");
+}
+
+#[tokio::test]
+async fn order_by_and_limit() {
+    let root = tmp_dir("order");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, SUM(amount) AS total FROM sales GROUP BY region \
+         ORDER BY total DESC LIMIT 2;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines.len(), 4, "{}", out);
+    assert_eq!(lines[0], "region|total");
+    assert_eq!(lines[2], "APAC|300.000");
+    assert_eq!(lines[3], "US|200.000");
+}
+
+#[tokio::test]
+async fn set_operations_combine_tables() {
+    let root = tmp_dir("union");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_table(&root, "vip", VIP_SSF, VIP_CSV, 1);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region FROM sales UNION ALL SELECT region FROM vip ORDER BY region;",
+    )
+    .await;
+    assert_eq!(result_lines(&out).len(), 8, "{}", out);
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region FROM sales UNION SELECT region FROM vip ORDER BY region DESC;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[2..], ["US", "EU", "APAC"]);
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region FROM sales EXCEPT SELECT region FROM vip WHERE tier >= 2 \
+         INTERSECT SELECT region FROM vip WHERE tier < 3;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[2..], ["APAC", "US"]);
+
+    let mut req =
+        parse_sql("SELECT region FROM sales UNION SELECT COUNT(*), MAX(id) FROM sales;").unwrap();
+    resolve_tables(&mut req, &root.to_string_lossy()).unwrap();
+    let err = run_query(&ports, req).await.unwrap_err();
+    assert!(err.to_string().contains("returns 2 columns"), "{}", err);
+}
+
+#[tokio::test]
+async fn window_functions() {
+    let root = tmp_dir("window");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    // Partitioned on a non-key column: evaluated on the coordinator.
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, amount, RANK() OVER (PARTITION BY region ORDER BY amount DESC) AS r \
+         FROM sales ORDER BY region, r;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "region|amount|r");
+    assert_eq!(
+        lines[2..],
+        [
+            "APAC|300.000|1",
+            "EU|100.000|1",
+            "EU|50.000|2",
+            "US|200.000|1"
+        ]
+    );
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT id, SUM(amount) OVER (ORDER BY id) AS running, LAG(amount) OVER (ORDER BY id) \
+         FROM sales ORDER BY id;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "id|running|lag_amount");
+    assert_eq!(
+        lines[2..],
+        [
+            "1|100.000|NULL",
+            "2|300.000|100.000",
+            "3|350.000|200.000",
+            "4|650.000|50.000"
+        ]
+    );
+
+    // Partitioned on the key: evaluated by the workers.
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT id, ROW_NUMBER() OVER (PARTITION BY id ORDER BY amount) FROM sales WHERE id > 1;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "id|row_number");
+    assert_eq!(lines[2..], ["2|1", "3|1", "4|1"]);
+
+    let out = query(
+        &ports,
+        &sales,
+        "WITH ranked AS (SELECT region, amount, \
+         ROW_NUMBER() OVER (PARTITION BY region ORDER BY amount DESC) AS rn FROM sales) \
+         SELECT region, amount FROM ranked WHERE rn = 1 ORDER BY amount DESC LIMIT 2;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[2..], ["APAC|300.000", "US|200.000"]);
+}
+
+#[tokio::test]
+async fn rollup_and_grouping_sets() {
+    let root = tmp_dir("rollup");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, GROUPING(region), SUM(amount) FROM sales GROUP BY ROLLUP(region);",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "region|grouping_region|sum_amount");
+    assert_eq!(
+        lines[2..],
+        [
+            "APAC|0|300.000",
+            "EU|0|150.000",
+            "US|0|200.000",
+            "NULL|1|650.000"
+        ]
+    );
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, id, COUNT(*) AS n FROM sales WHERE amount < 250 \
+         GROUP BY GROUPING SETS ((region, id), (), region) ORDER BY n DESC LIMIT 3;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "region|id|n");
+    assert_eq!(lines[2..], ["NULL|NULL|3", "EU|NULL|2", "EU|1|1"]);
+}
+
+#[tokio::test]
+async fn columns_follow_select_order() {
+    let root = tmp_dir("select_order");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT SUM(amount), region, COUNT(*) FROM sales GROUP BY region;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "sum_amount|region|count_star");
+    assert_eq!(
+        lines[2..],
+        ["300.000|APAC|1", "150.000|EU|2", "200.000|US|1"]
+    );
+
+    let out = query(&ports, &sales, "SELECT id, region FROM sales WHERE id = 1;").await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "id|region");
+    assert_eq!(lines[2..], ["1|EU"]);
+}
+
+#[tokio::test]
+async fn aggregate_filters_share_one_scan() {
+    let root = tmp_dir("filter");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT COUNT(*), COUNT(*) FILTER (WHERE active), \
+         SUM(amount) FILTER (WHERE region = \"EU\" AND active = true) AS eu FROM sales;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "count_star|count_star_where_active|eu");
+    assert_eq!(lines[2], "4|3|150.000");
+    assert!(out.contains("Rows scanned:       4"), "{}", out);
+
+    let out = query(
+        &ports,
+        &sales,
+        "SELECT region, SUM(amount) FILTER (WHERE amount > 60) FROM sales \
+         WHERE active GROUP BY region;",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "region|sum_amount_where_amount_60");
+    assert_eq!(lines[2..], ["APAC|300.000", "EU|100.000"]);
+}
+
+#[tokio::test]
+async fn group_keys_keep_values_apart() {
+    let root = tmp_dir("keys");
+    let ssf = "id: int64 key\na: string nullable\nb: string nullable\n";
+    // ("x|y", "z") and ("x", "y|z") both used to become the key "x|y|z";
+    // the string "NULL" used to collide with a real NULL.
+    let csv = "id,a,b\n1,x|y,z\n2,x,y|z\n3,NULL,z\n4,,z\n";
+    let t = build_table(&root, "t", ssf, csv, 2);
+    let ports = spawn_workers(&t, 2).await;
+
+    let out = query(&ports, &t, "SELECT a, b, COUNT(*) FROM t GROUP BY a, b;").await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "a|b|count_star");
+    assert_eq!(lines.len(), 6, "{}", out);
+}
+
+#[tokio::test]
+async fn explain_and_explain_analyze() {
+    let root = tmp_dir("explain");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let sql = "SELECT region, SUM(amount) AS total FROM sales WHERE id > 2 \
+               GROUP BY region ORDER BY total DESC LIMIT 1;";
+    let out = query(&ports, &sales, &format!("EXPLAIN {}", sql)).await;
+    assert!(
+        out.contains("Scan sales [region, amount, id] WHERE id > 2"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("Merge: merge partial aggregates by region"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains(&format!(
+            "seg-000000 on worker {}: pruned by zone map (id > 2)",
+            ports[0]
+        )),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains(&format!("seg-000001 on worker {}: scan", ports[1])),
+        "{}",
+        out
+    );
+
+    let out = query(&ports, &sales, &format!("EXPLAIN ANALYZE {}", sql)).await;
+    assert!(out.contains("skipped, zone map (id > 2)"), "{}", out);
+    assert!(out.contains("scanned 2, matched 2, out 2"), "{}", out);
+    assert!(out.contains("Limit 1: 2 -> 1"), "{}", out);
+    assert!(out.contains("Result: 1 rows"), "{}", out);
+    assert!(out.contains("segments pruned: 1"), "{}", out);
+}
+
+#[tokio::test]
+async fn stats_break_down_per_worker() {
+    let root = tmp_dir("stats");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+
+    let mut req = parse_sql(
+        "SELECT COUNT(*) FROM sales WHERE id > 2 AND amount < (SELECT MAX(amount) FROM sales);",
+    )
+    .unwrap();
+    resolve_tables(&mut req, &root.to_string_lossy()).unwrap();
+    let result = run_query_result(&ports, req).await.expect("query");
+    let stats = &result.stats;
+
+    // The subquery scans both segments; the outer query prunes segment 0.
+    assert_eq!(stats.rows_scanned, 6);
+    assert_eq!(stats.segments_skipped, 1);
+    assert_eq!(stats.workers.len(), 2);
+    let (w0, w1) = (&stats.workers[0], &stats.workers[1]);
+    assert_eq!((w0.worker_port, w0.segment, w0.requests), (ports[0], 0, 2));
+    assert_eq!(w0.pruned_by, vec!["zone map (id > 2)"]);
+    assert_eq!(
+        (w1.rows_scanned, w1.rows_matched, w1.segments_pruned),
+        (4, 3, 0)
+    );
+    assert!(w1.bytes_read > 0);
+    assert_eq!(w0.retries + w1.retries, 0);
+    for w in &stats.workers {
+        assert!(w.latency_us >= w.exec_us + w.serialize_us);
+        assert!(stats.wall_us >= w.latency_us);
+    }
+    assert!(result.to_text().contains("Segments skipped:   1"));
+}
+
+#[tokio::test]
+async fn prepared_statements_take_parameters() {
+    let root = tmp_dir("prepare");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy();
+
+    let mut cache = StatementCache::default();
+    let sql = "SELECT region, SUM(amount) AS total FROM sales \
+               WHERE id BETWEEN $1 AND $2 AND region IN ($3, \"US\") \
+               GROUP BY region ORDER BY region;";
+    let stmt = cache.prepare(sql, &data).expect("prepare");
+    assert_eq!(stmt.params, 3);
+    assert_eq!(cache.prepare(sql, &data).unwrap().id, stmt.id);
+    assert_eq!(cache.len(), 1);
+    // Bound once: the values are converted to the recorded column types.
+    let id_param = &stmt.request.filters[0].params[0];
+    assert_eq!(id_param.column_type, Some(ColumnType::Int64));
+    let mut req = stmt.request.clone();
+    let err = bind_params(
+        &mut req,
+        &[
+            ScalarValue::Int(1),
+            ScalarValue::Bool(true),
+            ScalarValue::String("EU".into()),
+        ],
+    )
+    .unwrap_err();
+    assert!(
+        err.message().contains("Cannot compare column 'id'"),
+        "{}",
+        err
+    );
+
+    let run = |values: Vec<ScalarValue>| {
+        let (ports, stmt) = (&ports, stmt.clone());
+        async move {
+            let r = execute_prepared(ports, &stmt, &values, &Settings::default()).await?;
+            Ok::<_, QueryError>(result_lines(&r.to_text()))
+        }
+    };
+    let all = run(vec![
+        ScalarValue::Int(1),
+        ScalarValue::Int(4),
+        ScalarValue::String("EU".into()),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(all[2..], ["EU|150.000", "US|200.000"]);
+    let pruned = run(vec![
+        ScalarValue::Int(3),
+        ScalarValue::Int(4),
+        ScalarValue::String("APAC".into()),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(pruned[2..], ["APAC|300.000"]);
+
+    let err = run(vec![ScalarValue::Int(1)]).await.unwrap_err();
+    assert!(matches!(err, QueryError::Bind { .. }), "{:?}", err);
+    let err = run(vec![
+        ScalarValue::String("x".into()),
+        ScalarValue::Int(4),
+        ScalarValue::String("EU".into()),
+    ])
+    .await
+    .unwrap_err();
+    assert!(matches!(err, QueryError::Bind { .. }), "{:?}", err);
+    assert!(matches!(
+        cache.get("nope", &data),
+        Err(QueryError::NotFound { .. })
+    ));
+
+    // Statements are resolved again once the catalog changes.
+    let mut settings = Settings::default();
+    let mut ddl = async |sql: &str| run_script(&ports, &data, sql, &mut settings).await;
+    ddl("CREATE VIEW cheap AS SELECT id, amount FROM sales WHERE amount < 150")
+        .await
+        .unwrap();
+    let view_sql = "SELECT COUNT(*) FROM cheap WHERE id > $1";
+    let by_view = cache.prepare(view_sql, &data).unwrap();
+    ddl("DROP VIEW cheap; CREATE VIEW cheap AS SELECT id, amount FROM sales WHERE amount < 250")
+        .await
+        .unwrap();
+    let again = cache.get(&by_view.id, &data).unwrap();
+    assert!(again.catalog_version > by_view.catalog_version);
+    let r = execute_prepared(&ports, &again, &[ScalarValue::Int(0)], &Settings::default())
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&r.to_text())[2], "3");
+    ddl("ALTER TABLE sales RENAME COLUMN amount TO total")
+        .await
+        .unwrap();
+    let err = cache.get(&stmt.id, &data).unwrap_err();
+    assert_eq!(err.status_code(), 422, "{}", err);
+
+    // Placeholders need the prepare/execute path.
+    let mut req = parse_sql("SELECT COUNT(*) FROM sales WHERE id > $1;").unwrap();
+    resolve_tables(&mut req, &data).unwrap();
+    let err = run_query(&ports, req).await.unwrap_err();
+    assert!(err.message().contains("unbound parameters"), "{}", err);
+}
+
+#[tokio::test]
+async fn quoted_identifiers_reach_workers() {
+    let root = tmp_dir("quoted");
+    let ssf = "order id: int64 key\nship-region: string\n";
+    let csv = "order id,ship-region\n1,EU\n2,it's\n3,EU\n";
+    let orders = build_table(&root, "orders", ssf, csv, 2);
+    let ports = spawn_workers(&orders, 2).await;
+
+    let out = query(
+        &ports,
+        &orders,
+        "SELECT \"ship-region\", COUNT(*) AS n FROM orders -- per region
+         WHERE \"ship-region\" IN ('EU', 'it''s') /* both */ GROUP BY \"ship-region\"
+         ORDER BY \"ship-region\"",
+    )
+    .await;
+    let lines = result_lines(&out);
+    assert_eq!(lines[0], "ship-region|n");
+    assert_eq!(lines[2..], ["EU|2", "it's|1"]);
+}
+
+#[tokio::test]
+async fn scripts_apply_session_settings() {
+    let root = tmp_dir("script");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let mut ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+
+    let mut settings = Settings::default();
+    let results = run_script(
+        &ports,
+        &data,
+        "SET format = json; SELECT COUNT(*) FROM sales; SHOW ALL",
+        &mut settings,
+    )
+    .await
+    .expect("script");
+    assert_eq!(results.len(), 3);
+    assert_eq!(settings.format, OutputFormat::Json);
+    assert_eq!(result_lines(&results[1].to_text())[2], "4");
+    assert_eq!(
+        result_lines(&results[2].to_text())[2..],
+        ["format|json", "timeout|0", "allow_partial_results|false"]
+    );
+
+    let err = run_script(&ports, &data, "SET colour = 1", &mut settings)
+        .await
+        .unwrap_err();
+    assert!(err.message().contains("Unknown setting"), "{}", err);
+    // Settings changed before a failing statement are kept.
+    let err = run_script(
+        &ports,
+        &data,
+        "SET timeout = 250; SET timeout = 'soon'",
+        &mut settings,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.message().contains("Invalid value 'soon'"), "{}", err);
+    assert_eq!(settings.timeout_ms, 250);
+    // The statements before the failing one keep their results.
+    let statements =
+        parse_script("SET timeout = 300; SELECT COUNT(*) FROM sales; SELECT bogus FROM sales")
+            .unwrap();
+    let mut results = Vec::new();
+    let err = run_statements(&ports, &data, statements, &mut settings, &mut results)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 422);
+    assert_eq!(results.len(), 2);
+    assert_eq!(result_lines(&results[1].to_text())[2], "4");
+    assert_eq!(settings.timeout_ms, 300);
+
+    // Segment 1's worker is gone.
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    ports[1] = dead.local_addr().unwrap().port();
+    drop(dead);
+    let sql = "SELECT COUNT(*) FROM sales";
+    let err = run_script(&ports, &data, sql, &mut settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 503);
+    let results = run_script(
+        &ports,
+        &data,
+        &format!("SET allow_partial_results = true; {}", sql),
+        &mut settings,
+    )
+    .await
+    .expect("partial result");
+    let out = results[1].to_text();
+    assert_eq!(result_lines(&out)[2], "2");
+    assert!(
+        out.contains("Partial result: 1 segments unavailable"),
+        "{}",
+        out
+    );
+
+    // A worker that accepts but never answers.
+    let _hung = std::net::TcpListener::bind(("127.0.0.1", ports[1])).unwrap();
+    let err = run_script(&ports, &data, sql, &mut settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 504, "{}", err);
+}
+
+#[tokio::test]
+async fn create_and_drop_tables() {
+    let root = tmp_dir("ddl");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+
+    let results = run_script(
+        &ports,
+        &data,
+        "CREATE TABLE events (id int64 KEY, kind string NULL);\
+         SELECT id, kind FROM events",
+        &mut settings,
+    )
+    .await
+    .expect("create");
+    assert_eq!(results[0].to_text(), "CREATE TABLE\n");
+    let out = results[1].to_text();
+    assert!(out.starts_with("empty result"), "{}", out);
+    let events = root.join("events");
+    assert_eq!(
+        fs::read_to_string(events.join("_schema.ssf")).unwrap(),
+        "id: int64 key\nkind: string nullable\n"
+    );
+    assert!(events.join("seg-000001").join("kind.bin").is_file());
+
+    let err = run_script(
+        &ports,
+        &data,
+        "CREATE TABLE events (id int64 KEY)",
+        &mut settings,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status_code(), 422, "{}", err);
+    let err = run_script(
+        &ports,
+        &data,
+        "CREATE TABLE t2 (a int64, b int64)",
+        &mut settings,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.message().contains("exactly one 'key'"), "{}", err);
+    assert!(!root.join("t2").exists());
+    for column in ["../../x", "..", ".x", "a/b", "a\\b"] {
+        let sql = format!("CREATE TABLE t3 (id int64 KEY, \"{}\" string)", column);
+        let err = run_script(&ports, &data, &sql, &mut settings)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), 422, "{}: {}", sql, err);
+    }
+    assert!(!root.join("t3").exists() && !root.parent().unwrap().join("x.bin").exists());
+
+    run_script(&ports, &data, "DROP TABLE events", &mut settings)
+        .await
+        .expect("drop");
+    assert!(!events.exists());
+    let err = run_script(&ports, &data, "DROP TABLE events", &mut settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+    run_script(&ports, &data, "DROP TABLE IF EXISTS events", &mut settings)
+        .await
+        .expect("drop if exists");
+    run_script(&ports, &data, "DROP TABLE sales", &mut settings)
+        .await
+        .expect("drop sales");
+    let err = run_script(&ports, &data, "SELECT COUNT(*) FROM sales", &mut settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+}
+
+#[tokio::test]
+async fn inserts_append_delta_segments() {
+    let root = tmp_dir("insert");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_colocated(&root, "vip", VIP_SSF, VIP_CSV, &sales);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    assert_eq!(
+        run("INSERT INTO sales VALUES (7, 'US', 70, false)")
+            .await
+            .unwrap(),
+        "INSERT 1\n"
+    );
+    assert_eq!(
+        run("INSERT INTO sales (amount, id, region, active) \
+             VALUES (5, 5, 'EU', true), (10.5, 0, 'US', false)")
+        .await
+        .unwrap(),
+        "INSERT 2\n"
+    );
+    let out = run("SELECT COUNT(*), SUM(amount) FROM sales")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "7|735.500", "{}", out);
+    // Keys go to the base segment whose range holds them.
+    let meta = |n: u32| fs::read_to_string(sales.join(format!("seg-{:06}/_segment.txt", n)));
+    assert_eq!(meta(2).unwrap(), "base=1\n");
+    assert_eq!(meta(3).unwrap(), "base=0\n");
+    assert_eq!(meta(4).unwrap(), "base=1\n");
+    assert!(meta(0).is_err());
+    assert_eq!(count_segments(&sales).unwrap(), 2);
+
+    // Segment 1 is 3,4 + 7 + 5; the merge join needs it back in key order.
+    run("INSERT INTO vip VALUES (4, 'EU', 1), (5, 'EU', 1), (7, 'US', 2)")
+        .await
+        .unwrap();
+    let out = run("SELECT COUNT(*) FROM sales JOIN vip ON vip.id = sales.id")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "6", "{}", out);
+
+    run("CREATE TABLE events (id int64 KEY, note string NULL)")
+        .await
+        .unwrap();
+    run("INSERT INTO events (id) VALUES (1), (2)")
+        .await
+        .unwrap();
+    let out = run("INSERT INTO events SELECT id, region FROM sales WHERE id >= 5")
+        .await
+        .unwrap();
+    assert_eq!(out, "INSERT 2\n");
+    let out = run("SELECT id, note FROM events ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["1|NULL", "2|NULL", "5|EU", "7|US"],
+        "{}",
+        out
+    );
+
+    let err = run("INSERT INTO sales (id) VALUES (9)").await.unwrap_err();
+    assert!(err.message().contains("'region' is NOT NULL"), "{}", err);
+    let err = run("INSERT INTO sales VALUES (9, 'EU', 'lots', true)")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 422, "{}", err);
+    assert!(err.message().contains("Cannot store 'lots'"), "{}", err);
+    let err = run("INSERT INTO missing VALUES (1)").await.unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+}
+
+#[tokio::test]
+async fn deletes_write_deletion_vectors() {
+    let root = tmp_dir("delete");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_colocated(&root, "vip", VIP_SSF, VIP_CSV, &sales);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    run("INSERT INTO sales VALUES (5, 'EU', 5, true)")
+        .await
+        .unwrap();
+    assert_eq!(
+        run("DELETE FROM sales WHERE region = 'EU'").await.unwrap(),
+        "DELETE 3\n"
+    );
+    for seg in ["seg-000000", "seg-000001", "seg-000002"] {
+        assert!(sales.join(seg).join("_deleted.bin").is_file(), "{}", seg);
+    }
+    // Deleted rows are neither returned nor counted as scanned.
+    let out = run("SELECT id, region FROM sales ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2..], ["2|US", "4|APAC"], "{}", out);
+    assert!(out.contains("Rows scanned:       2"), "{}", out);
+    assert_eq!(
+        run("DELETE FROM sales WHERE region = 'EU'").await.unwrap(),
+        "DELETE 0\n"
+    );
+
+    assert_eq!(
+        run("DELETE FROM sales WHERE id IN (SELECT id FROM vip WHERE tier = 1)")
+            .await
+            .unwrap(),
+        "DELETE 1\n"
+    );
+    run("INSERT INTO sales VALUES (1, 'EU', 1, true)")
+        .await
+        .unwrap();
+    let out = run("SELECT COUNT(*) FROM sales JOIN vip ON vip.id = sales.id")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "1", "{}", out);
+
+    assert_eq!(run("DELETE FROM sales").await.unwrap(), "DELETE 2\n");
+    let out = run("SELECT id FROM sales").await.unwrap();
+    assert!(out.starts_with("empty result"), "{}", out);
+    let err = run("DELETE FROM missing").await.unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+}
+
+#[tokio::test]
+async fn mutations_run_once_without_timeout() {
+    let root = tmp_dir("mutate-once");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let mut ports = spawn_workers(&sales, 1).await;
+    // Nothing listens on the port of segment 1.
+    ports.push(
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port(),
+    );
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    let err = run("DELETE FROM sales WHERE region = 'EU'")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 503, "{}", err);
+    assert!(!err.message().contains("after retry"), "{}", err);
+    assert!(
+        err.message().ends_with("segments already changed: 0"),
+        "{}",
+        err
+    );
+    assert!(sales.join("seg-000000/_deleted.bin").is_file());
+    assert!(!sales.join("seg-000001/_deleted.bin").exists());
+    let err = run("DELETE FROM sales WHERE id > 2").await.unwrap_err();
+    assert!(err.message().ends_with("no segment was changed"), "{}", err);
+    let err = run("UPDATE sales SET amount = 1 WHERE region = 'US'")
+        .await
+        .unwrap_err();
+    assert!(!err.message().contains("after retry"), "{}", err);
+    assert!(
+        err.message().ends_with("segments already changed: 0"),
+        "{}",
+        err
+    );
+
+    // The session timeout is for queries; a mutation runs to the end.
+    ports.pop();
+    let mut settings = Settings::default();
+    let out = run_script(
+        &ports,
+        &data,
+        "SET timeout = 1; DELETE FROM sales WHERE id = 2",
+        &mut settings,
+    )
+    .await
+    .unwrap();
+    assert_eq!(out.last().unwrap().to_text(), "DELETE 1\n");
+}
+
+#[tokio::test]
+async fn joins_require_shared_segment_boundaries() {
+    let root = tmp_dir("colocate");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_colocated(&root, "vip", VIP_SSF, VIP_CSV, &sales);
+    // Same segment count, but split as [2, 3] | [4, 5] against [1, 2] | [3, 4].
+    build_table(
+        &root,
+        "late",
+        VIP_SSF,
+        "id,region,tier\n2,US,1\n3,EU,1\n4,EU,2\n5,US,2\n",
+        2,
+    );
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    let out = run("SELECT COUNT(*) FROM sales JOIN vip ON vip.id = sales.id")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "3", "{}", out);
+    let err = run("SELECT COUNT(*) FROM sales JOIN late ON late.id = sales.id")
+        .await
+        .unwrap_err();
+    assert!(err.message().contains("not co-located"), "{}", err);
+
+    // Loading the reference again draws new boundaries.
+    let schema = parse_schema_file(SALES_SSF).unwrap();
+    load_table(
+        &sales,
+        &root.join("sales.csv"),
+        &"id".to_string(),
+        2,
+        &schema,
+    )
+    .unwrap();
+    let err = run("SELECT COUNT(*) FROM sales JOIN vip ON vip.id = sales.id")
+        .await
+        .unwrap_err();
+    assert!(err.message().contains("not co-located"), "{}", err);
+}
+
+#[tokio::test]
+async fn updates_rewrite_matching_segments() {
+    let root = tmp_dir("update");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    run("INSERT INTO sales VALUES (5, 'EU', 5, true); DELETE FROM sales WHERE id = 3")
+        .await
+        .unwrap();
+    assert_eq!(
+        run("UPDATE sales SET amount = 7, active = false WHERE id >= 4")
+            .await
+            .unwrap(),
+        "UPDATE 2\n"
+    );
+    // Only the segments holding matches get a new generation.
+    assert!(!sales.join("seg-000000").join("gen-000001").exists());
+    for seg in ["seg-000001", "seg-000002"] {
+        let meta = std::fs::read_to_string(sales.join(seg).join("_segment.txt")).unwrap();
+        assert!(meta.contains("generation=1"), "{}: {}", seg, meta);
+        assert!(
+            sales
+                .join(seg)
+                .join("gen-000001")
+                .join("amount.bin")
+                .is_file()
+        );
+    }
+    assert_eq!(
+        run("UPDATE sales SET amount = id WHERE region = 'US'")
+            .await
+            .unwrap(),
+        "UPDATE 1\n"
+    );
+    let out = run("SELECT id, amount, active FROM sales ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        [
+            "1|100.000|true",
+            "2|2.000|false",
+            "4|7.000|false",
+            "5|7.000|false"
+        ],
+        "{}",
+        out
+    );
+
+    // A second rewrite drops the generation before the previous one.
+    run("UPDATE sales SET active = true WHERE id = 4")
+        .await
+        .unwrap();
+    let seg = sales.join("seg-000001");
+    assert!(seg.join("gen-000002").is_dir());
+    assert!(seg.join("gen-000001").is_dir());
+    assert!(!seg.join("amount.bin").exists());
+    assert!(!seg.join("_deleted.bin").exists());
+    let out = run("SELECT COUNT(*) FROM sales WHERE active = true")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "2", "{}", out);
+    assert_eq!(
+        run("UPDATE sales SET amount = 1 WHERE id > 100")
+            .await
+            .unwrap(),
+        "UPDATE 0\n"
+    );
+    // `column op number`, per row; integers widen into a float column.
+    run("UPDATE sales SET amount = amount * 1.5 WHERE id <= 2")
+        .await
+        .unwrap();
+    run("UPDATE sales SET amount = id + 10 WHERE id = 5")
+        .await
+        .unwrap();
+    let out = run("SELECT id, amount FROM sales ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["1|150.000", "2|3.000", "4|7.000", "5|15.000"],
+        "{}",
+        out
+    );
+
+    for sql in [
+        "UPDATE sales SET id = 9",
+        "UPDATE sales SET region = NULL",
+        "UPDATE sales SET active = 1",
+        "UPDATE sales SET region = amount",
+        "UPDATE sales SET amount = 1, amount = 2",
+        "UPDATE sales SET missing = 1",
+        "UPDATE sales SET region = region * 2",
+        "UPDATE sales SET amount = amount + 'x'",
+        "UPDATE sales SET amount = amount / 0",
+        "UPDATE sales SET active = amount + 1",
+    ] {
+        let err = run(sql).await.unwrap_err();
+        assert_eq!(err.status_code(), 422, "{}: {}", sql, err);
+    }
+    // One operator with a literal on the right, nothing longer.
+    let err = run("UPDATE sales SET amount = amount * amount")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 400, "{}", err);
+    let err = run("UPDATE missing SET a = 1").await.unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+}
+
+#[tokio::test]
+async fn create_table_as_materializes_results() {
+    let root = tmp_dir("ctas");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    assert_eq!(
+        run(
+            "CREATE TABLE summary AS SELECT region, SUM(amount) AS total, COUNT(*) AS n \
+             FROM sales GROUP BY region"
+        )
+        .await
+        .unwrap(),
+        "SELECT 3\n"
+    );
+    let summary = root.join("summary");
+    assert_eq!(
+        fs::read_to_string(summary.join("_schema.ssf")).unwrap(),
+        "region: string key\ntotal: float64 nullable\nn: int64 nullable\n"
+    );
+    assert_eq!(count_segments(&summary).unwrap(), 2);
+    let out = run("SELECT region, total, n FROM summary WHERE n >= 1 ORDER BY region")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["APAC|300.000|1", "EU|150.000|2", "US|200.000|1"],
+        "{}",
+        out
+    );
+
+    // Columns of the source table keep their type.
+    run("CREATE TABLE active KEY (id) AS SELECT region, id FROM sales WHERE active = true")
+        .await
+        .unwrap();
+    let schema = fs::read_to_string(root.join("active").join("_schema.ssf")).unwrap();
+    assert!(schema.contains("id: int64 key"), "{}", schema);
+    let out = run("SELECT COUNT(*) FROM active WHERE id >= 3")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "2", "{}", out);
+
+    for (sql, status) in [
+        ("CREATE TABLE summary AS SELECT id FROM sales", 422),
+        (
+            "CREATE TABLE other KEY (missing) AS SELECT id FROM sales",
+            422,
+        ),
+        ("CREATE TABLE other AS EXPLAIN SELECT id FROM sales", 400),
+    ] {
+        let err = run(sql).await.unwrap_err();
+        assert_eq!(err.status_code(), status, "{}: {}", sql, err);
+    }
+    assert!(!root.join("other").exists());
+}
+
+#[tokio::test]
+async fn catalog_serves_many_tables() {
+    let root = tmp_dir("catalog");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    build_table(&root, "vip", VIP_SSF, VIP_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|mut r| r.pop().unwrap())
+    };
+
+    // Without `_catalog.txt`, the catalog is what the root holds.
+    let out = run("SHOW TABLES").await.unwrap();
+    assert_eq!(
+        result_lines(&out.to_text())[2..],
+        ["sales|table", "vip|table"]
+    );
+    let out = run("SELECT COUNT(*) FROM vip").await.unwrap();
+    assert_eq!(result_lines(&out.to_text())[2], "3");
+
+    run("CREATE TABLE events (id int64 KEY, kind string NULL DEFAULT 'x')")
+        .await
+        .unwrap();
+    run("CREATE VIEW eu AS SELECT id FROM sales WHERE region = 'EU'")
+        .await
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("_catalog.txt")).unwrap(),
+        "eu\nevents\nsales\nvip\nversion=2\n"
+    );
+    let out = run("SHOW TABLES").await.unwrap();
+    assert_eq!(
+        result_lines(&out.to_text())[2..],
+        ["eu|view", "events|table", "sales|table", "vip|table"]
+    );
+
+    let out = result_lines(&run("DESCRIBE events").await.unwrap().to_text());
+    assert_eq!(
+        out[0],
+        "column_name|data_type|is_nullable|is_key|column_default"
+    );
+    assert_eq!(
+        out[2..],
+        ["id|int64|NO|true|NULL", "kind|string|YES|false|x"]
+    );
+    let out = run(
+        "SELECT table_name, COUNT(*) FROM information_schema.columns \
+         WHERE is_key = true GROUP BY table_name ORDER BY table_name",
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        result_lines(&out.to_text())[2..],
+        ["events|1", "sales|1", "vip|1"]
+    );
+    let out = run(
+        "SELECT ordinal_position, column_name FROM information_schema.columns \
+         WHERE table_name = 'sales' ORDER BY ordinal_position",
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        result_lines(&out.to_text())[2..],
+        ["1|id", "2|region", "3|amount", "4|active"]
+    );
+
+    // A directory that is not in the catalog is not a table.
+    build_table(&root, "stray", VIP_SSF, VIP_CSV, 2);
+    for sql in [
+        "SELECT COUNT(*) FROM stray",
+        "SELECT COUNT(*) FROM missing",
+        "DESCRIBE eu",
+        "DELETE FROM stray",
+    ] {
+        let err = run(sql).await.unwrap_err();
+        assert_eq!(err.status_code(), 404, "{}: {}", sql, err);
+    }
+    let err = run("CREATE TABLE information_schema.tables (id int64 KEY)")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), 422, "{}", err);
+
+    run("DROP VIEW eu").await.unwrap();
+    run("DROP TABLE events").await.unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("_catalog.txt")).unwrap(),
+        "sales\nvip\nversion=4\n"
+    );
+}
+
+#[tokio::test]
+async fn views_and_materialized_views() {
+    let root = tmp_dir("views");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|mut r| r.pop().unwrap())
+    };
+
+    assert_eq!(
+        run("CREATE VIEW eu AS SELECT id, amount FROM sales WHERE region = 'EU'")
+            .await
+            .unwrap()
+            .to_text(),
+        "CREATE VIEW\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("eu").join("_view.sql")).unwrap(),
+        "SELECT id, amount FROM sales WHERE region = 'EU'\n"
+    );
+    run("CREATE VIEW big_eu AS SELECT id FROM eu WHERE amount > 60")
+        .await
+        .unwrap();
+    let out = run("SELECT COUNT(*), SUM(amount) FROM eu").await.unwrap();
+    assert_eq!(result_lines(&out.to_text())[2], "2|150.000");
+    let out = run("SELECT id FROM big_eu").await.unwrap();
+    assert_eq!(result_lines(&out.to_text())[2..], ["1"]);
+
+    run("CREATE MATERIALIZED VIEW by_region AS \
+         SELECT region, SUM(amount) AS total, COUNT(*) AS n FROM sales GROUP BY region")
+    .await
+    .unwrap();
+    let out = run("SELECT region, total, n FROM by_region ORDER BY region")
+        .await
+        .unwrap()
+        .to_text();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["APAC|300.000|1", "EU|150.000|2", "US|200.000|1"],
+        "{}",
+        out
+    );
+    // Coarser groups and AVG come from the stored sums and counts.
+    let total = "SELECT SUM(amount), AVG(amount) FROM sales";
+    let check = |result: QueryResult, expected: &str, scanned: u64| {
+        assert_eq!(result_lines(&result.to_text())[2], expected);
+        assert_eq!(result.stats.rows_scanned, scanned);
+    };
+    check(run(total).await.unwrap(), "650.000|162.500", 0);
+    let out = run("SELECT SUM(amount) FROM sales WHERE active = true")
+        .await
+        .unwrap();
+    assert_eq!(out.stats.rows_scanned, 4);
+
+    // Segments written since the refresh are scanned; the rest are not.
+    run("INSERT INTO sales VALUES (5, 'EU', 10, true)")
+        .await
+        .unwrap();
+    check(run(total).await.unwrap(), "660.000|132.000", 1);
+    let refresh = "REFRESH MATERIALIZED VIEW by_region";
+    assert_eq!(run(refresh).await.unwrap().to_text(), "REFRESH 1\n");
+    assert_eq!(run(refresh).await.unwrap().to_text(), "REFRESH 0\n");
+    check(run(total).await.unwrap(), "660.000|132.000", 0);
+    run("UPDATE sales SET amount = 1 WHERE id = 4")
+        .await
+        .unwrap();
+    run("DELETE FROM sales WHERE id = 1").await.unwrap();
+    assert_eq!(run(refresh).await.unwrap().to_text(), "REFRESH 2\n");
+    check(run(total).await.unwrap(), "261.000|65.250", 0);
+    let out = run("EXPLAIN ANALYZE SELECT SUM(amount) FROM sales")
+        .await
+        .unwrap();
+    assert!(
+        out.to_text().contains("From materialized view by_region"),
+        "{}",
+        out.to_text()
+    );
+    // Over 64 values the query's IN list is hashed before it is matched.
+    let ids = |ids: &mut dyn Iterator<Item = u32>| {
+        ids.map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+    };
+    run(&format!(
+        "CREATE MATERIALIZED VIEW some_ids AS SELECT region, COUNT(*) AS n FROM sales \
+         WHERE id IN ({}) GROUP BY region",
+        ids(&mut (1..=65))
+    ))
+    .await
+    .unwrap();
+    let out = run(&format!(
+        "SELECT COUNT(*) FROM sales WHERE id IN ({})",
+        ids(&mut (1..=65).rev())
+    ))
+    .await
+    .unwrap();
+    check(out, "4", 0);
+    run("DROP MATERIALIZED VIEW some_ids").await.unwrap();
+
+    for (sql, status) in [
+        ("CREATE VIEW eu AS SELECT id FROM sales", 422),
+        ("CREATE VIEW v AS SELECT missing FROM sales", 422),
+        ("CREATE MATERIALIZED VIEW m AS SELECT id FROM sales", 422),
+        ("CREATE MATERIALIZED VIEW m AS SELECT COUNT(*) FROM eu", 422),
+        ("REFRESH MATERIALIZED VIEW eu", 404),
+        ("DROP VIEW missing", 404),
+    ] {
+        let err = run(sql).await.unwrap_err();
+        assert_eq!(err.status_code(), status, "{}: {}", sql, err);
+    }
+    run("DROP VIEW IF EXISTS missing").await.unwrap();
+    run("DROP MATERIALIZED VIEW by_region").await.unwrap();
+    run("DROP VIEW big_eu").await.unwrap();
+    assert!(!root.join("by_region").exists() && !root.join("big_eu").exists());
+    let out = run("SELECT SUM(amount) FROM sales").await.unwrap();
+    assert_eq!(out.stats.rows_scanned, 4);
+}
+
+#[tokio::test]
+async fn alter_table_evolves_schema() {
+    let root = tmp_dir("alter");
+    let sales = build_table(&root, "sales", SALES_SSF, SALES_CSV, 2);
+    let ports = spawn_workers(&sales, 2).await;
+    let data = root.to_string_lossy().to_string();
+    let mut settings = Settings::default();
+    let mut run = async |sql: &str| {
+        run_script(&ports, &data, sql, &mut settings)
+            .await
+            .map(|r| r.last().unwrap().to_text())
+    };
+
+    assert_eq!(
+        run("ALTER TABLE sales ADD COLUMN note string DEFAULT 'none'")
+            .await
+            .unwrap(),
+        "ALTER TABLE\n"
+    );
+    run("ALTER TABLE sales ADD rank int").await.unwrap();
+    // Existing segments have no file for the new columns.
+    assert!(!sales.join("seg-000000").join("note.bin").exists());
+    run("INSERT INTO sales (id, region, amount, active) VALUES (5, 'EU', 5, true)")
+        .await
+        .unwrap();
+    run("INSERT INTO sales VALUES (6, 'US', 6, false, 'six', 6)")
+        .await
+        .unwrap();
+    let out = run("SELECT id, note, rank FROM sales WHERE id >= 4 ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["4|none|NULL", "5|none|NULL", "6|six|6"],
+        "{}",
+        out
+    );
+    // Zone maps of segments without the file see only the default.
+    let out = run("SELECT COUNT(*) FROM sales WHERE note = 'six'")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "1", "{}", out);
+
+    run("ALTER TABLE sales RENAME COLUMN amount TO total")
+        .await
+        .unwrap();
+    let out = run("SELECT SUM(total) FROM sales").await.unwrap();
+    assert_eq!(result_lines(&out)[2], "661.000", "{}", out);
+    let err = run("SELECT amount FROM sales").await.unwrap_err();
+    assert_eq!(err.status_code(), 422, "{}", err);
+
+    // The dropped column's files stay, so the new one gets other files.
+    run("ALTER TABLE sales DROP COLUMN region").await.unwrap();
+    run("ALTER TABLE sales ADD COLUMN region string NOT NULL DEFAULT 'x'")
+        .await
+        .unwrap();
+    let out = run("SELECT COUNT(*) FROM sales WHERE region = 'x'")
+        .await
+        .unwrap();
+    assert_eq!(result_lines(&out)[2], "6", "{}", out);
+
+    // A rewrite writes every column of the current schema.
+    run("UPDATE sales SET rank = 2 WHERE id = 2").await.unwrap();
+    let generation = sales.join("seg-000000").join("gen-000001");
+    for file in ["amount.bin", "note.bin", "rank.bin", "region~1.bin"] {
+        assert!(generation.join(file).is_file(), "{}", file);
+    }
+    let out = run("SELECT id, total, region, rank FROM sales WHERE id <= 2 ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        result_lines(&out)[2..],
+        ["1|100.000|x|NULL", "2|200.000|x|2"],
+        "{}",
+        out
+    );
+
+    let schema = fs::read_to_string(sales.join("_schema.ssf")).unwrap();
+    assert!(
+        schema.contains("total: float64 file=\"amount\""),
+        "{}",
+        schema
+    );
+    let table_txt = fs::read_to_string(sales.join("_table.txt")).unwrap();
+    assert!(table_txt.contains("schema_version=6"), "{}", table_txt);
+
+    for sql in [
+        "ALTER TABLE sales ADD COLUMN note string",
+        "ALTER TABLE sales ADD COLUMN flag bool NOT NULL",
+        "ALTER TABLE sales ADD COLUMN n int DEFAULT 'abc'",
+        "ALTER TABLE sales DROP COLUMN id",
+        "ALTER TABLE sales DROP COLUMN missing",
+        "ALTER TABLE sales RENAME COLUMN note TO rank",
+        // Column names become file names in the segments.
+        "ALTER TABLE sales ADD COLUMN \"../../x\" string",
+        "ALTER TABLE sales ADD COLUMN \"..\" int",
+        "ALTER TABLE sales ADD COLUMN \"a\\b\" int",
+        "ALTER TABLE sales RENAME COLUMN note TO \"../x\"",
+        "ALTER TABLE sales RENAME COLUMN note TO \".note\"",
+    ] {
+        let err = run(sql).await.unwrap_err();
+        assert_eq!(err.status_code(), 422, "{}: {}", sql, err);
+    }
+    let err = run("ALTER TABLE missing DROP COLUMN a").await.unwrap_err();
+    assert_eq!(err.status_code(), 404, "{}", err);
+}
+
+const SALES_SSF: &str = r#"id: int64 key
+region: string
+amount: float64
+active: bool
+"#;
+
+const SALES_CSV: &str = r#"id,region,amount,active
+1,EU,100,true
+2,US,200,false
+3,EU,50,true
+4,APAC,300,true
+"#;
+
+const VIP_SSF: &str = r#"id: int64 key
+region: string
+tier: int64
+"#;
+
+const VIP_CSV: &str = r#"id,region,tier
+1,EU,2
+2,US,1
+3,APAC,3
+"#;
@@ -0,0 +1,146 @@
+use minidist::coordinator::coordinator_route::{resolve_tables, run_query};
+use minidist::minisql::minisql_bind::bind;
+use minidist::minisql::minisql_error::QueryError;
+use minidist::minisql::minisql_parse::parse_sql;
+use minidist::rpc::{QueryRequest, ScalarValue};
+use minidist::storage::storage_init::init_table;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn events_table(prefix: &str) -> PathBuf {
+    let mut root = std::env::temp_dir();
+    root.push(format!("minidist-bind-{}", prefix));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let schema = root.join("events.ssf");
+    fs::write(
+        &schema,
+        "id: int64 key\nkind: string\nday: date\nat: timestamp(ms)\namount: float64\n",
+    )
+    .unwrap();
+    let dir = root.join("events");
+    fs::create_dir_all(&dir).unwrap();
+    init_table(&dir, &schema).unwrap();
+    dir
+}
+
+fn bound(table: &Path, sql: &str) -> Result<QueryRequest, String> {
+    let mut req = parse_sql(sql).map_err(|e| e.to_string())?;
+    resolve_tables(&mut req, &table.parent().unwrap().to_string_lossy()).unwrap();
+    bind(&mut req).map_err(|e| e.to_string())?;
+    Ok(req)
+}
+
+#[test]
+fn rejects_unknown_columns() {
+    let t = events_table("unknown");
+    let err = bound(&t, "SELECT COUNT(*) FROM events WHERE knd = \"a\";").unwrap_err();
+    assert!(err.contains("Unknown column 'knd' in WHERE"), "{}", err);
+    assert!(err.contains("id, kind, day, at, amount"), "{}", err);
+
+    let err = bound(&t, "SELECT SUM(amt) FROM events;").unwrap_err();
+    assert!(err.contains("Unknown column 'amt' in SELECT"), "{}", err);
+
+    let err = bound(
+        &t,
+        "WITH k AS (SELECT kind, COUNT(*) AS n FROM events GROUP BY kind) \
+         SELECT kind FROM k WHERE total > 1;",
+    )
+    .unwrap_err();
+    assert!(err.contains("Unknown column 'total'"), "{}", err);
+}
+
+#[test]
+fn type_checks_literals() {
+    let t = events_table("types");
+    let err = bound(&t, "SELECT COUNT(*) FROM events WHERE kind = 5;").unwrap_err();
+    assert_eq!(err, "Cannot compare column 'kind' (string) with 5");
+
+    let err = bound(&t, "SELECT MAX(kind) FROM events;").unwrap_err();
+    assert_eq!(err, "MAX needs a numeric column, 'kind' is string");
+    let err = bound(&t, "SELECT SUM(day) FROM events;").unwrap_err();
+    assert_eq!(err, "SUM needs a numeric column, 'day' is date");
+    assert!(bound(&t, "SELECT MIN(at) FROM events;").is_err());
+    assert!(bound(&t, "SELECT AVG(id), MAX(amount) FROM events;").is_ok());
+
+    let err = bound(
+        &t,
+        "SELECT COUNT(*) FROM events WHERE day = \"2024-13-01\";",
+    )
+    .unwrap_err();
+    assert!(err.starts_with("Invalid date '2024-13-01'"), "{}", err);
+
+    let req = bound(
+        &t,
+        "SELECT COUNT(*) FROM events WHERE day BETWEEN \"1970-01-02\" AND \"1970-02-01\" \
+         AND at >= \"1970-01-01 00:00:01\";",
+    )
+    .unwrap();
+    assert_eq!(req.filters[0].value, ScalarValue::Int(1));
+    assert_eq!(req.filters[0].value_hi, Some(ScalarValue::Int(31)));
+    assert_eq!(req.filters[1].value, ScalarValue::Int(1000));
+}
+
+#[test]
+fn rejects_ungrouped_columns() {
+    let t = events_table("grouped");
+    let err = bound(&t, "SELECT kind, day, COUNT(*) FROM events GROUP BY kind;").unwrap_err();
+    assert_eq!(
+        err,
+        "Column 'day' must appear in GROUP BY or be used in an aggregate"
+    );
+    assert!(bound(&t, "SELECT kind, COUNT(*) FROM events GROUP BY kind, day;").is_ok());
+}
+
+#[tokio::test]
+async fn errors_before_contacting_workers() {
+    let t = events_table("no_workers");
+    let mut req = parse_sql("SELECT SUM(amount) FROM events WHERE kid = 1;").unwrap();
+    resolve_tables(&mut req, &t.parent().unwrap().to_string_lossy()).unwrap();
+    // Port 1 has no worker; binding must fail first.
+    let err = run_query(&[1], req).await.unwrap_err();
+    assert!(matches!(err, QueryError::Bind { .. }), "{:?}", err);
+    assert_eq!(err.status_code(), 422);
+    assert!(err.message().contains("Unknown column 'kid'"), "{}", err);
+
+    let mut req = parse_sql("SELECT SUM(amount) FROM events;").unwrap();
+    resolve_tables(&mut req, &t.parent().unwrap().to_string_lossy()).unwrap();
+    let err = run_query(&[1], req).await.unwrap_err();
+    assert!(
+        matches!(err, QueryError::WorkerUnavailable { worker_port: 1, .. }),
+        "{:?}",
+        err
+    );
+    assert_eq!(err.status_code(), 503);
+}
+
+#[test]
+fn binds_order_by_against_the_result() {
+    let t = events_table("order_by");
+    let err = bound(
+        &t,
+        "SELECT kind, COUNT(*) FROM events GROUP BY kind ORDER BY kid;",
+    )
+    .unwrap_err();
+    assert!(
+        err.starts_with("Unknown column 'kid' in ORDER BY"),
+        "{}",
+        err
+    );
+    // Sorting happens after projection, so table columns left out are gone.
+    let err = bound(&t, "SELECT kind FROM events ORDER BY amount;").unwrap_err();
+    assert!(
+        err.starts_with("Unknown column 'amount' in ORDER BY"),
+        "{}",
+        err
+    );
+
+    for sql in [
+        "SELECT kind, COUNT(*) FROM events GROUP BY kind ORDER BY kind;",
+        "SELECT kind, COUNT(*) FROM events GROUP BY kind ORDER BY COUNT(*) DESC;",
+        "SELECT kind, SUM(amount) AS total FROM events GROUP BY kind ORDER BY total;",
+        "SELECT * FROM events ORDER BY count_star;",
+    ] {
+        assert!(bound(&t, sql).is_ok(), "{}: {:?}", sql, bound(&t, sql));
+    }
+}
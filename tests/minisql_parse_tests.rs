@@ -1,5 +1,10 @@
-use minidist_rs::minisql::minisql_parse::parse_sql;
-use minidist_rs::rpc::{AggregateFn, Predicate};
+use minidist::minisql::minisql_error::QueryError;
+use minidist::minisql::minisql_parse::{InsertSource, Statement, parse_script, parse_sql};
+use minidist::rpc::{
+    AggregateFn, ArithOp, AssignedValue, Explain, InList, Mutation, ParamSlot, Predicate,
+    ScalarValue, WindowFn,
+};
+use minidist::storage::storage_alter::SchemaChange;
 
 #[test]
 fn parses_example_query() {
@@ -17,3 +22,495 @@ fn parses_example_query() {
     assert_eq!(filt.column, "amount");
     assert!(matches!(filt.pred, Predicate::Gt));
 }
+
+#[test]
+fn parses_join_on_either_side() {
+    let sql = "SELECT o.region, SUM(i.qty) FROM o JOIN i ON i.order_id = o.id GROUP BY o.region;";
+    let req = parse_sql(sql).expect("should parse");
+    assert_eq!(req.table, "o");
+    assert_eq!(req.projections, vec!["o.region"]);
+    let join = req.join.expect("join");
+    assert_eq!(join.left_name, "o");
+    assert_eq!(join.right_name, "i");
+    assert_eq!(join.left_key, "id");
+    assert_eq!(join.right_key, "order_id");
+}
+
+#[test]
+fn parses_in_lists_and_subqueries() {
+    let sql = "SELECT COUNT(*) FROM sales WHERE id IN (1, 2) AND amount <= 10 \
+               AND region IN (SELECT region FROM vip) AND amount > (SELECT AVG(amount) FROM sales);";
+    let req = parse_sql(sql).expect("should parse");
+    assert_eq!(req.filters.len(), 4);
+    assert!(matches!(req.filters[0].pred, Predicate::In));
+    assert!(matches!(req.filters[0].list, Some(InList::Values(ref v)) if v.len() == 2));
+    assert!(matches!(req.filters[1].pred, Predicate::Le));
+    let inner = req.filters[2].subquery.as_ref().expect("IN subquery");
+    assert_eq!(inner.table, "vip");
+    assert_eq!(inner.projections, vec!["region"]);
+    assert!(matches!(req.filters[3].pred, Predicate::Gt));
+    let scalar = req.filters[3].subquery.as_ref().expect("scalar subquery");
+    assert_eq!(scalar.aggregates[0].output_name, "AVG(amount)");
+}
+
+#[test]
+fn parses_ctes_with_order_and_limit() {
+    let sql = "WITH daily AS (SELECT created, SUM(amount) AS total FROM sales GROUP BY created) \
+               SELECT AVG(total) FROM daily ORDER BY AVG(total) DESC LIMIT 5;";
+    let req = parse_sql(sql).expect("parse");
+    assert_eq!(req.table, "daily");
+    assert_eq!(req.ctes.len(), 1);
+    assert_eq!(req.ctes[0].name, "daily");
+    assert_eq!(req.ctes[0].query.table, "sales");
+    assert_eq!(
+        req.ctes[0].query.aggregates[0].alias.as_deref(),
+        Some("total")
+    );
+    assert_eq!(req.order_by[0].column, "AVG(total)");
+    assert!(req.order_by[0].descending);
+    assert_eq!(req.limit, Some(5));
+}
+
+#[test]
+fn parses_window_functions() {
+    let sql = "SELECT region, LEAD(amount, 2) OVER (PARTITION BY region ORDER BY created DESC), \
+               SUM(amount) OVER (ORDER BY created) AS running FROM sales;";
+    let req = parse_sql(sql).expect("parse");
+    assert_eq!(req.windows.len(), 2);
+    assert_eq!(req.windows[0].func, WindowFn::Lead);
+    assert_eq!(req.windows[0].offset, 2);
+    assert_eq!(req.windows[0].partition_by, vec!["region"]);
+    assert!(req.windows[0].order_by[0].descending);
+    assert_eq!(req.windows[1].func, WindowFn::Aggregate(AggregateFn::Sum));
+    assert_eq!(req.windows[1].alias.as_deref(), Some("running"));
+
+    let err = parse_sql("SELECT COUNT(*), RANK() OVER (ORDER BY id) FROM sales;").unwrap_err();
+    assert!(err.message().contains("cannot be combined"));
+    // Valid syntax with an invalid meaning binds, not parses, wrong.
+    assert!(matches!(err, QueryError::Bind { .. }), "{:?}", err);
+    assert_eq!(err.status_code(), 422);
+}
+
+#[test]
+fn expands_rollup_and_cube() {
+    let req = parse_sql("SELECT COUNT(*) FROM t GROUP BY ROLLUP(a, b);").expect("parse");
+    assert_eq!(req.group_by, vec!["a", "b"]);
+    assert_eq!(
+        req.grouping_sets,
+        vec![vec!["a", "b"], vec!["a"], Vec::<&str>::new()]
+    );
+
+    let req = parse_sql("SELECT GROUPING(a, b), COUNT(*) FROM t GROUP BY CUBE(a, b);").unwrap();
+    assert_eq!(
+        req.grouping_sets,
+        vec![vec!["a", "b"], vec!["a"], vec!["b"], Vec::<&str>::new()]
+    );
+    assert_eq!(req.grouping[0].columns, vec!["a", "b"]);
+
+    let err = parse_sql("SELECT GROUPING(c), COUNT(*) FROM t GROUP BY a;").unwrap_err();
+    assert!(err.message().contains("not a GROUP BY column"));
+    assert_eq!(err.status_code(), 422);
+}
+
+#[test]
+fn syntax_errors_carry_positions() {
+    let err = parse_sql("SELECT region\nFROM sales\nWHERE amount >> 5;").unwrap_err();
+    let QueryError::Parse { start, .. } = &err else {
+        panic!("expected parse error, got {:?}", err);
+    };
+    assert_eq!(start.map(|p| (p.line, p.column)), Some((3, 15)));
+    assert_eq!(err.status_code(), 400);
+
+    let err = parse_sql("SELECT COUNT(*) FROM t LIMIT 99999999999999999999;").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invalid LIMIT: 99999999999999999999 (line 1, column 30)"
+    );
+}
+
+#[test]
+fn parses_explain() {
+    let req = parse_sql("SELECT COUNT(*) FROM t;").unwrap();
+    assert_eq!(req.explain, None);
+
+    let req = parse_sql("explain SELECT COUNT(*) FROM t;").unwrap();
+    assert_eq!(req.explain, Some(Explain::Plan));
+    assert_eq!(req.query, "SELECT COUNT(*) FROM t");
+
+    let req = parse_sql("EXPLAIN ANALYZE WITH c AS (SELECT a FROM t) SELECT a FROM c;").unwrap();
+    assert_eq!(req.explain, Some(Explain::Analyze));
+    assert_eq!(req.ctes.len(), 1);
+}
+
+#[test]
+fn numbers_parameters() {
+    let req = parse_sql("SELECT a FROM t WHERE b BETWEEN $2 AND $3 AND c IN (1, $1);").unwrap();
+    let slots = |i: usize| {
+        req.filters[i]
+            .params
+            .iter()
+            .map(|p| (p.slot, p.index))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        slots(0),
+        vec![(ParamSlot::Value, 1), (ParamSlot::ValueHi, 2)]
+    );
+    assert_eq!(slots(1), vec![(ParamSlot::ListItem(1), 0)]);
+
+    // `?` are numbered left to right, subqueries included.
+    let req = parse_sql("SELECT a FROM t WHERE b > ? AND c < (SELECT MAX(c) FROM t WHERE d = ?);")
+        .unwrap();
+    assert_eq!(req.filters[0].params[0].index, 0);
+    let sub = req.filters[1].subquery.as_ref().unwrap();
+    assert_eq!(sub.filters[0].params[0].index, 1);
+
+    let err = parse_sql("SELECT a FROM t WHERE b > $1 AND c < ?;").unwrap_err();
+    assert!(err.message().contains("Cannot mix"), "{}", err);
+    assert!(parse_sql("SELECT a FROM t WHERE b > $0;").is_err());
+    let err = parse_sql("SELECT a FROM t WHERE b > $2;").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Parameter $2 is used but $1 is not (line 1, column 27)"
+    );
+    assert_eq!(err.status_code(), 400);
+}
+
+#[test]
+fn lexical_forms() {
+    let sql = "-- dashboard query\n\
+               SELECT \"order id\", SUM(\"unit-price\") AS \"Total \"\"net\"\"\" /* gross? */\n\
+               FROM \"my table\" WHERE note = 'it''s' AND tag IN ('a\\n', \"b\\n\\u0041\")\n\
+               AND t.\"x y\" > 1";
+    let req = parse_sql(sql).expect("should parse");
+    assert_eq!(req.table, "my table");
+    assert_eq!(req.projections, vec!["order id"]);
+    assert_eq!(req.aggregates[0].column.as_deref(), Some("unit-price"));
+    assert_eq!(req.aggregates[0].alias.as_deref(), Some("Total \"net\""));
+    assert_eq!(req.filters[0].value, ScalarValue::String("it's".into()));
+    let Some(InList::Values(values)) = &req.filters[1].list else {
+        panic!("expected IN list");
+    };
+    // Backslashes are plain text in single quotes.
+    assert_eq!(
+        values,
+        &[
+            ScalarValue::String("a\\n".into()),
+            ScalarValue::String("b\nA".into())
+        ]
+    );
+    assert_eq!(req.filters[2].column, "t.x y");
+
+    assert!(parse_sql("SELECT a FROM t WHERE b = 'open;").is_err());
+    assert!(parse_sql("SELECT a FROM t /* open").is_err());
+}
+
+#[test]
+fn parses_scripts() {
+    let script = parse_script(
+        "SET timeout = 500; set format TO json;; SHOW format; SHOW ALL;\n\
+         SELECT a FROM t WHERE b = 'x;y' -- not a separator;\n;\n\
+         SELECT COUNT(*) FROM t",
+    )
+    .unwrap();
+    assert_eq!(script.len(), 6);
+    assert!(matches!(
+        &script[0],
+        Statement::Set { name, value: ScalarValue::Int(500) } if name == "timeout"
+    ));
+    assert!(matches!(
+        &script[1],
+        Statement::Set { value: ScalarValue::String(v), .. } if v == "json"
+    ));
+    assert!(matches!(&script[2], Statement::Show { name: Some(n) } if n == "format"));
+    assert!(matches!(&script[3], Statement::Show { name: None }));
+    let Statement::Query(req) = &script[4] else {
+        panic!("expected a query");
+    };
+    assert_eq!(req.filters[0].value, ScalarValue::String("x;y".into()));
+    assert!(req.query.starts_with("SELECT a FROM t WHERE b = 'x;y' --"));
+
+    // Positions are relative to the whole script.
+    let err = parse_script("SET timeout = 1;\nSELECT a FROM t WHERE b >> 1;").unwrap_err();
+    let QueryError::Parse { start, .. } = err else {
+        panic!("expected a parse error");
+    };
+    assert_eq!(start.map(|p| p.line), Some(2));
+    assert!(parse_script(" ; ").is_err());
+    assert!(parse_script("SET timeout = $1;").is_err());
+}
+
+#[test]
+fn parses_table_ddl() {
+    let script = parse_script(
+        "CREATE TABLE events (id bigint PRIMARY KEY, \"event type\" string NULL, \
+         at timestamp(ms) NOT NULL, score DOUBLE);\n\
+         drop table if exists events; DROP TABLE events",
+    )
+    .unwrap();
+    let Statement::CreateTable { name, columns } = &script[0] else {
+        panic!("expected CREATE TABLE");
+    };
+    assert_eq!(name, "events");
+    let described: Vec<_> = columns
+        .iter()
+        .map(|c| {
+            (
+                c.name.as_str(),
+                c.col_type.to_string(),
+                c.nullable,
+                c.is_key,
+            )
+        })
+        .collect();
+    assert_eq!(
+        described,
+        [
+            ("id", "int64".to_string(), false, true),
+            ("event type", "string".to_string(), true, false),
+            ("at", "timestamp(ms)".to_string(), false, false),
+            ("score", "float64".to_string(), false, false),
+        ]
+    );
+    assert!(matches!(
+        &script[1],
+        Statement::DropTable { name, if_exists: true } if name == "events"
+    ));
+    assert!(matches!(
+        &script[2],
+        Statement::DropTable {
+            if_exists: false,
+            ..
+        }
+    ));
+
+    let err = parse_script("CREATE TABLE t (id int128 KEY)").unwrap_err();
+    let QueryError::Parse { start, .. } = &err else {
+        panic!("expected a parse error");
+    };
+    assert_eq!(start.map(|p| p.column), Some(20), "{}", err);
+    assert!(parse_script("CREATE TABLE t ()").is_err());
+    assert!(parse_script("CREATETABLE t (id int64 KEY)").is_err());
+}
+
+#[test]
+fn parses_inserts() {
+    let script = parse_script(
+        "INSERT INTO t VALUES (1, 'a', NULL, -2.5, true), (2, \"b\", null, 0, false);\n\
+         insert into t (id, \"note text\") select id, name from s where id > 3",
+    )
+    .unwrap();
+    let Statement::Insert {
+        table,
+        columns,
+        source: InsertSource::Values(rows),
+    } = &script[0]
+    else {
+        panic!("expected INSERT ... VALUES");
+    };
+    assert_eq!(table, "t");
+    assert!(columns.is_empty());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(
+        rows[0],
+        [
+            Some(ScalarValue::Int(1)),
+            Some(ScalarValue::String("a".into())),
+            None,
+            Some(ScalarValue::Float(-2.5)),
+            Some(ScalarValue::Bool(true)),
+        ]
+    );
+    let Statement::Insert {
+        columns,
+        source: InsertSource::Query(req),
+        ..
+    } = &script[1]
+    else {
+        panic!("expected INSERT ... SELECT");
+    };
+    assert_eq!(columns, &["id", "note text"]);
+    assert_eq!(req.table, "s");
+    assert_eq!(req.projections, ["id", "name"]);
+
+    assert!(parse_script("INSERT INTO t VALUES ($1)").is_err());
+    assert!(parse_script("INSERT INTO t VALUES ()").is_err());
+    assert!(parse_script("INSERT INTO t EXPLAIN SELECT a FROM s").is_err());
+}
+
+#[test]
+fn parses_deletes() {
+    let script =
+        parse_script("DELETE FROM t WHERE id > 3 AND region = 'EU'; delete from \"t 2\"").unwrap();
+    let Statement::Delete(req) = &script[0] else {
+        panic!("expected DELETE");
+    };
+    assert_eq!(req.table, "t");
+    assert_eq!(req.filters.len(), 2);
+    assert_eq!(req.mutation, Some(Mutation::Delete));
+    assert!(matches!(req.aggregates[0].func, AggregateFn::Count));
+    let Statement::Delete(req) = &script[1] else {
+        panic!("expected DELETE");
+    };
+    assert_eq!(req.table, "t 2");
+    assert!(req.filters.is_empty());
+    assert!(parse_script("DELETE FROM t WHERE id = ?").is_err());
+}
+
+#[test]
+fn parses_create_table_as() {
+    let script = parse_script(
+        "CREATE TABLE s AS SELECT region, SUM(amount) FROM t GROUP BY region; \
+         CREATE TABLE k KEY (id) AS SELECT id FROM t",
+    )
+    .unwrap();
+    let Statement::CreateTableAs { name, key, query } = &script[0] else {
+        panic!("expected CREATE TABLE ... AS");
+    };
+    assert_eq!(name, "s");
+    assert_eq!(key, &None);
+    assert_eq!(query.group_by, ["region"]);
+    assert!(matches!(
+        &script[1],
+        Statement::CreateTableAs { key: Some(k), .. } if k == "id"
+    ));
+    assert!(parse_script("CREATE TABLE s AS SELECT id FROM t WHERE id = ?").is_err());
+}
+
+#[test]
+fn parses_views() {
+    let script = parse_script(
+        "CREATE VIEW v AS SELECT id FROM t WHERE id > 1; \
+         create materialized view m as select region, count(*) from t group by region; \
+         REFRESH MATERIALIZED VIEW m; DROP MATERIALIZED VIEW IF EXISTS m; DROP VIEW v",
+    )
+    .unwrap();
+    let Statement::CreateView {
+        name,
+        query,
+        materialized,
+    } = &script[0]
+    else {
+        panic!("expected CREATE VIEW");
+    };
+    assert_eq!(name, "v");
+    assert_eq!(query.query, "SELECT id FROM t WHERE id > 1");
+    assert!(!materialized);
+    assert!(matches!(
+        &script[1],
+        Statement::CreateView { materialized: true, query, .. } if query.group_by == ["region"]
+    ));
+    assert!(matches!(&script[2], Statement::RefreshView { name } if name == "m"));
+    assert!(matches!(
+        &script[3],
+        Statement::DropView {
+            if_exists: true,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &script[4],
+        Statement::DropView {
+            if_exists: false,
+            ..
+        }
+    ));
+    // A view is one SELECT.
+    assert!(parse_script("CREATE VIEW v AS SELECT id FROM t ORDER BY id").is_err());
+    assert!(parse_script("CREATE VIEW v AS SELECT id FROM t WHERE id = $1").is_err());
+}
+
+#[test]
+fn parses_catalog_statements() {
+    let script = parse_script(
+        "SHOW TABLES; show tables; SHOW timeout; DESCRIBE sales; \
+         SELECT column_name FROM information_schema.columns WHERE table_name = 'sales'",
+    )
+    .unwrap();
+    assert!(matches!(&script[0], Statement::ShowTables));
+    assert!(matches!(&script[1], Statement::ShowTables));
+    assert!(matches!(&script[2], Statement::Show { name: Some(n) } if n == "timeout"));
+    assert!(matches!(&script[3], Statement::Describe { name } if name == "sales"));
+    let Statement::Query(query) = &script[4] else {
+        panic!("expected a query");
+    };
+    assert_eq!(query.table, "information_schema.columns");
+    assert!(parse_script("DESCRIBE").is_err());
+}
+
+#[test]
+fn parses_alter_table() {
+    let script = parse_script(
+        "ALTER TABLE t ADD COLUMN c int DEFAULT 3; alter table t add d string not null; \
+         ALTER TABLE t DROP COLUMN c; ALTER TABLE t RENAME e TO \"f g\"",
+    )
+    .unwrap();
+    let Statement::AlterTable {
+        name,
+        change: SchemaChange::AddColumn(col),
+    } = &script[0]
+    else {
+        panic!("expected ADD COLUMN");
+    };
+    assert_eq!(name, "t");
+    assert_eq!(col.name, "c");
+    assert!(col.nullable);
+    assert_eq!(col.default, Some(ScalarValue::Int(3)));
+    assert!(matches!(
+        &script[1],
+        Statement::AlterTable { change: SchemaChange::AddColumn(col), .. } if !col.nullable
+    ));
+    assert!(matches!(
+        &script[2],
+        Statement::AlterTable { change: SchemaChange::DropColumn(c), .. } if c == "c"
+    ));
+    assert!(matches!(
+        &script[3],
+        Statement::AlterTable { change: SchemaChange::RenameColumn { from, to }, .. }
+            if from == "e" && to == "f g"
+    ));
+    assert!(parse_script("ALTER TABLE t RENAME e TOf").is_err());
+    assert!(parse_script("ALTER TABLE t ADD c int DEFAULT ?").is_err());
+}
+
+#[test]
+fn parses_updates() {
+    let script = parse_script(
+        "UPDATE t SET a = 1, b = NULL, c = d, e = e * 1.5 WHERE id > 3; update t set a = 'x'",
+    )
+    .unwrap();
+    let Statement::Update(req) = &script[0] else {
+        panic!("expected UPDATE");
+    };
+    assert_eq!(req.table, "t");
+    assert_eq!(req.filters.len(), 1);
+    let Some(Mutation::Update(assignments)) = &req.mutation else {
+        panic!("expected assignments");
+    };
+    let values: Vec<_> = assignments
+        .iter()
+        .map(|a| (a.column.as_str(), a.value.clone()))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            ("a", AssignedValue::Value(Some(ScalarValue::Int(1)))),
+            ("b", AssignedValue::Value(None)),
+            ("c", AssignedValue::Column("d".to_string())),
+            (
+                "e",
+                AssignedValue::Arithmetic {
+                    column: "e".to_string(),
+                    op: ArithOp::Mul,
+                    value: ScalarValue::Float(1.5),
+                }
+            ),
+        ]
+    );
+    let Statement::Update(req) = &script[1] else {
+        panic!("expected UPDATE");
+    };
+    assert!(req.filters.is_empty());
+    assert!(parse_script("UPDATE t SET a = $1").is_err());
+    assert!(parse_script("UPDATE t SET a = 1 WHERE id = ?").is_err());
+}
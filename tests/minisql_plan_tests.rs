@@ -0,0 +1,121 @@
+use minidist::coordinator::coordinator_route::{resolve_tables, run_query};
+use minidist::minisql::minisql_parse::parse_sql;
+use minidist::minisql::minisql_plan::{LogicalPlan, fold_predicates, plan_query};
+use minidist::rpc::{InList, Predicate, ScalarValue};
+use minidist::storage::storage_init::init_table;
+use std::fs;
+
+fn filters(sql: &str) -> Result<Vec<(String, Predicate, ScalarValue)>, String> {
+    let req = parse_sql(sql).expect("parse");
+    Ok(fold_predicates(req.filters)?
+        .into_iter()
+        .map(|f| (f.column, f.pred, f.value))
+        .collect())
+}
+
+#[test]
+fn folds_ranges_per_column() {
+    let folded =
+        filters("SELECT COUNT(*) FROM t WHERE a > 1 AND a >= 5 AND a < 10 AND b = 2;").unwrap();
+    assert_eq!(
+        folded,
+        vec![
+            ("a".to_string(), Predicate::Ge, ScalarValue::Int(5)),
+            ("a".to_string(), Predicate::Lt, ScalarValue::Int(10)),
+            ("b".to_string(), Predicate::Eq, ScalarValue::Int(2)),
+        ]
+    );
+
+    let folded = filters("SELECT COUNT(*) FROM t WHERE a IN (1, 2, 3) AND a > 1 AND a <= 2;");
+    assert_eq!(
+        folded.unwrap(),
+        vec![("a".to_string(), Predicate::Eq, ScalarValue::Int(2))]
+    );
+
+    let req =
+        parse_sql("SELECT COUNT(*) FROM t WHERE a BETWEEN 1 AND 9 AND a IN (2, 4, 12);").unwrap();
+    let folded = fold_predicates(req.filters).unwrap();
+    assert_eq!(folded.len(), 1);
+    assert!(matches!(
+        &folded[0].list,
+        Some(InList::Values(v)) if v == &vec![ScalarValue::Int(2), ScalarValue::Int(4)]
+    ));
+}
+
+#[test]
+fn detects_contradictions() {
+    for sql in [
+        "SELECT COUNT(*) FROM t WHERE a = 1 AND a = 2;",
+        "SELECT COUNT(*) FROM t WHERE a > 5 AND a < 3;",
+        "SELECT COUNT(*) FROM t WHERE a >= 5 AND a < 5;",
+        "SELECT COUNT(*) FROM t WHERE a BETWEEN 9 AND 1;",
+        "SELECT COUNT(*) FROM t WHERE a IN (1, 2) AND a = 3;",
+        "SELECT COUNT(*) FROM t WHERE flag AND flag = false;",
+    ] {
+        let err = filters(sql).unwrap_err();
+        assert!(err.contains("no value of"), "{}: {}", sql, err);
+    }
+    // Different types are left for the worker to compare.
+    assert_eq!(
+        filters("SELECT COUNT(*) FROM t WHERE a = 1 AND a = \"x\";")
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn pushes_filters_and_prunes_columns() {
+    let req = parse_sql(
+        "SELECT region, SUM(amount) AS total FROM sales WHERE amount > 10 AND amount > 20 \
+         GROUP BY region ORDER BY total DESC LIMIT 2;",
+    )
+    .unwrap();
+    let fragments = plan_query(&req);
+
+    let LogicalPlan::Project { input, .. } = &fragments.coordinator else {
+        panic!("{:?}", fragments.coordinator);
+    };
+    let LogicalPlan::Limit { input, count: 2 } = input.as_ref() else {
+        panic!("{:?}", input);
+    };
+    assert!(matches!(input.as_ref(), LogicalPlan::Sort { input, .. }
+        if matches!(input.as_ref(), LogicalPlan::Gather)));
+
+    let LogicalPlan::Aggregate { input, .. } = &fragments.worker else {
+        panic!("{:?}", fragments.worker);
+    };
+    let LogicalPlan::Scan {
+        columns, filters, ..
+    } = input.as_ref()
+    else {
+        panic!("{:?}", input);
+    };
+    assert_eq!(columns, &vec!["region", "amount"]);
+    assert_eq!(filters.len(), 1);
+    assert_eq!(filters[0].value, ScalarValue::Int(20));
+
+    let worker = fragments.worker_request(&req).unwrap();
+    assert_eq!(worker.scan_columns, vec!["region", "amount"]);
+    assert_eq!(worker.filters.len(), 1);
+}
+
+#[tokio::test]
+async fn contradiction_skips_workers() {
+    let mut root = std::env::temp_dir();
+    root.push("minidist-plan-contradiction");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let schema = root.join("t.ssf");
+    fs::write(&schema, "id: int64 key\namount: float64\n").unwrap();
+    let dir = root.join("t");
+    fs::create_dir_all(&dir).unwrap();
+    init_table(&dir, &schema).unwrap();
+
+    let mut req = parse_sql("SELECT SUM(amount) FROM t WHERE amount > 5 AND amount < 1;").unwrap();
+    resolve_tables(&mut req, &root.to_string_lossy()).unwrap();
+    // No worker listens on port 1; the plan must not need one.
+    let out = run_query(&[1], req).await.expect("query");
+    assert!(out.starts_with("empty result"), "{}", out);
+    assert!(out.contains("Segments skipped:   1"), "{}", out);
+}
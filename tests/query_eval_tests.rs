@@ -1,11 +1,12 @@
-use minidist_rs::coordinator::coordinator_merge::merge_partials;
-use minidist_rs::minisql::minisql_parse::parse_sql;
-use minidist_rs::storage::storage_init::init_table;
-use minidist_rs::storage::storage_load::load_table;
-use minidist_rs::storage::storage_schema::parse_schema_file;
-use minidist_rs::worker::worker_exec::{WorkerContext, execute_query};
+use minidist::coordinator::coordinator_merge::merge_partials;
+use minidist::minisql::minisql_parse::parse_sql;
+use minidist::rpc::{GroupKey, ScalarValue};
+use minidist::storage::storage_init::init_table;
+use minidist::storage::storage_load::{load_table, load_table_colocated};
+use minidist::storage::storage_schema::parse_schema_file;
+use minidist::worker::worker_exec::{WorkerContext, execute_query};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
@@ -19,6 +20,10 @@ fn tmp_dir(prefix: &str) -> PathBuf {
     path
 }
 
+fn group(value: &str) -> GroupKey {
+    vec![Some(ScalarValue::String(value.to_string()))]
+}
+
 fn build_sales_table() -> PathBuf {
     let dir = tmp_dir("table");
     let schema_path = dir.join("sales.ssf");
@@ -41,9 +46,9 @@ fn query_group_by_region() {
     let partials = run_on_all_segments(&table_dir, 2, &req);
     let (merged, _, _, _) = merge_partials(&partials);
 
-    let eu = merged.get("EU").expect("EU group");
-    let us = merged.get("US").expect("US group");
-    let apac = merged.get("APAC").expect("APAC group");
+    let eu = merged.get(&group("EU")).expect("EU group");
+    let us = merged.get(&group("US")).expect("US group");
+    let apac = merged.get(&group("APAC")).expect("APAC group");
 
     assert_eq!(eu["SUM(amount)"].sum, 150.0);
     assert_eq!(eu["SUM(amount)"].count, 2);
@@ -60,7 +65,7 @@ fn query_filter_and_count() {
 
     let partials = run_on_all_segments(&table_dir, 2, &req);
     let (merged, _, _, _) = merge_partials(&partials);
-    let agg = merged.get("all").expect("all group");
+    let agg = merged.get(&Vec::new()).expect("all group");
 
     let count = agg.get("COUNT(*)").expect("count agg");
     let sum = agg.get("SUM(amount)").expect("sum agg");
@@ -77,18 +82,54 @@ fn query_select_star_yields_group() {
     let partials = run_on_all_segments(&table_dir, 2, &req);
     let (merged, _, _, _) = merge_partials(&partials);
     assert!(
-        merged.contains_key("all"),
+        merged.contains_key(&Vec::new()),
         "expected default group 'all' even without aggregates"
     );
-    let state = merged["all"].get("COUNT(*)").expect("implicit count");
+    let state = merged[&Vec::new()].get("COUNT(*)").expect("implicit count");
     assert_eq!(state.count, 4);
 }
 
+#[test]
+fn query_colocated_join() {
+    let orders_dir = build_sales_table();
+    let items_dir = tmp_dir("items");
+    let items_csv = items_dir.join("items.csv");
+    fs::write(&items_csv, ITEMS_CSV).unwrap();
+    let items_schema = parse_schema_file(ITEMS_SSF).unwrap();
+    load_table_colocated(
+        &items_dir,
+        &items_csv,
+        &"order_id".to_string(),
+        2,
+        &items_schema,
+        &orders_dir,
+    )
+    .unwrap();
+    fs::write(items_dir.join("_schema.ssf"), ITEMS_SSF).unwrap();
+
+    let mut req = parse_sql(
+        "SELECT sales.region, SUM(items.qty), COUNT(*) FROM sales \
+         JOIN items ON items.order_id = sales.id GROUP BY sales.region;",
+    )
+    .expect("parse");
+    req.table = orders_dir.to_string_lossy().to_string();
+    req.join.as_mut().unwrap().right_table = items_dir.to_string_lossy().to_string();
+
+    let partials = run_on_all_segments(&orders_dir, 2, &req);
+    let (merged, _, _, _) = merge_partials(&partials);
+
+    assert_eq!(merged[&group("EU")]["SUM(items.qty)"].sum, 5.0);
+    assert_eq!(merged[&group("US")]["SUM(items.qty)"].sum, 9.0);
+    assert_eq!(merged[&group("US")]["COUNT(*)"].count, 2);
+    assert_eq!(merged[&group("APAC")]["SUM(items.qty)"].sum, 1.0);
+    assert_eq!(merged.len(), 3);
+}
+
 fn run_on_all_segments(
-    table_dir: &PathBuf,
+    table_dir: &Path,
     segments: u32,
-    req: &minidist_rs::rpc::QueryRequest,
-) -> Vec<minidist_rs::rpc::PartialAggregate> {
+    req: &minidist::rpc::QueryRequest,
+) -> Vec<minidist::rpc::PartialAggregate> {
     let mut partials = Vec::new();
     for segment in 0..segments {
         let ctx = WorkerContext {
@@ -112,3 +153,16 @@ const SALES_CSV: &str = r#"id,region,amount
 3,EU,50
 4,APAC,300
 "#;
+
+const ITEMS_SSF: &str = r#"order_id: int64 key
+qty: int64
+"#;
+
+const ITEMS_CSV: &str = r#"order_id,qty
+4,1
+1,2
+3,3
+2,4
+2,5
+9,6
+"#;
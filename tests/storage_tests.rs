@@ -1,6 +1,12 @@
-use minidist_rs::storage::storage_init::init_table;
-use minidist_rs::storage::storage_load::load_table;
-use minidist_rs::storage::storage_schema::{ColumnType, parse_schema_file};
+use minidist::rpc::ScalarValue;
+use minidist::storage::storage_append::append_rows;
+use minidist::storage::storage_deletion::DeletionVector;
+use minidist::storage::storage_init::{init_table, init_table_with_schema};
+use minidist::storage::storage_load::{create_empty_segments, load_table, load_table_colocated};
+use minidist::storage::storage_schema::{
+    ColumnType, format_schema, parse_schema_file, validate_schema,
+};
+use minidist::storage::storage_segment::{base_segment_count, segment_parts};
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
@@ -19,7 +25,7 @@ fn tmp_dir(prefix: &str) -> PathBuf {
 #[test]
 fn parses_sales_schema_file() {
     let contents = SALES_SSF;
-    let cols = parse_schema_file(&contents).expect("schema should parse");
+    let cols = parse_schema_file(contents).expect("schema should parse");
     assert_eq!(cols.len(), 3);
     assert!(cols[0].is_key);
     assert_eq!(cols[0].name, "id");
@@ -103,6 +109,165 @@ fn storage_load_rejects_mismatched_sort_key() {
     );
 }
 
+#[test]
+fn storage_load_keeps_equal_keys_in_one_segment() {
+    let tmp = tmp_dir("load-ties");
+    let csv_path = tmp.join("sales.csv");
+    fs::write(
+        &csv_path,
+        "id,region,amount\n1,EU,1\n1,US,2\n1,EU,3\n2,EU,4\n",
+    )
+    .unwrap();
+    let schema = parse_schema_file(SALES_SSF).unwrap();
+    load_table(&tmp, &csv_path, &"id".to_string(), 2, &schema).expect("load succeeds");
+
+    assert_eq!(read_int64s(tmp.join("seg-000000/id.bin")), vec![1, 1, 1]);
+    assert_eq!(read_int64s(tmp.join("seg-000001/id.bin")), vec![2]);
+}
+
+#[test]
+fn storage_load_colocated_aligns_segments() {
+    let orders = tmp_dir("colocate-orders");
+    let orders_csv = orders.join("sales.csv");
+    fs::write(&orders_csv, SALES_CSV).unwrap();
+    let schema = parse_schema_file(SALES_SSF).unwrap();
+    load_table(&orders, &orders_csv, &"id".to_string(), 2, &schema).unwrap();
+    fs::write(orders.join("_schema.ssf"), SALES_SSF).unwrap();
+
+    let items = tmp_dir("colocate-items");
+    let items_csv = items.join("items.csv");
+    fs::write(&items_csv, "order_id,qty\n4,1\n1,2\n3,3\n2,4\n2,5\n9,6\n").unwrap();
+    let items_schema = parse_schema_file(ITEMS_SSF).unwrap();
+    load_table_colocated(
+        &items,
+        &items_csv,
+        &"order_id".to_string(),
+        2,
+        &items_schema,
+        &orders,
+    )
+    .expect("colocated load succeeds");
+
+    // orders split as [1, 2] | [3, 4]
+    assert_eq!(
+        read_int64s(items.join("seg-000000/order_id.bin")),
+        vec![1, 2, 2]
+    );
+    assert_eq!(
+        read_int64s(items.join("seg-000001/order_id.bin")),
+        vec![3, 4, 9]
+    );
+
+    let err = load_table_colocated(
+        &items,
+        &items_csv,
+        &"order_id".to_string(),
+        3,
+        &items_schema,
+        &orders,
+    )
+    .unwrap_err();
+    assert!(err.contains("Reference table has 2 segments"), "{}", err);
+}
+
+#[test]
+fn storage_append_spreads_rows_of_empty_table() {
+    let tmp = tmp_dir("append");
+    let schema = parse_schema_file(SALES_SSF).unwrap();
+    init_table_with_schema(&tmp, &schema).unwrap();
+    create_empty_segments(&tmp, &schema, 2).unwrap();
+    let row = |id: i64, region: &str| {
+        vec![
+            Some(ScalarValue::Int(id)),
+            Some(ScalarValue::String(region.into())),
+            Some(ScalarValue::Float(1.0)),
+        ]
+    };
+
+    let written = append_rows(
+        &tmp,
+        &schema,
+        vec![row(4, "d"), row(1, "a"), row(3, "c"), row(2, "b")],
+    )
+    .unwrap();
+    assert_eq!(written, [2, 3]);
+    assert_eq!(read_int64s(tmp.join("seg-000002/id.bin")), [1, 2]);
+    assert_eq!(read_strings(tmp.join("seg-000003/region.bin")), ["c", "d"]);
+
+    // Later rows follow the key ranges of the first batch.
+    let written = append_rows(&tmp, &schema, vec![row(0, "z"), row(9, "y")]).unwrap();
+    assert_eq!(written, [4, 5]);
+    assert_eq!(
+        segment_parts(&tmp, 1),
+        [
+            tmp.join("seg-000001"),
+            tmp.join("seg-000003"),
+            tmp.join("seg-000005")
+        ]
+    );
+    assert_eq!(base_segment_count(&tmp).unwrap(), 2);
+
+    let mut null_key = row(5, "e");
+    null_key[0] = None;
+    assert!(append_rows(&tmp, &schema, vec![null_key]).is_err());
+}
+
+#[test]
+fn schema_defaults_and_files_round_trip() {
+    let ssf = "id: int64 key\n\
+               note: string nullable default=\"a \\\"b\\\"\\nc\" file=\"old note\"\n\
+               score: float64 default=1.5\n";
+    let schema = parse_schema_file(ssf).unwrap();
+    assert_eq!(
+        schema[1].default,
+        Some(ScalarValue::String("a \"b\"\nc".to_string()))
+    );
+    assert_eq!(schema[1].file_name(), "old note.bin");
+    assert_eq!(schema[2].default, Some(ScalarValue::Float(1.5)));
+    assert_eq!(format_schema(&schema), ssf);
+    assert!(parse_schema_file("id: int64 key default=x\n").is_err());
+    assert!(parse_schema_file("id: int64 key\nn: string default=\"open\n").is_err());
+}
+
+#[test]
+fn schema_names_stay_inside_segments() {
+    let schema = parse_schema_file("id: int64 key\nnote: string\n").unwrap();
+    assert!(validate_schema(&schema).is_ok());
+    for name in [
+        "../../x", "a/b", "a\\b", ".", "..", ".hidden", "a\tb", "a\nb", "a:b", "",
+    ] {
+        let mut renamed = schema.clone();
+        renamed[1].name = name.to_string();
+        assert!(validate_schema(&renamed).is_err(), "{:?}", name);
+        let mut moved = schema.clone();
+        moved[1].file = Some(name.to_string());
+        if !name.contains(':') {
+            assert!(validate_schema(&moved).is_err(), "file {:?}", name);
+        }
+    }
+    let mut dotted = schema.clone();
+    dotted[1].name = "v1.2".to_string();
+    assert!(validate_schema(&dotted).is_ok());
+}
+
+#[test]
+fn deletion_vector_round_trips() {
+    let tmp = tmp_dir("deleted");
+    let mut deleted = DeletionVector::read(&tmp).unwrap();
+    assert_eq!(deleted.count(), 0);
+    deleted.delete(0);
+    deleted.delete(9);
+    deleted.delete(9);
+    deleted.write(&tmp).unwrap();
+
+    let read = DeletionVector::read(&tmp).unwrap();
+    assert_eq!(read, deleted);
+    assert_eq!(read.count(), 2);
+    assert!(read.is_deleted(0) && read.is_deleted(9));
+    assert!(!read.is_deleted(1) && !read.is_deleted(1000));
+    assert_eq!(fs::read(tmp.join("_deleted.bin")).unwrap(), [0b1, 0b10]);
+}
+
 fn read_int64s(path: PathBuf) -> Vec<i64> {
     let mut f = fs::File::open(path).unwrap();
     let mut buf = Vec::new();
@@ -175,3 +340,7 @@ const SALES_CSV: &str = r#"id,region,amount
 3,EU,50
 4,APAC,300
 "#;
+
+const ITEMS_SSF: &str = r#"order_id: int64 key
+qty: int64
+"#;